@@ -9,6 +9,7 @@ export default defineConfig({
     reporter: "list",
     use: {
         baseURL: "http://127.0.0.1:8300",
+        extraHTTPHeaders: { Authorization: "Bearer 12345" },
         launchOptions: {
             slowMo: 500,
         },
@@ -1,7 +1,7 @@
 import { test, expect, type Page } from "@playwright/test";
 
 test.beforeEach(async ({ page }) => {
-    await page.goto("/admin?token=12345");
+    await page.goto("/admin");
 });
 test.describe("Setup Criteria", () => {
     test("Code", async ({ page, browserName }) => {
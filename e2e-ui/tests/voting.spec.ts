@@ -1,7 +1,7 @@
 import { test, expect, type Page } from "@playwright/test";
 test.describe("Create voting", () => {
     test("Create voting", async ({ page, browserName }) => {
-        await page.goto("/admin/votings?token=12345");
+        await page.goto("/admin/votings");
         const name = page.locator("#voting-id");
         await name.fill(`language-${browserName}`);
         await expect(name).toHaveValue(`language-${browserName}`);
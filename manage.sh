@@ -12,6 +12,7 @@ echo_help_msg() {
      up                        Uses the compose.yml to build and run the container.
      t|test                    Runs cargo tests.
      r|run                     Runs the binary of the server in debug compilation.
+     bootstrap                 Creates the first admin operator, where ARG1 is the name.
      db                        Short steps for diesel-cli.
      db:s, diesel:setup        Setup diesel.
      db:r, diesel:run          Run DB migration.
@@ -68,6 +69,9 @@ case "$1" in
     "r"|"run")
         RUST_LOG=$log DATABASE_URL=sqlite://db/db.sqlite VOTERS_VERDICT_SQLITE_CONNECTION=db/db.sqlite cargo run --bin voters-verdict-machine --features=admin,db,sqlx_sqlite
         ;;
+    "bootstrap")
+        cargo run --bin voters-verdict-admin --features=file -- bootstrap $2
+        ;;
     "db:s"|"diesel:setup")
         DATABASE_URL="db/db.sqlite" diesel setup
         ;;
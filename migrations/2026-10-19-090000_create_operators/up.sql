@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE operators (
+  name TEXT PRIMARY KEY NOT NULL,
+  password_hash TEXT NOT NULL,
+  role TEXT NOT NULL DEFAULT 'maintainer'
+);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE votings ADD COLUMN owner TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE votings ADD COLUMN invite_code_rotations TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,27 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE emojis;
+DROP INDEX criteria_name;
+INSERT INTO criteria SELECT * FROM criteria_duplicates;
+DROP TABLE criteria_duplicates;
+DROP INDEX ballots_voting;
+DROP INDEX ballots_human_identifier;
+INSERT INTO ballots SELECT * FROM ballots_duplicates;
+DROP TABLE ballots_duplicates;
+ALTER TABLE ballots DROP COLUMN voting;
+
+CREATE TABLE votings_old (
+    name TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    candidates TEXT NOT NULL,
+    categories TEXT NOT NULL,
+    styles TEXT NOT NULL,
+    invite_code TEXT NOT NULL,
+    owner TEXT NOT NULL DEFAULT '',
+    invite_code_rotations TEXT NOT NULL DEFAULT '[]'
+);
+INSERT INTO votings_old SELECT * FROM votings;
+INSERT INTO votings_old SELECT * FROM votings_duplicates;
+DROP TABLE votings_duplicates;
+DROP TABLE votings;
+ALTER TABLE votings_old RENAME TO votings;
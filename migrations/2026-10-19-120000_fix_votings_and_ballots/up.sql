@@ -0,0 +1,51 @@
+-- Your SQL goes here
+-- votings are looked up by name, SQLite can't add a primary key to a table.
+CREATE TABLE votings_new (
+    name TEXT PRIMARY KEY NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    candidates TEXT NOT NULL,
+    categories TEXT NOT NULL,
+    styles TEXT NOT NULL,
+    invite_code TEXT NOT NULL,
+    owner TEXT NOT NULL DEFAULT '',
+    invite_code_rotations TEXT NOT NULL DEFAULT '[]'
+);
+-- Duplicates are kept aside instead of dropped, the latest row of a name wins.
+CREATE TABLE votings_duplicates AS
+    SELECT * FROM votings
+    WHERE rowid NOT IN (SELECT MAX(rowid) FROM votings GROUP BY name);
+INSERT INTO votings_new
+    SELECT name, expires_at, created_at, candidates, categories, styles, invite_code, owner, invite_code_rotations
+    FROM votings WHERE rowid IN (SELECT MAX(rowid) FROM votings GROUP BY name);
+DROP TABLE votings;
+ALTER TABLE votings_new RENAME TO votings;
+
+-- ballots belong to a voting, the human_identifier is "voting-voter-candidate".
+ALTER TABLE ballots ADD COLUMN voting TEXT NOT NULL DEFAULT '';
+-- Voting names may hold '-' themselves, the longest one the identifier starts
+-- with wins. Ballots of no known voting fall back to the first segment. The
+-- voting is lowercase, like the code writes and looks it up.
+UPDATE ballots SET voting = lower(COALESCE(
+    (SELECT votings.name FROM votings
+        WHERE lower(substr(ballots.human_identifier, 1, length(votings.name) + 1)) = lower(votings.name) || '-'
+        ORDER BY length(votings.name) DESC LIMIT 1),
+    substr(human_identifier, 1, instr(human_identifier, '-') - 1)))
+    WHERE voting = '' AND instr(human_identifier, '-') > 0;
+CREATE TABLE ballots_duplicates AS
+    SELECT * FROM ballots
+    WHERE id NOT IN (SELECT MAX(id) FROM ballots GROUP BY human_identifier);
+DELETE FROM ballots WHERE id IN (SELECT id FROM ballots_duplicates);
+CREATE UNIQUE INDEX ballots_human_identifier ON ballots (human_identifier);
+CREATE INDEX ballots_voting ON ballots (voting);
+
+CREATE TABLE criteria_duplicates AS
+    SELECT * FROM criteria
+    WHERE id NOT IN (SELECT MAX(id) FROM criteria GROUP BY name);
+DELETE FROM criteria WHERE id IN (SELECT id FROM criteria_duplicates);
+CREATE UNIQUE INDEX criteria_name ON criteria (name);
+
+CREATE TABLE emojis (
+  name TEXT PRIMARY KEY NOT NULL,
+  emojis TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE votes;
+DROP TABLE voting_criteria;
+DROP TABLE voting_candidates;
@@ -0,0 +1,38 @@
+-- Your SQL goes here
+CREATE TABLE voting_candidates (
+  voting TEXT NOT NULL REFERENCES votings (name) ON DELETE CASCADE,
+  candidate TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (voting, candidate)
+);
+
+-- The weight is the one of the voting, criteria can change afterwards.
+CREATE TABLE voting_criteria (
+  voting TEXT NOT NULL REFERENCES votings (name) ON DELETE CASCADE,
+  criterion TEXT NOT NULL,
+  weight FLOAT,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (voting, criterion)
+);
+
+CREATE TABLE votes (
+  ballot TEXT NOT NULL REFERENCES ballots (human_identifier) ON DELETE CASCADE,
+  criterion TEXT NOT NULL,
+  point SMALLINT NOT NULL,
+  PRIMARY KEY (ballot, criterion)
+);
+
+INSERT OR IGNORE INTO voting_candidates ( voting, candidate, position )
+  SELECT votings.name, json_extract(c.value, '$.id'), c.key
+  FROM votings, json_each(votings.candidates) AS c
+  WHERE json_valid(votings.candidates) AND json_extract(c.value, '$.id') IS NOT NULL;
+
+INSERT OR IGNORE INTO voting_criteria ( voting, criterion, weight, position )
+  SELECT votings.name, json_extract(c.value, '$.name'), json_extract(c.value, '$.weight'), c.key
+  FROM votings, json_each(votings.categories) AS c
+  WHERE json_valid(votings.categories) AND json_extract(c.value, '$.name') IS NOT NULL;
+
+INSERT OR IGNORE INTO votes ( ballot, criterion, point )
+  SELECT ballots.human_identifier, json_extract(v.value, '$.name'), json_extract(v.value, '$.point')
+  FROM ballots, json_each(ballots.votes) AS v
+  WHERE json_valid(ballots.votes) AND json_extract(v.value, '$.name') IS NOT NULL;
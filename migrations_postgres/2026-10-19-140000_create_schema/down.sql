@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE votes;
+DROP TABLE voting_criteria;
+DROP TABLE voting_candidates;
+DROP TABLE emojis;
+DROP TABLE operators;
+DROP TABLE ballots;
+DROP TABLE votings;
+DROP TABLE criteria;
+DROP TABLE candidates;
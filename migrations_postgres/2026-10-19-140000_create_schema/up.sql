@@ -0,0 +1,80 @@
+-- Your SQL goes here
+-- The schema of migrations/ up to 2026-10-19-130000_normalise_votings_and_votes.
+-- Dates stay text, like in SQLite they are RFC 3339 strings.
+CREATE TABLE candidates (
+  id TEXT PRIMARY KEY NOT NULL,
+  label TEXT NOT NULL,
+  voter BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE criteria (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  name TEXT NOT NULL,
+  min SMALLINT NOT NULL,
+  max SMALLINT NOT NULL,
+  weight DOUBLE PRECISION
+);
+CREATE UNIQUE INDEX criteria_name ON criteria (name);
+
+CREATE TABLE votings (
+  name TEXT PRIMARY KEY NOT NULL,
+  expires_at TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  candidates TEXT NOT NULL,
+  categories TEXT NOT NULL,
+  styles TEXT NOT NULL,
+  invite_code TEXT NOT NULL,
+  owner TEXT NOT NULL DEFAULT '',
+  invite_code_rotations TEXT NOT NULL DEFAULT '[]'
+);
+
+-- ballots belong to a voting, the human_identifier is "voting-voter-candidate".
+CREATE TABLE ballots (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  human_identifier TEXT NOT NULL,
+  voter TEXT NOT NULL,
+  candidate TEXT NOT NULL,
+  sum SMALLINT NOT NULL,
+  weighted DOUBLE PRECISION NOT NULL,
+  mean DOUBLE PRECISION NOT NULL,
+  notes TEXT NOT NULL,
+  votes TEXT NULL,
+  voted_on TEXT NOT NULL,
+  voting TEXT NOT NULL DEFAULT ''
+);
+CREATE UNIQUE INDEX ballots_human_identifier ON ballots (human_identifier);
+CREATE INDEX ballots_voting ON ballots (voting);
+
+CREATE TABLE operators (
+  name TEXT PRIMARY KEY NOT NULL,
+  password_hash TEXT NOT NULL,
+  role TEXT NOT NULL DEFAULT 'maintainer'
+);
+
+CREATE TABLE emojis (
+  name TEXT PRIMARY KEY NOT NULL,
+  emojis TEXT NOT NULL
+);
+
+CREATE TABLE voting_candidates (
+  voting TEXT NOT NULL REFERENCES votings (name) ON DELETE CASCADE,
+  candidate TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (voting, candidate)
+);
+
+-- The weight is the one of the voting, criteria can change afterwards.
+CREATE TABLE voting_criteria (
+  voting TEXT NOT NULL REFERENCES votings (name) ON DELETE CASCADE,
+  criterion TEXT NOT NULL,
+  weight DOUBLE PRECISION,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (voting, criterion)
+);
+
+CREATE TABLE votes (
+  ballot TEXT NOT NULL REFERENCES ballots (human_identifier) ON DELETE CASCADE,
+  criterion TEXT NOT NULL,
+  point SMALLINT NOT NULL,
+  PRIMARY KEY (ballot, criterion)
+);
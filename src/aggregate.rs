@@ -0,0 +1,470 @@
+use crate::{
+    authentication::{can, Authorized},
+    ballots::TableRow,
+    common::{CastBallots, Criterion, Empty, Fill, Voting},
+    csrf::CsrfVerified,
+    error::VoteErrorKind,
+    persistence::{storage, Kind, PutMode},
+    serialize::FromStorage,
+};
+use rocket::{
+    debug, error, get, http::Status, post, serde::json::Json, serde::Deserialize, serde::Serialize,
+    tokio::sync::Mutex, warn,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, OnceLock},
+};
+
+/// The points of one criterion of a candidate.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CriterionTotals {
+    pub votes: u32,
+    pub points: i64,
+}
+
+/// The running totals of a candidate over every counted ballot.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CandidateTotals {
+    pub ballots: u32,
+    pub sum: i64,
+    pub weighted: f64,
+    pub criteria: BTreeMap<String, CriterionTotals>,
+}
+impl CandidateTotals {
+    fn count(&mut self, row: &TableRow) {
+        self.ballots += 1;
+        self.sum += i64::from(row.sum);
+        self.weighted += f64::from(row.weighted);
+        for vote in &row.votes {
+            let criterion = self.criteria.entry(vote.name.clone()).or_default();
+            criterion.votes += 1;
+            criterion.points += i64::from(vote.point);
+        }
+    }
+}
+
+/// The totals of a voting, every new ballot adds its points to them. Reading
+/// the totals loads the aggregate alone, neither the ballots nor their list.
+/// Ballots removed from the storage are only taken back by `rebuild`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ResultsAggregate {
+    pub voting: String,
+    /// How many ballots are in the totals. A ballot is counted after its
+    /// write, which refuses an existing id, so none is counted twice.
+    pub counted: u64,
+    pub candidates: BTreeMap<String, CandidateTotals>,
+}
+
+/// A line of the results page, the totals of a candidate with the points of
+/// the criteria in the order of the voting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CandidateResult {
+    pub candidate: String,
+    pub ballots: u32,
+    pub points: Vec<i64>,
+    pub sum: i64,
+    pub weighted: f64,
+    /// The points per vote.
+    pub mean: f64,
+}
+
+impl ResultsAggregate {
+    pub fn new(voting: &str) -> Self {
+        Self {
+            voting: voting.to_lowercase(),
+            ..Default::default()
+        }
+    }
+    /// Totals without a stored aggregate, e.g. of rows computed by a database.
+    pub fn from_rows(voting: &str, rows: Vec<TableRow>) -> Self {
+        let mut aggregate = Self::new(voting);
+        aggregate.add(&rows);
+        aggregate
+    }
+    fn add(&mut self, rows: &[TableRow]) {
+        for row in rows {
+            self.candidates
+                .entry(row.candidate.clone())
+                .or_default()
+                .count(row);
+        }
+    }
+    /// Adds the ballot, if it belongs to this voting. Returns whether it was
+    /// added.
+    fn count(&mut self, cast_ballots: CastBallots, categories: &Vec<Criterion>) -> bool {
+        if !belongs(&cast_ballots, &self.voting) {
+            return false;
+        }
+        self.add(&TableRow::from_cast_ballots(vec![cast_ballots], categories));
+        self.counted += 1;
+        true
+    }
+    /// The candidates, the best first by `order_by`, `sum`, `weighted` or
+    /// `mean`.
+    pub fn standings(&self, categories: &[Criterion], order_by: &str) -> Vec<CandidateResult> {
+        let mut standings: Vec<CandidateResult> = self
+            .candidates
+            .iter()
+            .map(|(candidate, totals)| {
+                let votes: u32 = totals.criteria.values().map(|c| c.votes).sum();
+                CandidateResult {
+                    candidate: candidate.clone(),
+                    ballots: totals.ballots,
+                    points: categories
+                        .iter()
+                        .map(|c| totals.criteria.get(&c.name).map_or(0, |t| t.points))
+                        .collect(),
+                    sum: totals.sum,
+                    weighted: totals.weighted,
+                    mean: match votes {
+                        0 => 0.0,
+                        votes => totals.sum as f64 / f64::from(votes),
+                    },
+                }
+            })
+            .collect();
+        match order_by {
+            "weighted" => standings.sort_by(|p, n| n.weighted.total_cmp(&p.weighted)),
+            "mean" => standings.sort_by(|p, n| n.mean.total_cmp(&p.mean)),
+            _ => standings.sort_by_key(|c| std::cmp::Reverse(c.sum)),
+        }
+        standings
+    }
+}
+
+/// Whether the ballot is cast in the voting, ballots of a voting with a
+/// similar name like `voting-a` share the prefix of the ids of `voting`.
+fn belongs(cast_ballots: &CastBallots, voting_id: &str) -> bool {
+    match &cast_ballots.voting {
+        Some(voting) => voting.eq_ignore_ascii_case(voting_id),
+        None => false,
+    }
+}
+
+/// Serializes the updates of an aggregate within the server.
+fn lock(voting_id: &str) -> Arc<Mutex<()>> {
+    static LOCKS: OnceLock<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+    let mut locks = match LOCKS.get_or_init(Default::default).lock() {
+        Ok(locks) => locks,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    locks.entry(voting_id.to_string()).or_default().clone()
+}
+
+/// The stored aggregate and whether it exists. One of an older layout is
+/// counted anew, like a missing one.
+async fn load(voting_id: &str) -> Result<(Option<ResultsAggregate>, bool), VoteErrorKind<'static>> {
+    match storage()?.get(Kind::Result, voting_id).await {
+        Ok(raw) => match rocket::serde::json::from_str(&raw) {
+            Ok(aggregate) => Ok((Some(aggregate), true)),
+            Err(e) => {
+                warn!("Counting the results of {} anew: {:?}", voting_id, e);
+                Ok((None, true))
+            }
+        },
+        Err(VoteErrorKind::NotFound(_)) => Ok((None, false)),
+        Err(e) => Err(e),
+    }
+}
+
+async fn store(
+    aggregate: &ResultsAggregate,
+    exists: bool,
+) -> Result<String, VoteErrorKind<'static>> {
+    let mode = match exists {
+        true => PutMode::Replace,
+        false => PutMode::Create,
+    };
+    let stringified = rocket::serde::json::to_string(aggregate)?;
+    storage()?
+        .put(Kind::Result, &aggregate.voting, stringified, mode)
+        .await
+}
+
+/// The ids of the ballots, which may belong to the voting. Ids are
+/// `<voting>-<voter>-<candidate>`, a voting like `voting-a` shares the prefix
+/// of `voting`.
+async fn ballot_ids(voting_id: &str) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let prefix = voting_id.to_string() + "-";
+    Ok(storage()?
+        .list(Kind::Ballot)
+        .await?
+        .into_iter()
+        .filter(|id| id.starts_with(&prefix))
+        .collect())
+}
+
+/// Passes every ballot of the voting to `each`. Ballots the backend streams
+/// for the voting are passed while they are read, the rest is loaded by id.
+async fn for_each_ballot(
+    voting_id: &str,
+    each: &mut (dyn FnMut(&str, CastBallots) + Send),
+) -> Result<(), VoteErrorKind<'static>> {
+    let ids = ballot_ids(voting_id).await?;
+    let mut pending: HashSet<String> = ids.iter().cloned().collect();
+    let mut broken = None;
+    storage()?
+        .for_each_ballot(voting_id, &mut |id, raw| {
+            if pending.remove(id) {
+                match rocket::serde::json::from_str::<CastBallots>(raw) {
+                    Ok(cast_ballots) if belongs(&cast_ballots, voting_id) => each(id, cast_ballots),
+                    Ok(_) => {}
+                    Err(e) => broken = Some(e),
+                }
+            }
+        })
+        .await?;
+    if let Some(e) = broken {
+        return Err(VoteErrorKind::Serialize(e));
+    }
+    let ids: Vec<String> = ids.into_iter().filter(|id| pending.contains(id)).collect();
+    let empty = CastBallots::empty();
+    let loads = ids.iter().map(|id| empty.try_load(id));
+    for (id, loaded) in ids.iter().zip(futures::future::join_all(loads).await) {
+        match loaded {
+            Ok(cast_ballots) if belongs(&cast_ballots, voting_id) => each(id, cast_ballots),
+            Ok(_) | Err(VoteErrorKind::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// The rows of every ballot of the voting in the order of their ids.
+pub async fn table_rows(
+    voting_id: &str,
+    categories: &Vec<Criterion>,
+) -> Result<Vec<TableRow>, VoteErrorKind<'static>> {
+    let mut rows = BTreeMap::new();
+    for_each_ballot(&voting_id.to_lowercase(), &mut |id, cast_ballots| {
+        rows.insert(
+            id.to_string(),
+            TableRow::from_cast_ballots(vec![cast_ballots], categories),
+        );
+    })
+    .await?;
+    Ok(rows.into_values().flatten().collect())
+}
+
+/// A new aggregate with every ballot of the voting counted.
+async fn count_all(
+    voting_id: &str,
+    categories: &Vec<Criterion>,
+) -> Result<ResultsAggregate, VoteErrorKind<'static>> {
+    let mut aggregate = ResultsAggregate::new(voting_id);
+    for_each_ballot(voting_id, &mut |_, cast_ballots| {
+        aggregate.count(cast_ballots, categories);
+    })
+    .await?;
+    Ok(aggregate)
+}
+
+/// Counts a new ballot, which was just written. If that fails, the aggregate
+/// is dropped and counted anew on its next read. Only if it can't be dropped
+/// either, its totals are behind and the error is returned.
+pub async fn count_ballot(cast_ballots: CastBallots) -> Result<(), VoteErrorKind<'static>> {
+    let storage = storage()?;
+    if storage.computes_results() {
+        return Ok(());
+    }
+    let voting_id = match &cast_ballots.voting {
+        Some(voting) => voting.to_lowercase(),
+        None => return Ok(()),
+    };
+    let voting = Voting::fill(&voting_id, false, "voting").await;
+    let lock = lock(&voting_id);
+    let _guard = lock.lock().await;
+    let counted = match load(&voting_id).await {
+        Ok((Some(mut aggregate), exists)) => {
+            match aggregate.count(cast_ballots, &voting.categories) {
+                true => store(&aggregate, exists).await.map(|_| ()),
+                false => Ok(()),
+            }
+        }
+        // Counts the new ballot as well.
+        Ok((None, exists)) => match count_all(&voting_id, &voting.categories).await {
+            Ok(aggregate) => store(&aggregate, exists).await.map(|_| ()),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    match counted {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            warn!("Dropping the results of {}: {:?}", voting_id, e);
+            match storage.delete(Kind::Result, &voting_id).await {
+                Ok(_) | Err(VoteErrorKind::NotFound(_)) => Ok(()),
+                Err(_) => Err(e),
+            }
+        }
+    }
+}
+
+/// The stored aggregate of the voting. Only a missing one is counted out of
+/// the ballots.
+pub async fn results(
+    voting_id: &str,
+    categories: &Vec<Criterion>,
+) -> Result<ResultsAggregate, VoteErrorKind<'static>> {
+    let voting_id = voting_id.to_lowercase();
+    let lock = lock(&voting_id);
+    let _guard = lock.lock().await;
+    let (aggregate, exists) = load(&voting_id).await?;
+    if let Some(aggregate) = aggregate {
+        return Ok(aggregate);
+    }
+    debug!("Counting the results of {}.", voting_id);
+    let aggregate = count_all(&voting_id, categories).await?;
+    store(&aggregate, exists).await?;
+    Ok(aggregate)
+}
+
+/// Counts every ballot of the voting anew, e.g. after the weights of its
+/// criteria changed.
+pub async fn rebuild(
+    voting_id: &str,
+    categories: &Vec<Criterion>,
+) -> Result<ResultsAggregate, VoteErrorKind<'static>> {
+    let voting_id = voting_id.to_lowercase();
+    let lock = lock(&voting_id);
+    let _guard = lock.lock().await;
+    let (_, exists) = load(&voting_id).await?;
+    let aggregate = count_all(&voting_id, categories).await?;
+    store(&aggregate, exists).await?;
+    Ok(aggregate)
+}
+
+/// The totals of the voting, computed by the backend if it can, otherwise out
+/// of the aggregate.
+pub async fn totals(
+    voting_id: &str,
+    categories: &Vec<Criterion>,
+) -> Result<ResultsAggregate, VoteErrorKind<'static>> {
+    let voting_id = voting_id.to_lowercase();
+    match storage()?.results(&voting_id, None, "id").await? {
+        Some(rows) => Ok(ResultsAggregate::from_rows(&voting_id, rows)),
+        None => results(&voting_id, categories).await,
+    }
+}
+
+fn to_status(e: VoteErrorKind<'static>) -> Status {
+    error!("{:?}", e);
+    Status::from(&e)
+}
+
+#[get("/<voting_id>/totals")]
+pub async fn get_totals(
+    voting_id: &str,
+) -> Result<Json<BTreeMap<String, CandidateTotals>>, Status> {
+    let voting_id = voting_id.to_lowercase();
+    let voting = Voting::empty()
+        .try_load(&voting_id)
+        .await
+        .map_err(to_status)?;
+    let aggregate = totals(&voting_id, &voting.categories)
+        .await
+        .map_err(to_status)?;
+    Ok(Json(aggregate.candidates))
+}
+
+#[post("/results/<voting_id>")]
+pub async fn rebuild_results(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageStorage>,
+    voting_id: &str,
+) -> Result<Json<BTreeMap<String, CandidateTotals>>, Status> {
+    let voting_id = voting_id.to_lowercase();
+    let voting = Voting::empty()
+        .try_load(&voting_id)
+        .await
+        .map_err(to_status)?;
+    authorized.user.audit("rebuilt the results of", &voting_id);
+    let aggregate = rebuild(&voting_id, &voting.categories)
+        .await
+        .map_err(to_status)?;
+    Ok(Json(aggregate.candidates))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn categories() -> Vec<Criterion> {
+        vec![
+            Criterion {
+                name: String::from("Style"),
+                min: 0,
+                max: 10,
+                weight: None,
+            },
+            Criterion {
+                name: String::from("Weather"),
+                min: 0,
+                max: 10,
+                weight: Some(20.0),
+            },
+        ]
+    }
+
+    #[rocket::async_test]
+    async fn counts_the_ballots_of_the_voting() {
+        if storage().unwrap().computes_results() {
+            return;
+        }
+        let aggregate = results("voting", &categories()).await.unwrap();
+        assert_eq!(aggregate.counted, 3);
+        let joe = &aggregate.candidates["Joe"];
+        assert_eq!(joe.ballots, 2);
+        assert_eq!(joe.criteria["Style"].votes, 2);
+        assert_eq!(
+            joe.sum,
+            table_rows("voting", &categories())
+                .await
+                .unwrap()
+                .iter()
+                .filter(|r| r.candidate == "Joe")
+                .map(|r| i64::from(r.sum))
+                .sum::<i64>()
+        );
+        let by_sum = aggregate.standings(&categories(), "sum");
+        assert_eq!(by_sum[0].candidate, "Joe");
+        assert_eq!(by_sum[0].points, vec![9, 17]);
+        let by_mean = aggregate.standings(&categories(), "mean");
+        assert_eq!(by_mean[0].candidate, "Doe");
+        assert_eq!(by_mean[0].mean, 7.5);
+    }
+
+    #[rocket::async_test]
+    async fn keeps_other_votings_apart() {
+        let voting = CastBallots {
+            voting: Some(String::from("Voting-A")),
+            ballots: vec![crate::common::KnownBallots {
+                voter: String::from("Jill"),
+                ballots: vec![crate::common::Ballot {
+                    candidate: String::from("Joe"),
+                    votes: vec![crate::common::Vote {
+                        name: String::from("Style"),
+                        point: 3,
+                    }],
+                    notes: None,
+                    voted_on: Some(chrono::Utc::now()),
+                }],
+            }],
+        };
+        let mut aggregate = ResultsAggregate::new("voting");
+        assert!(!aggregate.count(voting.clone(), &categories()));
+        assert_eq!(aggregate.counted, 0);
+        assert!(aggregate.candidates.is_empty());
+
+        let mut other = ResultsAggregate::new("voting-a");
+        assert!(other.count(voting, &categories()));
+        assert_eq!(other.counted, 1);
+        assert_eq!(other.candidates["Joe"].sum, 3);
+        assert_eq!(other.candidates["Joe"].criteria["Style"].votes, 1);
+    }
+}
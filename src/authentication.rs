@@ -1,70 +1,447 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use rocket::{
-    http::Status,
-    info,
+    delete,
+    http::{Cookie, CookieJar, SameSite, Status},
+    info, post,
     request::{FromRequest, Outcome},
     response::status::Unauthorized,
+    serde::{Deserialize, Serialize},
     Request,
 };
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
 
 use crate::{
+    common::{Fill, Operator, Voting},
     config::{ADMIN_TOKEN, MAINTAINER_TOKEN},
+    csrf::{generate_csrf_token, is_same_token, CsrfVerified},
     error::VoteErrorKind,
+    rate_limit::{is_throttled, record_failure, record_success, Scope},
 };
+
+pub const SESSION_COOKIE: &str = "voters-verdict-session";
+/// How long a login lasts. Changing the role or password of an operator ends
+/// their sessions at once.
+const SESSION_TTL: Duration = Duration::from_secs(8 * 60 * 60);
+
 /////////////////////////////////////////////
 //                                         //
 //               ADMIN                     //
 //                                         //
 /////////////////////////////////////////////
-#[derive(PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
 pub enum UserRole {
     Admin,
     Maintainer,
 }
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Admin => "admin",
+            UserRole::Maintainer => "maintainer",
+        }
+    }
+}
+impl std::str::FromStr for UserRole {
+    type Err = crate::error::FromErrorKind;
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role.trim().to_lowercase().as_str() {
+            "admin" => Ok(UserRole::Admin),
+            "maintainer" => Ok(UserRole::Maintainer),
+            _ => Err(crate::error::FromErrorKind::Serialize(String::from(
+                "Unknown role.",
+            ))),
+        }
+    }
+}
 pub struct ElevatedUser {
+    pub name: String,
     pub role: UserRole,
 }
 impl ElevatedUser {
-    fn new_admin() -> Self {
+    pub fn new(name: &str, role: UserRole) -> Self {
         Self {
-            role: UserRole::Admin,
+            name: name.to_string(),
+            role,
         }
     }
+    pub fn new_admin() -> Self {
+        Self::new("admin", UserRole::Admin)
+    }
     pub fn new_maintainer() -> Self {
-        Self {
-            role: UserRole::Maintainer,
+        Self::new("maintainer", UserRole::Maintainer)
+    }
+    pub fn audit(&self, action: &str, target: &str) {
+        info!(
+            "AUDIT: {} ({}) {} {}",
+            self.name,
+            self.role.as_str(),
+            action,
+            target
+        );
+    }
+}
+
+/// The credentials of the `Authorization` header, a token of the environment
+/// as `Bearer` or an operator as `Basic`.
+#[derive(Debug, PartialEq)]
+enum Credentials {
+    Token(String),
+    Operator { name: String, password: String },
+}
+impl Credentials {
+    fn from_header(value: &str) -> Option<Self> {
+        let (scheme, value) = value.trim().split_once(' ')?;
+        match scheme.to_lowercase().as_str() {
+            "bearer" => Some(Credentials::Token(value.trim().to_string())),
+            "basic" => {
+                let decoded = String::from_utf8(STANDARD.decode(value.trim()).ok()?).ok()?;
+                let (name, password) = decoded.split_once(':')?;
+                Some(Credentials::Operator {
+                    name: name.to_string(),
+                    password: password.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+    /// Only names an operator can have are looked up and hashed.
+    async fn verify(&self) -> Option<ElevatedUser> {
+        fn is_token_valid(var_key: &str, token_value: &str) -> bool {
+            match std::env::var(var_key) {
+                Ok(t) => !t.is_empty() && is_same_token(&t, token_value),
+                Err(_) => false,
+            }
+        }
+        match self {
+            Credentials::Token(token) if is_token_valid(MAINTAINER_TOKEN, token) => {
+                Some(ElevatedUser::new_maintainer())
+            }
+            Credentials::Token(token) if is_token_valid(ADMIN_TOKEN, token) => {
+                Some(ElevatedUser::new_admin())
+            }
+            Credentials::Token(_) => None,
+            Credentials::Operator { name, password } => {
+                if !Operator::is_valid_name(name) {
+                    Operator::verify_unknown(password);
+                    return None;
+                }
+                let operator = Operator::fill(name, true, "operator").await;
+                if operator.name.is_empty() {
+                    Operator::verify_unknown(password);
+                    return None;
+                }
+                operator
+                    .verify(password)
+                    .then(|| ElevatedUser::new(&operator.name, operator.role))
+            }
         }
     }
 }
 
+/////////////////////////////////////////////
+//                                         //
+//               SESSIONS                  //
+//                                         //
+/////////////////////////////////////////////
+
+struct Session {
+    user: ElevatedUser,
+    started_at: Instant,
+}
+
+/// Logged in users by the id of their cookie. Passwords are verified once
+/// per login instead of on every request.
+fn sessions() -> &'static Mutex<HashMap<String, Session>> {
+    static SESSIONS: OnceLock<Mutex<HashMap<String, Session>>> = OnceLock::new();
+    SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+fn open_session(user: &ElevatedUser) -> String {
+    let id = generate_csrf_token();
+    if let Ok(mut sessions) = sessions().lock() {
+        sessions.retain(|_, s| s.started_at.elapsed() < SESSION_TTL);
+        sessions.insert(
+            id.clone(),
+            Session {
+                user: ElevatedUser::new(&user.name, user.role.clone()),
+                started_at: Instant::now(),
+            },
+        );
+    }
+    id
+}
+fn session_user(id: &str) -> Option<ElevatedUser> {
+    let sessions = sessions().lock().ok()?;
+    let session = sessions.get(id)?;
+    (session.started_at.elapsed() < SESSION_TTL)
+        .then(|| ElevatedUser::new(&session.user.name, session.user.role.clone()))
+}
+fn close_session(id: &str) {
+    if let Ok(mut sessions) = sessions().lock() {
+        sessions.remove(id);
+    }
+}
+/// Ends every session of the operator `name`.
+pub fn close_sessions_of(name: &str) {
+    if let Ok(mut sessions) = sessions().lock() {
+        sessions.retain(|_, s| !s.user.name.eq_ignore_ascii_case(name));
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ElevatedUser {
     type Error = VoteErrorKind<'r>;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let unautorized_error =
-            VoteErrorKind::Unauthorized(Unauthorized(String::from("Provide a valid token")));
-        fn is_token_valid(var_key: &str, token_value: &str) -> bool {
-            match std::env::var(var_key) {
-                Ok(t) => t == token_value,
-                Err(_) => false,
+        let unautorized_error = VoteErrorKind::Unauthorized(Unauthorized(String::from(
+            "Log in or provide valid credentials",
+        )));
+        if let Some(user) = req
+            .cookies()
+            .get(SESSION_COOKIE)
+            .and_then(|c| session_user(c.value()))
+        {
+            return Outcome::Success(user);
+        }
+        let credentials = match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(Credentials::from_header)
+        {
+            Some(c) => c,
+            None => return Outcome::Error((Status::Unauthorized, unautorized_error)),
+        };
+        if is_throttled(req, Scope::Admin) {
+            return Outcome::Error((Status::TooManyRequests, unautorized_error));
+        }
+        match credentials.verify().await {
+            Some(user) => {
+                info!("{} logged in.", user.name);
+                record_success(req, Scope::Admin);
+                Outcome::Success(user)
+            }
+            None => {
+                record_failure(req, Scope::Admin);
+                Outcome::Error((Status::Unauthorized, unautorized_error))
             }
         }
-        match req.query_value::<&str>("token") {
-            Some(possible_token) => match possible_token {
-                Ok(t) => {
-                    if is_token_valid(MAINTAINER_TOKEN, t) {
-                        info!("Maintainer logged in.");
-                        return Outcome::Success(ElevatedUser::new_maintainer());
-                    }
-                    if is_token_valid(ADMIN_TOKEN, t) {
-                        info!("Admin logged in.");
-                        return Outcome::Success(ElevatedUser::new_admin());
-                    }
-                    Outcome::Error((Status::BadRequest, unautorized_error))
+    }
+}
+
+/// Logs in with the credentials of the `Authorization` header and keeps the
+/// session in a cookie.
+#[post("/")]
+pub fn post_session(_csrf: CsrfVerified, user: ElevatedUser, cookies: &CookieJar<'_>) -> String {
+    cookies.add(
+        Cookie::build((SESSION_COOKIE, open_session(&user)))
+            .path("/")
+            .same_site(SameSite::Strict)
+            .http_only(true)
+            .secure(true),
+    );
+    user.audit("started", "a session");
+    format!("Logged in as {}.", user.name)
+}
+
+#[delete("/")]
+pub fn delete_session(_csrf: CsrfVerified, cookies: &CookieJar<'_>) -> String {
+    if let Some(cookie) = cookies.get(SESSION_COOKIE) {
+        close_session(cookie.value());
+    }
+    cookies.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    String::from("Logged out.")
+}
+
+/////////////////////////////////////////////
+//                                         //
+//            PERMISSIONS                  //
+//                                         //
+/////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    CreateVoting,
+    EditVoting,
+    CloseVoting,
+    ViewInviteCodes,
+    ManageUsers,
+    ManageCriteria,
+    ManageOperators,
+    ManageStorage,
+}
+impl Permission {
+    /// Voting scoped permissions are checked against the owner of the voting
+    /// in the first dynamic segment of the route.
+    fn is_voting_scoped(&self) -> bool {
+        matches!(self, Permission::EditVoting | Permission::CloseVoting)
+    }
+}
+impl UserRole {
+    pub fn permissions(&self) -> Vec<Permission> {
+        match self {
+            UserRole::Admin => vec![
+                Permission::CreateVoting,
+                Permission::EditVoting,
+                Permission::CloseVoting,
+                Permission::ViewInviteCodes,
+                Permission::ManageUsers,
+                Permission::ManageCriteria,
+                Permission::ManageOperators,
+                Permission::ManageStorage,
+            ],
+            UserRole::Maintainer => vec![
+                Permission::CreateVoting,
+                Permission::EditVoting,
+                Permission::CloseVoting,
+                Permission::ViewInviteCodes,
+                Permission::ManageUsers,
+                Permission::ManageCriteria,
+            ],
+        }
+    }
+}
+impl ElevatedUser {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.role.permissions().contains(&permission)
+    }
+    /// Admins manage every voting, everybody else only the ones they own.
+    pub fn owns(&self, voting: &Voting) -> bool {
+        match &voting.owner {
+            _ if self.role == UserRole::Admin => true,
+            Some(owner) => owner == &self.name,
+            None => false,
+        }
+    }
+}
+
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+pub mod can {
+    use super::{Permission, RequiredPermission};
+    macro_rules! required_permission {
+        ($($name:ident),*) => {
+            $(
+                pub struct $name;
+                impl RequiredPermission for $name {
+                    const PERMISSION: Permission = Permission::$name;
                 }
-                Err(_) => Outcome::Error((Status::BadRequest, unautorized_error)),
-            },
-            None => Outcome::Error((Status::BadRequest, unautorized_error)),
+            )*
+        };
+    }
+    required_permission!(
+        CreateVoting,
+        EditVoting,
+        CloseVoting,
+        ViewInviteCodes,
+        ManageUsers,
+        ManageCriteria,
+        ManageOperators,
+        ManageStorage
+    );
+}
+
+/// Request guard for every admin and API route, which changes data.
+/// It resolves the operator and checks the permission `P` and, for voting
+/// scoped permissions, the ownership of the requested voting.
+pub struct Authorized<P: RequiredPermission> {
+    pub user: ElevatedUser,
+    permission: PhantomData<P>,
+}
+impl<P: RequiredPermission> Authorized<P> {
+    pub fn new(user: ElevatedUser) -> Self {
+        Self {
+            user,
+            permission: PhantomData,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, P: RequiredPermission> FromRequest<'r> for Authorized<P> {
+    type Error = VoteErrorKind<'r>;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = match ElevatedUser::from_request(req).await {
+            Outcome::Success(u) => u,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+        let forbidden_error = VoteErrorKind::Unauthorized(Unauthorized(String::from(
+            "Missing permission for this action.",
+        )));
+        if !user.has_permission(P::PERMISSION) {
+            user.audit("was denied", &format!("{:?}", P::PERMISSION));
+            return Outcome::Error((Status::Forbidden, forbidden_error));
         }
+        if P::PERMISSION.is_voting_scoped() {
+            let voting = match req.param::<&str>(0) {
+                Some(Ok(v)) => Voting::fill(&v.to_lowercase(), true, "voting").await,
+                _ => return Outcome::Error((Status::Forbidden, forbidden_error)),
+            };
+            if !user.owns(&voting) {
+                user.audit("was denied", &voting.name);
+                return Outcome::Error((Status::Forbidden, forbidden_error));
+            }
+        }
+        Outcome::Success(Authorized::new(user))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_credentials_of_the_header() {
+        assert_eq!(
+            Credentials::from_header("Bearer secret"),
+            Some(Credentials::Token(String::from("secret")))
+        );
+        assert_eq!(
+            Credentials::from_header(&format!("Basic {}", STANDARD.encode("alice:pass:word"))),
+            Some(Credentials::Operator {
+                name: String::from("alice"),
+                password: String::from("pass:word")
+            })
+        );
+        assert_eq!(Credentials::from_header("Basic not-base64"), None);
+        assert_eq!(Credentials::from_header("Digest secret"), None);
+    }
+
+    #[rocket::async_test]
+    async fn rejects_names_no_operator_can_have() {
+        let credentials = Credentials::Operator {
+            name: String::from("../candidates/voter_obama"),
+            password: String::from("password"),
+        };
+        assert!(credentials.verify().await.is_none());
+        assert!(Credentials::Token(String::new()).verify().await.is_none());
+    }
+
+    #[test]
+    fn keeps_users_logged_in() {
+        let id = open_session(&ElevatedUser::new("alice", UserRole::Maintainer));
+        let user = session_user(&id).unwrap();
+        assert_eq!(user.name, "alice");
+        assert_eq!(user.role, UserRole::Maintainer);
+        close_session(&id);
+        assert!(session_user(&id).is_none());
+        assert!(session_user("unknown").is_none());
+    }
+
+    #[test]
+    fn ends_the_sessions_of_a_modified_operator() {
+        let first = open_session(&ElevatedUser::new("bob", UserRole::Admin));
+        let second = open_session(&ElevatedUser::new("bob", UserRole::Admin));
+        let other = open_session(&ElevatedUser::new("carol", UserRole::Admin));
+        close_sessions_of("Bob");
+        assert!(session_user(&first).is_none());
+        assert!(session_user(&second).is_none());
+        assert_eq!(session_user(&other).unwrap().name, "carol");
     }
 }
@@ -0,0 +1,480 @@
+use crate::{
+    authentication::{can, Authorized},
+    csrf::CsrfVerified,
+    error::VoteErrorKind,
+    memory::MemoryStorage,
+    migration::{canonical, entries_of, ids_of, migrate, MigrationReport, MIGRATED_KINDS},
+    persistence::{Kind, PutMode, Storage, StorageBackend},
+};
+use chrono::{DateTime, Utc};
+use rocket::{
+    data::{ByteUnit, Data, Limits},
+    error, get,
+    http::Status,
+    post,
+    serde::{
+        json::{self, Json, Value},
+        Deserialize, Serialize,
+    },
+    warn, FromFormField, State,
+};
+use std::collections::{BTreeMap, HashSet};
+
+/// The version of the archive format written by `export`.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// The size of an archive accepted by `restore_backup`, unless the `backup`
+/// limit of Rocket.toml says otherwise.
+const ARCHIVE_LIMIT: ByteUnit = ByteUnit::Mebibyte(64);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ArchiveEntry {
+    pub id: String,
+    pub entry: Value,
+}
+
+/// Every voting, candidate, criterion, operator, emoji category and ballot
+/// of a storage, by the directory of their kind. The entries are kept as the
+/// storage holds them, under the id their type gives them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Archive {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// The backend the archive was exported from.
+    pub storage: String,
+    pub entries: BTreeMap<String, Vec<ArchiveEntry>>,
+}
+
+impl Archive {
+    /// The kinds of the archive, if it can be restored. Every entry has to be
+    /// readable as its type.
+    fn kinds(&self) -> Result<Vec<(Kind, &Vec<ArchiveEntry>)>, String> {
+        if self.version != ARCHIVE_VERSION {
+            return Err(format!(
+                "Archive version {} isn't supported, expected {}.",
+                self.version, ARCHIVE_VERSION
+            ));
+        }
+        let mut kinds = vec![];
+        for (dir, entries) in &self.entries {
+            let kind = match MIGRATED_KINDS.into_iter().find(|k| k.dir() == dir) {
+                Some(kind) => kind,
+                None => return Err(format!("Archive holds unknown kind {}.", dir)),
+            };
+            let mut ids = HashSet::new();
+            if let Some(twice) = entries.iter().find(|e| !ids.insert(&e.id)) {
+                return Err(format!("Archive holds {} {} twice.", dir, twice.id));
+            }
+            for ArchiveEntry { id, entry } in entries {
+                if let Err(e) = canonical(kind, &entry.to_string()) {
+                    return Err(format!("Archive holds unreadable {} {}: {}", dir, id, e));
+                }
+            }
+            kinds.push((kind, entries));
+        }
+        Ok(kinds)
+    }
+    /// Whether the archive can be restored, and why not.
+    pub fn check(&self) -> Result<(), String> {
+        self.kinds().map(|_| ())
+    }
+    /// The archive in memory, nothing is written before it is read completely.
+    async fn stage(&self) -> Result<MemoryStorage, VoteErrorKind<'static>> {
+        let archived = MemoryStorage::new();
+        for (kind, entries) in self.kinds().map_err(VoteErrorKind::Internal)? {
+            for ArchiveEntry { id, entry } in entries {
+                archived
+                    .put(kind, id, entry.to_string(), PutMode::Create)
+                    .await?;
+            }
+        }
+        Ok(archived)
+    }
+}
+
+/// How `restore` treats the entries a storage has already.
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromFormField)]
+pub enum RestoreMode {
+    /// Entries of the archive are added, the ones of the storage are kept.
+    #[default]
+    Merge,
+    /// The storage holds the archive afterwards and nothing else.
+    Replace,
+}
+
+/// What a restore did, `kinds` per kind of the archive.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RestoreReport {
+    /// Whether the storage holds every entry of the archive as it is.
+    pub verified: bool,
+    /// Entries of the storage, which differed from the archive, overwritten
+    /// on a replace.
+    pub replaced: BTreeMap<String, Vec<String>>,
+    /// Entries of the storage, which weren't in the archive, deleted on a
+    /// replace after every entry of the archive was written.
+    pub deleted: BTreeMap<String, Vec<String>>,
+    pub kinds: Vec<MigrationReport>,
+}
+
+/// Reads every entry of the migrated kinds. Entries, which can't be read as
+/// their type, are left out.
+pub async fn export(storage: &dyn StorageBackend) -> Result<Archive, VoteErrorKind<'static>> {
+    let mut entries = BTreeMap::new();
+    for kind in MIGRATED_KINDS {
+        let mut of_kind = vec![];
+        for id in ids_of(storage, kind).await? {
+            let raw = match storage.get(kind, &id).await {
+                Ok(raw) => raw,
+                Err(VoteErrorKind::NotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            match canonical(kind, &raw) {
+                Ok((id, _)) => of_kind.push(ArchiveEntry {
+                    id,
+                    entry: json::from_str(&raw)?,
+                }),
+                Err(e) => warn!("Leaving out {} {}: {}", kind.dir(), id, e),
+            }
+        }
+        entries.insert(kind.dir().to_string(), of_kind);
+    }
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        created_at: Utc::now(),
+        storage: storage.name().to_string(),
+        entries,
+    })
+}
+
+/// Deletes the entries of `kind` in `target`, which `archived` doesn't hold
+/// as they are.
+async fn delete_others(
+    archived: &MemoryStorage,
+    target: &dyn StorageBackend,
+    kind: Kind,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let kept = entries_of(archived, kind).await?;
+    let mut deleted = vec![];
+    for id in ids_of(target, kind).await? {
+        let raw = match target.get(kind, &id).await {
+            Ok(raw) => raw,
+            Err(VoteErrorKind::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        if let Ok((typed, entry)) = canonical(kind, &raw) {
+            if kept.get(&typed) == Some(&entry) {
+                continue;
+            }
+        }
+        target.delete(kind, &id).await?;
+        deleted.push(id);
+    }
+    Ok(deleted)
+}
+
+/// An entry `write_archived` wrote and what the target held before.
+struct Written {
+    kind: Kind,
+    id: String,
+    previous: Option<String>,
+}
+
+/// Writes every entry of `archived` to `target`, which it doesn't hold as it
+/// is, and logs each to `written`.
+async fn write_entries(
+    archived: &MemoryStorage,
+    target: &dyn StorageBackend,
+    written: &mut Vec<Written>,
+) -> Result<(), VoteErrorKind<'static>> {
+    for kind in MIGRATED_KINDS {
+        let in_target = entries_of(target, kind).await?;
+        for id in ids_of(archived, kind).await? {
+            let raw = archived.get(kind, &id).await?;
+            let (id, entry) = canonical(kind, &raw)?;
+            let (mode, previous) = match in_target.get(&id) {
+                Some(existing) if *existing == entry => continue,
+                Some(_) => (PutMode::Replace, Some(target.get(kind, &id).await?)),
+                None => (PutMode::Create, None),
+            };
+            target.put(kind, &id, raw, mode).await?;
+            written.push(Written { kind, id, previous });
+        }
+    }
+    Ok(())
+}
+
+/// Writes the entries of `archived` to `target` and returns the ones it
+/// replaced. If a write fails, the written entries are undone.
+async fn write_archived(
+    archived: &MemoryStorage,
+    target: &dyn StorageBackend,
+) -> Result<BTreeMap<String, Vec<String>>, VoteErrorKind<'static>> {
+    let mut written = vec![];
+    if let Err(e) = write_entries(archived, target, &mut written).await {
+        error!(
+            "Restoring failed, undoing {} entries: {:?}",
+            written.len(),
+            e
+        );
+        for Written { kind, id, previous } in written.into_iter().rev() {
+            let undone = match previous {
+                Some(previous) => target.put(kind, &id, previous, PutMode::Replace).await,
+                None => target.delete(kind, &id).await.map(|_| String::new()),
+            };
+            if let Err(e) = undone {
+                error!("Undoing {} {}: {:?}", kind.dir(), id, e);
+            }
+        }
+        return Err(e);
+    }
+    let mut replaced = BTreeMap::new();
+    for Written { kind, id, previous } in written {
+        if previous.is_some() {
+            replaced
+                .entry(kind.dir().to_string())
+                .or_insert_with(Vec::new)
+                .push(id);
+        }
+    }
+    Ok(replaced)
+}
+
+/// Writes the archive to `target`, see `RestoreMode`. A replace writes every
+/// entry before deleting the others and is refused, if the archive lacks the
+/// stored operator `caller`. The results of the target are counted anew on
+/// their next read.
+pub async fn restore(
+    archive: &Archive,
+    target: &dyn StorageBackend,
+    mode: RestoreMode,
+    caller: &str,
+) -> Result<RestoreReport, VoteErrorKind<'static>> {
+    let archived = archive.stage().await?;
+    let mut report = RestoreReport::default();
+    if mode == RestoreMode::Replace {
+        let caller = caller.to_lowercase();
+        if entries_of(target, Kind::Operator)
+            .await?
+            .contains_key(&caller)
+            && !entries_of(&archived, Kind::Operator)
+                .await?
+                .contains_key(&caller)
+        {
+            return Err(VoteErrorKind::Conflict(format!(
+                "The archive lacks the operator {}, who restores it.",
+                caller
+            )));
+        }
+        report.replaced = write_archived(&archived, target).await?;
+        for kind in MIGRATED_KINDS.into_iter().rev() {
+            let deleted = delete_others(&archived, target, kind).await?;
+            report.deleted.insert(kind.dir().to_string(), deleted);
+        }
+        if !target.computes_results() {
+            for id in target.list(Kind::Result).await? {
+                target.delete(Kind::Result, &id).await?;
+            }
+        }
+    }
+    report.kinds = migrate(&archived, target, false, &mut |_| {}).await?;
+    report.verified = report.kinds.iter().all(|r| r.is_verified());
+    Ok(report)
+}
+
+fn to_status(e: VoteErrorKind<'static>) -> Status {
+    error!("{:?}", e);
+    Status::from(&e)
+}
+
+#[get("/backup")]
+pub async fn get_backup(
+    authorized: Authorized<can::ManageStorage>,
+    storage: &State<Storage>,
+) -> Result<Json<Archive>, Status> {
+    authorized
+        .user
+        .audit("exported a backup of", storage.name());
+    Ok(Json(export(storage.as_ref()).await.map_err(to_status)?))
+}
+
+#[post("/restore?<mode>", data = "<archive>")]
+pub async fn restore_backup(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageStorage>,
+    mode: Option<RestoreMode>,
+    limits: &Limits,
+    storage: &State<Storage>,
+    archive: Data<'_>,
+) -> Result<Json<RestoreReport>, Status> {
+    let limit = limits.get("backup").unwrap_or(ARCHIVE_LIMIT);
+    let read = archive.open(limit).into_string().await.map_err(|e| {
+        error!("Reading the archive: {}", e);
+        Status::BadRequest
+    })?;
+    if !read.is_complete() {
+        return Err(Status::PayloadTooLarge);
+    }
+    let archive = json::from_str::<Archive>(&read).map_err(|e| {
+        warn!("Unreadable archive: {}", e);
+        Status::UnprocessableEntity
+    })?;
+    if let Err(e) = archive.check() {
+        warn!("{}", e);
+        return Err(Status::UnprocessableEntity);
+    }
+    let mode = mode.unwrap_or_default();
+    authorized.user.audit(
+        &format!("restored a backup from {} ({:?}) to", archive.storage, mode),
+        storage.name(),
+    );
+    Ok(Json(
+        restore(&archive, storage.as_ref(), mode, &authorized.user.name)
+            .await
+            .map_err(to_status)?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_data() -> MemoryStorage {
+        let test_data =
+            std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/";
+        MemoryStorage::seeded(&test_data, &test_data)
+    }
+
+    #[rocket::async_test]
+    async fn restores_an_exported_archive() {
+        let source = test_data();
+        let archive = export(&source).await.unwrap();
+        assert_eq!(archive.entries.len(), MIGRATED_KINDS.len());
+        assert_eq!(archive.entries["ballots"].len(), 3);
+        let archive: Archive = json::from_str(&json::to_string(&archive).unwrap()).unwrap();
+
+        let target = MemoryStorage::new();
+        let report = restore(&archive, &target, RestoreMode::Merge, "alice")
+            .await
+            .unwrap();
+        assert!(report.verified, "{:?}", report);
+        assert!(report.deleted.is_empty());
+        assert_eq!(export(&target).await.unwrap().entries, archive.entries);
+    }
+
+    #[rocket::async_test]
+    async fn merges_or_replaces_existing_entries() {
+        let archive = export(&test_data()).await.unwrap();
+        let target = test_data();
+        let voting = target.get(Kind::Voting, "voting").await.unwrap();
+        target
+            .put(
+                Kind::Voting,
+                "voting",
+                voting.replace("W1234", "X9876"),
+                PutMode::Replace,
+            )
+            .await
+            .unwrap();
+        target
+            .put(
+                Kind::Voting,
+                "other",
+                voting.replace("\"Voting\"", "\"Other\""),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+
+        let merged = restore(&archive, &target, RestoreMode::Merge, "alice")
+            .await
+            .unwrap();
+        assert!(!merged.verified);
+        let votings = merged.kinds.iter().find(|r| r.kind == "votings").unwrap();
+        assert_eq!(votings.differing, vec!["voting"]);
+        assert!(target.get(Kind::Voting, "other").await.is_ok());
+
+        let replaced = restore(&archive, &target, RestoreMode::Replace, "alice")
+            .await
+            .unwrap();
+        assert!(replaced.verified, "{:?}", replaced);
+        assert_eq!(replaced.replaced["votings"], vec!["voting"]);
+        assert_eq!(replaced.deleted["votings"], vec!["other"]);
+        assert!(replaced.deleted["ballots"].is_empty());
+        assert_eq!(export(&target).await.unwrap().entries, archive.entries);
+    }
+
+    #[rocket::async_test]
+    async fn refuses_to_replace_the_restoring_operator() {
+        let archive = export(&test_data()).await.unwrap();
+        let target = test_data();
+        let alice = crate::common::Operator {
+            name: String::from("Alice"),
+            password_hash: String::new(),
+            role: crate::authentication::UserRole::Admin,
+        };
+        target
+            .put(
+                Kind::Operator,
+                "alice",
+                json::to_string(&alice).unwrap(),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            restore(&archive, &target, RestoreMode::Replace, "Alice").await,
+            Err(VoteErrorKind::Conflict(_))
+        ));
+        assert!(target.get(Kind::Operator, "alice").await.is_ok());
+
+        let replaced = restore(&archive, &target, RestoreMode::Replace, "admin")
+            .await
+            .unwrap();
+        assert_eq!(replaced.deleted["operators"], vec!["alice"]);
+    }
+
+    #[rocket::async_test]
+    async fn leaves_the_target_alone_for_unreadable_archives() {
+        let mut archive = export(&test_data()).await.unwrap();
+        archive.entries.get_mut("votings").unwrap()[0].entry = Value::from("voting");
+        assert!(archive.check().unwrap_err().contains("unreadable"));
+
+        let target = test_data();
+        let before = export(&target).await.unwrap().entries;
+        assert!(restore(&archive, &target, RestoreMode::Replace, "admin")
+            .await
+            .is_err());
+        assert_eq!(export(&target).await.unwrap().entries, before);
+    }
+
+    #[test]
+    fn rejects_unknown_archives() {
+        let mut archive = Archive {
+            version: ARCHIVE_VERSION,
+            created_at: Utc::now(),
+            storage: String::from("memory"),
+            entries: BTreeMap::new(),
+        };
+        assert!(archive.check().is_ok());
+        archive.entries.insert(
+            String::from("votings"),
+            vec![
+                ArchiveEntry {
+                    id: String::from("voting"),
+                    entry: Value::Null,
+                };
+                2
+            ],
+        );
+        assert!(archive.check().unwrap_err().contains("twice"));
+        archive.entries.clear();
+        archive.entries.insert(String::from("results"), vec![]);
+        assert!(archive.check().is_err());
+        archive.entries.clear();
+        archive.version = ARCHIVE_VERSION + 1;
+        assert!(archive.check().is_err());
+    }
+}
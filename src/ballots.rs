@@ -1,27 +1,31 @@
-#[cfg(feature = "db")]
-use crate::db::common::Query;
 use crate::{
+    aggregate::{self, CandidateResult},
     common::{
-        from_optional_str, Ballot, Candidate, CastBallots, Criterion, Empty, Fill, IdGenerator,
-        KnownBallots, QueryableExt, Selfaware, Table as VVTable, Vote, VoteKind, Voting,
+        Ballot, Candidate, CastBallots, Criterion, Empty, Fill, IdGenerator, KnownBallots, Vote,
+        VoteKind, Voting,
     },
+    csrf::{is_same_token, CsrfOrAllowedOrigin},
     error::VoteErrorKind,
-    persistence::ToPersistence,
+    persistence::{storage, Kind, Storage},
+    rate_limit::{is_throttled, record_failure, record_success, Scope},
     routes::API_BALLOTS,
     serialize::ToStorage,
     validator::{compare_pattern_file_names, validate},
 };
+#[cfg(feature = "db")]
+use crate::{
+    common::{DbRow, DbValue, FromDbRow, QueryableExt, Selfaware, Table as VVTable},
+    db::{statements::BallotRow, Connection},
+};
 use chrono::prelude::*;
-#[cfg(feature = "diesel_sqlite")]
-use diesel::{prelude::*, table};
 use rocket::{
-    debug, error, get,
+    error, get,
     http::Status,
     post,
     request::{FromRequest, Outcome},
     response::status::{Created, Unauthorized},
     serde::{json::Json, Deserialize, Serialize},
-    Request,
+    Request, State,
 };
 
 #[derive(Debug, Serialize, PartialEq, Deserialize, Clone)]
@@ -34,12 +38,19 @@ impl<'r> FromRequest<'r> for Voter<'r> {
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let default_voting = Voting::empty();
+        let voting_id = req.uri().path().segments().get(3).unwrap_or_default();
+        if is_throttled(req, Scope::Voting(voting_id)) {
+            return Outcome::Error((
+                Status::TooManyRequests,
+                VoteErrorKind::Unauthorized(Unauthorized(String::from("Try again later."))),
+            ));
+        }
         let voting: Voting = match req.uri().path().segments().get(3) {
             Some(v) => Voting::fill(v, false, "voting").await,
             None => default_voting,
         };
         fn is_correct_invite_code(key: &str, voting: &Voting) -> bool {
-            key == voting.invite_code
+            !voting.invite_code.is_empty() && is_same_token(key, &voting.invite_code)
         }
         let unautorized_error =
             VoteErrorKind::Unauthorized(Unauthorized(String::from("Supply an invite code.")));
@@ -48,6 +59,7 @@ impl<'r> FromRequest<'r> for Voter<'r> {
             let has_correct_invite_code =
                 is_correct_invite_code(invite_code_header_value.unwrap(), &voting);
             if has_correct_invite_code {
+                record_success(req, Scope::Voting(voting_id));
                 let user_header_value = req.headers().get_one("x-concafe-user");
                 if user_header_value.is_some() {
                     Outcome::Success(Voter(user_header_value.unwrap()))
@@ -55,6 +67,7 @@ impl<'r> FromRequest<'r> for Voter<'r> {
                     Outcome::Error((Status::Unauthorized, unautorized_error))
                 }
             } else {
+                record_failure(req, Scope::Voting(voting_id));
                 Outcome::Error((Status::Unauthorized, unautorized_error))
             }
         } else {
@@ -80,6 +93,7 @@ pub async fn get_ballots_by_voting(voting: &str) -> Json<CastBallots> {
 
 #[post("/<voting_id>", format = "application/json", data = "<ballot>")]
 pub async fn post_ballot<'r>(
+    _csrf: CsrfOrAllowedOrigin,
     voter: Voter<'r>,
     voting_id: &str,
     ballot: Json<Ballot>,
@@ -101,31 +115,37 @@ pub async fn post_ballot<'r>(
                 ballots: vec![inner_ballot],
             }],
         };
-
-        #[cfg(not(feature = "sqlx_sqlite"))]
         match cast_ballot.save().await {
-            Ok(done) => Ok(Created::new(
-                API_BALLOTS.to_owned() + "/" + voting_id + "/voter/" + voter.0,
-            )),
-            Err(_e) => Err(Status::UnprocessableEntity),
-        }
-        #[cfg(feature = "sqlx_sqlite")]
-        match BallotsTable::from(CompleteBallotsTable::aggregate_all(cast_ballot).await)
-            .save()
-            .await
-        {
-            Ok(_done) => Ok(Created::new(
-                API_BALLOTS.to_owned() + "/" + voting_id + "/voter/" + voter.0,
-            )),
+            Ok(_done) => match aggregate::count_ballot(cast_ballot.clone()).await {
+                Ok(()) => Ok(Created::new(
+                    API_BALLOTS.to_owned() + "/" + voting_id + "/voter/" + voter.0,
+                )),
+                // The totals are behind, the ballot is taken back.
+                Err(e) => {
+                    error!("Counting the ballot failed: {:?}", e);
+                    if let Err(e) = take_back(&cast_ballot).await {
+                        error!("Taking back the ballot failed: {:?}", e);
+                    }
+                    Err(Status::InternalServerError)
+                }
+            },
             Err(_e) => Err(Status::UnprocessableEntity),
         }
     } else {
         Err(Status::UnprocessableEntity)
     }
 }
+/// Deletes a saved ballot, whose points couldn't be counted. Databases save
+/// the ballot and its votes in one transaction and sum them up in SQL, so
+/// only the other backends ever take a ballot back.
+async fn take_back(cast_ballot: &CastBallots) -> Result<String, VoteErrorKind<'static>> {
+    storage()?
+        .delete(Kind::Ballot, &IdGenerator::get_id(cast_ballot))
+        .await
+}
 #[cfg(feature = "db")]
 impl CompleteBallotsTable {
-    async fn aggregate_all(c_B: CastBallots) -> Self {
+    pub async fn aggregate_all(c_B: CastBallots) -> Self {
         let ballots = c_B.ballots.first().unwrap().ballots.first().unwrap();
         let voter = &c_B.ballots.first().unwrap().voter;
         let votes = &ballots.votes;
@@ -193,11 +213,10 @@ pub struct TableRow {
     pub weighted: f32,
     pub mean: f32,
     notes: String,
-    votes: Vec<Vote>,
+    pub(crate) votes: Vec<Vote>,
     pub voted_on: DateTime<Utc>,
 }
 
-#[cfg(not(feature = "diesel_sqlite"))]
 #[derive(Serialize, Debug, PartialEq, Clone, Deserialize)]
 #[serde(crate = "rocket::serde")]
 #[cfg(feature = "db")]
@@ -227,58 +246,77 @@ pub struct CompleteBallotsTable {
     votes: String,
     pub voted_on: DateTime<Utc>,
 }
+/// Every vote is kept in `votes` as well, so results can be computed in SQL.
 #[cfg(feature = "db")]
-impl BallotsTable {
-    fn values(&self) -> String {
-        format!(
-            "'{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}'",
-            self.get_id(),
-            self.candidate,
-            self.voter,
-            self.sum,
-            self.weighted,
-            self.mean,
-            self.notes,
-            self.votes,
-            self.voted_on.to_string()
+#[rocket::async_trait]
+impl crate::db::common::Query for BallotsTable {
+    async fn save_relations(&self, conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        let ballot = self.get_id();
+        let votes: Vec<Vec<DbValue>> = TableRow::from(self)
+            .votes
+            .iter()
+            .map(|v| {
+                vec![
+                    DbValue::Text(ballot.clone()),
+                    DbValue::Text(v.name.clone()),
+                    DbValue::Integer(i64::from(v.point)),
+                ]
+            })
+            .collect();
+        crate::db::replace_rows(
+            conn,
+            "votes",
+            "ballot",
+            &ballot,
+            "ballot, criterion, point",
+            &votes,
+        )
+        .await?;
+        Ok(())
+    }
+    async fn delete_relations(&self, conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        crate::db::replace_rows(
+            conn,
+            "votes",
+            "ballot",
+            &self.get_id(),
+            "ballot, criterion, point",
+            &[],
         )
+        .await?;
+        Ok(())
     }
 }
 #[cfg(feature = "db")]
-impl Query for BallotsTable {}
-#[cfg(feature = "db")]
 impl QueryableExt for BallotsTable {}
 #[cfg(feature = "db")]
-impl std::str::FromStr for BallotsTable {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let result: Vec<&str> = v.split(",-o-,").collect();
-        debug!("{:?}", result);
-        match CastBallots::from_str(v) {
-            Ok(_c_b) => Ok(Self {
-                voter: from_optional_str(result.get(2).copied()),
-                voting: result
-                    .get(0)
-                    .copied()
-                    .expect("The structure is always 0-1-2")
+impl FromDbRow for BallotsTable {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
+        Ok(Self {
+            voter: row.text("voter"),
+            // Rows from before the voting column hold it as prefix of the
+            // human identifier.
+            voting: match row.text("voting") {
+                voting if !voting.is_empty() => voting,
+                _ => row
+                    .text("human_identifier")
                     .split_once('-')
-                    .unwrap()
-                    .0
-                    .to_string(),
-                candidate: from_optional_str(result.get(1).copied()),
-                sum: i16::from_str(&from_optional_str(result.get(3).copied())).unwrap(),
-                weighted: f32::from_str(&from_optional_str(result.get(4).copied())).unwrap(),
-                mean: f32::from_str(&from_optional_str(result.get(5).copied())).unwrap(),
-                notes: from_optional_str(result.get(6).copied()),
-                votes: from_optional_str(result.get(7).copied()),
-                voted_on: DateTime::from_str(&from_optional_str(result.get(8).copied())).unwrap(),
-            }),
-            Err(_) => Ok(Self::empty()),
-        }
+                    .map(|(voting, _)| voting.to_string())
+                    .unwrap_or_default(),
+            },
+            candidate: row.text("candidate"),
+            sum: i16::try_from(row.integer("sum")?).map_err(|_| {
+                crate::error::FromErrorKind::Serialize(String::from("Column sum is too large."))
+            })?,
+            weighted: row.real("weighted")? as f32,
+            mean: row.real("mean")? as f32,
+            notes: row.text("notes"),
+            votes: row.text("votes"),
+            voted_on: row.timestamp("voted_on")?,
+        })
     }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl VVTable for BallotsTable {
     fn get_identity_column_name() -> String {
         String::from("human_identifier")
@@ -293,8 +331,19 @@ impl VVTable for BallotsTable {
     fn get_db_columns() -> String {
         CastBallots::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Text(self.get_id()),
+            DbValue::Text(self.candidate.clone()),
+            DbValue::Text(self.voter.clone()),
+            DbValue::Integer(i64::from(self.sum)),
+            DbValue::from_f32(self.weighted),
+            DbValue::from_f32(self.mean),
+            DbValue::Text(self.notes.clone()),
+            DbValue::Text(self.votes.clone()),
+            DbValue::Text(self.voted_on.to_string()),
+            DbValue::Text(self.voting.to_lowercase()),
+        ]
     }
 }
 #[cfg(feature = "db")]
@@ -327,14 +376,6 @@ impl IdGenerator for BallotsTable {
     }
 }
 #[cfg(feature = "db")]
-impl Fill for BallotsTable {}
-#[cfg(feature = "db")]
-impl crate::serialize::FromStorage for BallotsTable {}
-#[cfg(feature = "db")]
-impl crate::persistence::FromPersistence for BallotsTable {}
-#[cfg(feature = "db")]
-impl crate::persistence::Path for BallotsTable {}
-#[cfg(feature = "db")]
 impl Selfaware for BallotsTable {
     fn get_type(&self) -> String {
         String::from("CBallots")
@@ -345,20 +386,6 @@ impl Selfaware for BallotsTable {
     }
 }
 
-#[cfg(feature = "diesel_sqlite")]
-#[derive(Serialize, Debug, PartialEq, Clone, Deserialize, Queryable, Insertable, Selectable)]
-#[diesel(table_name = ballots )]
-#[serde(crate = "rocket::serde")]
-pub struct BallotsTable {
-    pub voter: String,
-    pub candidate: String,
-    pub sum: i16,
-    pub weighted: f32,
-    pub mean: f32,
-    notes: String,
-    votes: String,
-    pub voted_on: DateTime<Utc>,
-}
 #[cfg(feature = "db")]
 impl From<TableRow> for BallotsTable {
     fn from(tr: TableRow) -> Self {
@@ -382,6 +409,25 @@ impl From<TableRow> for BallotsTable {
     }
 }
 
+/// A row holds a single ballot of a voter.
+#[cfg(feature = "db")]
+impl From<BallotsTable> for CastBallots {
+    fn from(b_t: BallotsTable) -> Self {
+        let row = TableRow::from(&b_t);
+        Self {
+            voting: Some(b_t.voting),
+            ballots: vec![KnownBallots {
+                voter: b_t.voter,
+                ballots: vec![Ballot {
+                    candidate: b_t.candidate,
+                    votes: row.votes,
+                    notes: Some(b_t.notes),
+                    voted_on: Some(b_t.voted_on),
+                }],
+            }],
+        }
+    }
+}
 #[cfg(feature = "db")]
 impl From<&BallotsTable> for TableRow {
     fn from(b_t: &BallotsTable) -> Self {
@@ -404,9 +450,30 @@ impl From<&BallotsTable> for TableRow {
         }
     }
 }
-#[cfg(feature = "file")]
+#[cfg(feature = "db")]
+impl From<BallotRow> for TableRow {
+    fn from(b_r: BallotRow) -> Self {
+        Self {
+            voting: b_r.voting,
+            voter: b_r.voter,
+            candidate: b_r.candidate,
+            sum: i8::try_from(b_r.sum).unwrap_or(i8::MAX),
+            weighted: b_r.weighted as f32,
+            mean: b_r.mean as f32,
+            notes: b_r.notes,
+            votes: match rocket::serde::json::from_str::<Vec<Vote>>(&b_r.votes) {
+                Ok(vs) => vs,
+                Err(e) => {
+                    error!("From string votes to struct votes didnt work. {:?}", e);
+                    vec![]
+                }
+            },
+            voted_on: b_r.voted_on.parse::<DateTime<Utc>>().unwrap_or_default(),
+        }
+    }
+}
 impl TableRow {
-    fn from_cast_ballots(
+    pub(crate) fn from_cast_ballots(
         cast_ballots: Vec<CastBallots>,
         categories: &Vec<Criterion>,
     ) -> Vec<TableRow> {
@@ -416,7 +483,6 @@ impl TableRow {
         }
     }
 }
-#[cfg(feature = "file")]
 fn map_ballots(ballots: Vec<CastBallots>, categories: &Vec<Criterion>) -> Option<Vec<TableRow>> {
     ballots
         .iter()
@@ -435,7 +501,6 @@ fn map_ballots(ballots: Vec<CastBallots>, categories: &Vec<Criterion>) -> Option
         })
         .reduce(|c, n| [c, n].concat())
 }
-#[cfg(feature = "file")]
 fn map_ballot(
     known_ballot: KnownBallots,
     categories: &Vec<Criterion>,
@@ -522,121 +587,174 @@ fn verify_correct_voting_id(
     }
 }
 
-async fn collect_ballots(voting_id: &str) -> Table<Criterion, TableRow> {
+/// Ballots of a voting with their sum, weighted sum and mean. The backend
+/// computes them if it can, otherwise they are computed out of every ballot.
+async fn query_ballots(
+    storage: &Storage,
+    voting: &Voting,
+    voting_id: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Vec<TableRow> {
+    match storage.results(voting_id, filter, order_by).await {
+        Ok(Some(rows)) => rows,
+        Ok(None) => {
+            let mut rows: Vec<TableRow> = cast_ballots(voting_id, &voting.categories)
+                .await
+                .into_iter()
+                .filter(|b| match filter {
+                    Some(("voter", voter)) => compare_pattern_file_names(&b.voter, voter),
+                    Some((_, candidate)) => compare_pattern_file_names(&b.candidate, candidate),
+                    None => true,
+                })
+                .collect();
+            match order_by {
+                "voted_on" => rows.sort_by(|p, n| p.voted_on.cmp(&n.voted_on)),
+                "sum" => rows.sort_by(|p, n| p.sum.cmp(&n.sum)),
+                "mean" => rows.sort_by(|p, n| p.mean.total_cmp(&n.mean)),
+                "weighted" => rows.sort_by(|p, n| p.weighted.total_cmp(&n.weighted)),
+                _ => {}
+            }
+            rows
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            vec![]
+        }
+    }
+}
+
+/// The rows of every ballot of the voting.
+async fn cast_ballots(voting_id: &str, categories: &Vec<Criterion>) -> Vec<TableRow> {
+    match aggregate::table_rows(voting_id, categories).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!("{:?}", e);
+            vec![]
+        }
+    }
+}
+
+async fn collect_ballots_by(
+    storage: &Storage,
+    voting_id: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Table<Criterion, TableRow> {
     let lowercased_voting_id = voting_id.to_lowercase();
-    let loaded_ballots = CastBallots::empty().list().await.unwrap();
-    #[cfg(not(feature = "sqlx_sqlite"))]
-    let filtered_ballots: Vec<_> = loaded_ballots
-        .iter()
-        .filter(|b| b.starts_with(&lowercased_voting_id))
-        .map(|b| CastBallots::fill(b, true, "CBallots"))
-        .collect();
-    #[cfg(feature = "sqlx_sqlite")]
-    let filtered_ballots: Vec<_> = loaded_ballots
-        .iter()
-        .filter(|b| b.starts_with(&lowercased_voting_id))
-        .map(|b| BallotsTable::fill(b, true, "CBallots"))
-        .collect();
-    let collected_ballots: Vec<_> = futures::future::join_all(filtered_ballots).await;
     let voting = Voting::fill(&lowercased_voting_id, false, "voting").await;
-    debug!("{:?}", collected_ballots);
-    #[cfg(not(feature = "sqlx_sqlite"))]
-    let ballots = TableRow::from_cast_ballots(collected_ballots, &voting.categories);
-    #[cfg(feature = "sqlx_sqlite")]
-    let ballots = collected_ballots
-        .iter()
-        .map(|b| TableRow::from(b))
-        .collect();
+    let ballots = query_ballots(storage, &voting, &lowercased_voting_id, filter, order_by).await;
     verify_correct_voting_id(voting, &lowercased_voting_id, ballots)
 }
 
 #[get("/<voting_id>")]
-pub async fn get_ballots_by_voted_on(voting_id: &str) -> Json<Table<Criterion, TableRow>> {
-    Json(ballots_by_voted_on(voting_id).await)
+pub async fn get_ballots_by_voted_on(
+    storage: &State<Storage>,
+    voting_id: &str,
+) -> Json<Table<Criterion, TableRow>> {
+    Json(ballots_by_voted_on(storage, voting_id).await)
 }
-pub async fn ballots_by_voted_on(voting_id: &str) -> Table<Criterion, TableRow> {
-    let mut table = collect_ballots(voting_id).await;
-    if !table.rows.is_empty() {
-        table.rows.sort_by(|p, n| p.voted_on.cmp(&n.voted_on));
-    }
-    table
+pub async fn ballots_by_voted_on(storage: &Storage, voting_id: &str) -> Table<Criterion, TableRow> {
+    collect_ballots_by(storage, voting_id, None, "voted_on").await
 }
 
 #[get("/<voting_id>/results?<sort>")]
-pub async fn get_ballots_sorted(voting_id: &str, sort: &str) -> Json<Table<Criterion, TableRow>> {
+pub async fn get_ballots_sorted(
+    voting_id: &str,
+    sort: &str,
+) -> Json<Table<Criterion, CandidateResult>> {
     Json(ballots_sorted(voting_id, sort).await)
 }
-pub async fn ballots_sorted(voting_id: &str, sort: &str) -> Table<Criterion, TableRow> {
-    let mut table = collect_ballots(voting_id).await;
-    if !table.rows.is_empty() {
-        table.rows.sort_by(|p, n| match sort {
-            "sum" => p.sum.cmp(&n.sum),
-            "mean" => p.mean.total_cmp(&n.mean),
-            "weight" => p.weighted.total_cmp(&n.weighted),
-            _ => p.sum.cmp(&n.sum),
-        });
+/// The candidates of a voting, the best first. They are read from the totals,
+/// none of the ballots is loaded.
+pub async fn ballots_sorted(voting_id: &str, sort: &str) -> Table<Criterion, CandidateResult> {
+    let order_by = match sort {
+        "mean" => "mean",
+        "weight" => "weighted",
+        _ => "sum",
+    };
+    let lowercased_voting_id = voting_id.to_lowercase();
+    let voting = Voting::fill(&lowercased_voting_id, false, "voting").await;
+    if voting.name.to_lowercase() != lowercased_voting_id {
+        return Table::new(vec![], vec![]);
+    }
+    match aggregate::totals(&lowercased_voting_id, &voting.categories).await {
+        Ok(totals) => {
+            let standings = totals.standings(&voting.categories, order_by);
+            Table::new(voting.categories, standings)
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            Table::new(voting.categories, vec![])
+        }
     }
-    table
 }
 
 #[get("/<voting_id>/voters/<voter>")]
 pub async fn get_ballots_by_voter(
+    storage: &State<Storage>,
     voting_id: &str,
     voter: &str,
 ) -> Json<Table<Criterion, TableRow>> {
-    Json(ballots_by_voter(voting_id, voter).await)
+    Json(ballots_by_voter(storage, voting_id, voter).await)
 }
-pub async fn ballots_by_voter(voting_id: &str, voter: &str) -> Table<Criterion, TableRow> {
-    let voting = Voting::fill(voting_id, false, "voting").await;
-    let ballots: Vec<TableRow> = collect_ballots(voting_id)
-        .await
-        .rows
-        .iter()
-        .filter(|b| compare_pattern_file_names(&b.voter, voter))
-        .cloned()
-        .collect();
-    Table::<Criterion, TableRow>::new(voting.categories, ballots)
+pub async fn ballots_by_voter(
+    storage: &Storage,
+    voting_id: &str,
+    voter: &str,
+) -> Table<Criterion, TableRow> {
+    collect_ballots_by(storage, voting_id, Some(("voter", voter)), "id").await
 }
 
 #[get("/<voting_id>/candidates/<candidate>")]
 pub async fn get_ballots_by_candidate(
+    storage: &State<Storage>,
     voting_id: &str,
     candidate: &str,
 ) -> Json<Table<Criterion, TableRow>> {
-    Json(ballots_by_candidate(voting_id, candidate).await)
+    Json(ballots_by_candidate(storage, voting_id, candidate).await)
 }
-pub async fn ballots_by_candidate(voting_id: &str, candidate: &str) -> Table<Criterion, TableRow> {
-    let voting = Voting::fill(voting_id, false, "voting").await;
-    let ballots: Vec<TableRow> = collect_ballots(voting_id)
-        .await
-        .rows
-        .iter()
-        .filter(|b| compare_pattern_file_names(&b.candidate, candidate))
-        .cloned()
-        .collect();
-    Table::<Criterion, TableRow>::new(voting.categories, ballots)
+pub async fn ballots_by_candidate(
+    storage: &Storage,
+    voting_id: &str,
+    candidate: &str,
+) -> Table<Criterion, TableRow> {
+    collect_ballots_by(storage, voting_id, Some(("candidate", candidate)), "id").await
 }
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::common::VotingStyles;
+    use crate::{
+        common::VotingStyles,
+        persistence::{skips_without_storage, storage},
+    };
     #[rocket::async_test]
     async fn ballots_by_candidate_not_available() {
-        let response = super::ballots_by_candidate("voting", "candidate_Joe").await;
+        if skips_without_storage() {
+            return;
+        }
+        let response =
+            super::ballots_by_candidate(storage().unwrap(), "voting", "candidate_Joe").await;
         assert_eq!(response.headers.len(), 2);
         assert_eq!(response.rows.len(), 0);
     }
 
     #[rocket::async_test]
     async fn ballots_by_candidate() {
-        let response = super::ballots_by_candidate("voting", "joe").await;
+        if skips_without_storage() {
+            return;
+        }
+        let response = super::ballots_by_candidate(storage().unwrap(), "voting", "joe").await;
         assert_eq!(response.headers.len(), 2);
         assert_eq!(response.rows.len(), 2);
     }
 
     #[rocket::async_test]
     async fn ballots_by_voter() {
-        let response = super::ballots_by_voter("voting", "obama").await;
+        if skips_without_storage() {
+            return;
+        }
+        let response = super::ballots_by_voter(storage().unwrap(), "voting", "obama").await;
         assert_eq!(response.headers.len(), 2);
         assert_eq!(response.rows.len(), 2);
         assert_eq!(response.rows.get(1).unwrap().sum, 12);
@@ -644,27 +762,82 @@ mod test {
 
     #[rocket::async_test]
     async fn ballots_sorted() {
+        if skips_without_storage() {
+            return;
+        }
         let response = super::ballots_sorted("voting", "sum").await;
         assert_eq!(response.headers.len(), 2);
-        assert_eq!(response.rows.len(), 3);
-        assert_eq!(response.rows.get(1).unwrap().sum, 14);
+        assert_eq!(response.rows.len(), 2);
+        assert_eq!(response.rows.get(1).unwrap().sum, 15);
     }
 
     #[rocket::async_test]
     async fn collect_ballots() {
-        let response = super::collect_ballots("voting").await;
+        if skips_without_storage() {
+            return;
+        }
+        let response = super::collect_ballots_by(storage().unwrap(), "voting", None, "id").await;
         assert_eq!(response.headers.len(), 2);
         assert_eq!(response.rows.len(), 3);
     }
 
     #[rocket::async_test]
     async fn ballots_by_voted_on() {
-        let response = super::ballots_by_voted_on("voting").await;
+        if skips_without_storage() {
+            return;
+        }
+        let response = super::ballots_by_voted_on(storage().unwrap(), "voting").await;
         assert_eq!(response.headers.len(), 2);
         assert_eq!(response.rows.len(), 3);
         assert_eq!(response.rows.get(1).unwrap().sum, 12);
     }
 
+    #[rocket::async_test]
+    async fn post_ballot() {
+        if skips_without_storage() {
+            return;
+        }
+        use crate::{
+            authentication::{Authorized, ElevatedUser},
+            common::CreateVoting,
+        };
+        let create_voting = CreateVoting {
+            name: "Balloting".to_string(),
+            expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+            candidates: vec![String::from("joe")],
+            criterias: vec![String::from("style_0_10_15")],
+            styles: None,
+            invite_code: String::new(),
+        };
+        let admin = Authorized::new(ElevatedUser::new_admin());
+        crate::votes::post_vote(crate::csrf::CsrfVerified::new(), admin, Json(create_voting))
+            .await
+            .unwrap();
+        let ballot = Json(Ballot {
+            candidate: String::from("Joe"),
+            votes: vec![Vote {
+                name: String::from("Style"),
+                point: 7,
+            }],
+            notes: Some(String::from("Fine")),
+            voted_on: None,
+        });
+        let response = super::post_ballot(
+            CsrfOrAllowedOrigin::new(),
+            Voter("Michelle"),
+            "balloting",
+            ballot,
+        )
+        .await;
+        assert!(response.is_ok());
+
+        let response = super::collect_ballots_by(storage().unwrap(), "balloting", None, "id").await;
+        assert_eq!(response.rows.len(), 1);
+        assert_eq!(response.rows[0].voter, "Michelle");
+        assert_eq!(response.rows[0].notes, "Fine");
+        assert_eq!(response.rows[0].sum, 7);
+    }
+
     #[test]
     fn verify_correct_voting_id() {
         let voting = Voting {
@@ -675,6 +848,8 @@ mod test {
             categories: vec![],
             styles: VotingStyles::default(),
             invite_code: String::from("T1234"),
+            owner: None,
+            invite_code_rotations: vec![],
         };
         let response = super::verify_correct_voting_id(voting, "voting", vec![]);
         assert_eq!(response.headers.len(), 0);
@@ -691,8 +866,11 @@ mod test {
             categories: vec![],
             styles: VotingStyles::default(),
             invite_code: String::from("T1234"),
+            owner: None,
+            invite_code_rotations: vec![],
         };
         let rows = vec![TableRow {
+            voting: String::from("voting"),
             voter: String::from("test"),
             candidate: String::from("test"),
             sum: 9,
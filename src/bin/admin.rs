@@ -0,0 +1,219 @@
+use std::io::BufRead;
+#[cfg(feature = "local")]
+use voters_verdict::persistence::FileConfig;
+use voters_verdict::{
+    config::{BALLOT_LAYOUT, FILE_DIR},
+    consistency::check_storage,
+    migration::{migrate, Progress},
+    operators::bootstrap_admin,
+    persistence::{backend_of, storage},
+};
+
+const BOOTSTRAP_PASSWORD: &str = "VOTERS_VERDICT_BOOTSTRAP_PASSWORD";
+
+fn print_help() {
+    println!(
+        "voters-verdict-admin helps you to manage your voting machine.
+
+    Subcommands:
+
+     bootstrap <name>          Creates the first admin operator. The password is taken from
+                               {} or read from stdin.
+     check [--rebuild]         Compares the index of every directory with its entries and
+                               reports orphans, missing entries and unparsable JSON.
+                               --rebuild writes a new index, where they differ.
+     migrate <from> <to> [--dry-run]
+                               Copies every voting, candidate, criterion, operator, emoji
+                               category and ballot from one storage mode to another, e.g.
+                               `migrate local db`. Entries in both are kept and reported,
+                               if they differ. Counts and checksums of both are compared
+                               afterwards. --dry-run only reads.
+     migrate-ballots           Appends every ballot file below {} to the JSON Lines
+                               file of its voting and removes it, see {}.
+     help                      Prints this message.
+
+    The storage is selected with the same environment variables as the server.",
+        BOOTSTRAP_PASSWORD, FILE_DIR, BALLOT_LAYOUT
+    );
+}
+
+/// Prints every hundredth entry and the last one of a kind.
+fn print_progress(progress: Progress) {
+    if progress.done % 100 == 0 || progress.done == progress.total {
+        eprintln!(
+            "{}: {}/{}",
+            progress.kind.dir(),
+            progress.done,
+            progress.total
+        );
+    }
+}
+
+fn read_password() -> Option<String> {
+    match std::env::var(BOOTSTRAP_PASSWORD) {
+        Ok(p) => Some(p),
+        Err(_) => {
+            println!("Password for the new admin:");
+            let mut line = String::new();
+            match std::io::stdin().lock().read_line(&mut line) {
+                Ok(_) => Some(line.trim_end().to_string()),
+                Err(_) => None,
+            }
+        }
+    }
+}
+
+#[rocket::main]
+async fn main() {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|a| a.as_str()) {
+        Some("bootstrap") => {
+            let name = match args.get(2) {
+                Some(n) => n,
+                None => {
+                    eprintln!("Provide a name for the admin.");
+                    std::process::exit(1);
+                }
+            };
+            let password = match read_password() {
+                Some(p) => p,
+                None => {
+                    eprintln!("No password provided.");
+                    std::process::exit(1);
+                }
+            };
+            match bootstrap_admin(name, &password).await {
+                Ok(_) => println!("Admin {} created.", name),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("check") => {
+            let rebuild = args.get(2).is_some_and(|a| a == "--rebuild");
+            let reports = match storage() {
+                Ok(storage) => check_storage(storage.as_ref(), rebuild).await,
+                Err(e) => Err(e),
+            };
+            let reports = match reports {
+                Ok(reports) => reports,
+                Err(e) => {
+                    eprintln!("{}", e.to_string());
+                    std::process::exit(1);
+                }
+            };
+            let mut consistent = true;
+            for report in reports {
+                let state = match (report.is_consistent(), report.rebuilt) {
+                    (true, _) => "ok",
+                    (false, true) => "rebuilt",
+                    (false, false) => "inconsistent",
+                };
+                println!("{}: {}", report.kind, state);
+                for (label, ids) in [
+                    ("orphans", &report.orphans),
+                    ("missing", &report.missing),
+                    ("duplicates", &report.duplicates),
+                    ("unparsable", &report.unparsable),
+                ] {
+                    if !ids.is_empty() {
+                        println!("    {}: {}", label, ids.join(", "));
+                    }
+                }
+                consistent &= report.is_consistent() || report.rebuilt;
+                consistent &= report.unparsable.is_empty();
+            }
+            if !consistent {
+                std::process::exit(1);
+            }
+        }
+        Some("migrate") => {
+            let (from, to) = match (args.get(2), args.get(3)) {
+                (Some(from), Some(to)) if from != to => (from, to),
+                _ => {
+                    eprintln!("Provide two different storage modes, e.g. migrate local db.");
+                    std::process::exit(1);
+                }
+            };
+            let dry_run = args.get(4).is_some_and(|a| a == "--dry-run");
+            let migrated = match (backend_of(from).await, backend_of(to).await) {
+                (Ok(source), Ok(target)) => {
+                    migrate(
+                        source.as_ref(),
+                        target.as_ref(),
+                        dry_run,
+                        &mut print_progress,
+                    )
+                    .await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            let reports = match migrated {
+                Ok(reports) => reports,
+                Err(e) => {
+                    eprintln!("{}", e.to_string());
+                    std::process::exit(1);
+                }
+            };
+            let mut verified = true;
+            for report in reports {
+                let state = match (report.is_verified(), dry_run) {
+                    (true, true) => "ready",
+                    (true, false) => "verified",
+                    (false, _) => "failed",
+                };
+                println!(
+                    "{}: {}, {} read, {} {}, {} existing, checksum {} / {}",
+                    report.kind,
+                    state,
+                    report.read,
+                    report.written,
+                    if dry_run { "to write" } else { "written" },
+                    report.existing,
+                    report.source_checksum,
+                    report.target_checksum
+                );
+                for (label, ids) in [
+                    ("differing", &report.differing),
+                    ("unparsable", &report.unparsable),
+                    ("missing", &report.missing),
+                    ("failed", &report.failed),
+                ] {
+                    if !ids.is_empty() {
+                        println!("    {}: {}", label, ids.join(", "));
+                    }
+                }
+                verified &= report.is_verified();
+            }
+            if !verified {
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "local")]
+        Some("migrate-ballots") => {
+            let config = FileConfig::from_env();
+            if !config.journal() {
+                println!(
+                    "{} isn't jsonl, the server won't read the migrated ballots.",
+                    BALLOT_LAYOUT
+                );
+            }
+            match config.storage().with_journal().migrate_ballots().await {
+                Ok(migration) => {
+                    println!("{} ballots migrated.", migration.moved.len());
+                    if !migration.unparsable.is_empty() {
+                        println!("    unparsable: {}", migration.unparsable.join(", "));
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e.to_string());
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => print_help(),
+    }
+}
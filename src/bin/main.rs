@@ -5,19 +5,30 @@ extern crate rocket;
 use rocket::{get, response::Redirect};
 use rocket::{Build, Rocket};
 use voters_verdict::{
+    aggregate::{get_totals, rebuild_results},
+    authentication::{delete_session, post_session},
+    backup::{get_backup, restore_backup},
     ballots::{
         get_ballots_by_candidate, get_ballots_by_voted_on, get_ballots_by_voter,
         get_ballots_by_voting, get_ballots_sorted, post_ballot,
     },
+    cache::get_cache_stats,
     config::FILE_DIR,
+    consistency::{get_index_report, rebuild_index},
     criteria::{get_criterias, get_criterion, post_criterion},
+    operators::{get_operators, modify_operator, post_operator},
     plumping::{
-        internal_server_error, not_found, unauthorized, unprocessable_content, version_handler,
-        CORS,
+        forbidden, internal_server_error, not_found, preflight, too_many_requests, unauthenticated,
+        unauthorized, unprocessable_content, version_handler, CORS,
+    },
+    rate_limit::RateLimit,
+    routes::{
+        API_BALLOTS, API_CRITERIA, API_OPERATORS, API_SESSION, API_STORAGE, API_USERS, API_VOTINGS,
     },
-    routes::{API_BALLOTS, API_CRITERIA, API_USERS, API_VOTINGS},
     users::{get_user, get_users, get_users_by_type, post_user},
-    votes::{close_vote, get_full_vote, get_raw_vote, modify_voting, post_vote},
+    votes::{
+        close_vote, get_full_vote, get_raw_vote, modify_voting, post_vote, rotate_invite_code,
+    },
 };
 
 #[cfg(feature = "templates")]
@@ -31,8 +42,8 @@ use std::collections::HashMap;
 #[cfg(feature = "admin")]
 use voters_verdict::templates::admin::{
     render_admin_manage_panel, render_admin_panel, render_dev_admin_panel,
-    render_voting_admin_panel, render_voting_dev_admin_panel, render_votings_admin_panel,
-    render_votings_dev_admin_panel,
+    render_login_admin_panel, render_operators_admin_panel, render_voting_admin_panel,
+    render_voting_dev_admin_panel, render_votings_admin_panel, render_votings_dev_admin_panel,
 };
 #[cfg(feature = "templates")]
 use voters_verdict::{
@@ -67,6 +78,10 @@ fn format_critera(original_value: &Value, _: &HashMap<String, Value>) -> tera::R
     }
 }
 
+fn build() -> Rocket<Build> {
+    use voters_verdict::persistence::{figment, stage};
+    rocket::custom(figment()).attach(stage())
+}
 #[launch]
 #[cfg(feature = "templates")]
 fn rocket() -> Rocket<Build> {
@@ -78,23 +93,27 @@ fn rocket() -> Rocket<Build> {
     };
     info!("Currently asset directory: {}", file_dir);
     print_file_dir();
-    let rocket = rocket::build()
+    let rocket = build()
         .register(
             "/",
             catchers![
                 unprocessable_content,
                 unauthorized,
+                unauthenticated,
+                forbidden,
+                too_many_requests,
                 not_found,
                 internal_server_error
             ],
         )
         .attach(CORS)
+        .attach(RateLimit)
         .attach(Template::custom(|engines: &mut Engines| {
             engines
                 .tera
                 .register_filter("format_criteria", format_critera);
         }))
-        .mount("/", routes![login])
+        .mount("/", routes![login, preflight])
         .mount("/votings", routes![render_voting_index, render_voting])
         .mount(
             "/ballots",
@@ -115,7 +134,8 @@ fn rocket() -> Rocket<Build> {
                 get_ballots_by_voted_on,
                 get_ballots_sorted,
                 get_ballots_by_voter,
-                get_ballots_by_candidate
+                get_ballots_by_candidate,
+                get_totals
             ],
         )
         .mount(
@@ -126,6 +146,22 @@ fn rocket() -> Rocket<Build> {
             API_USERS,
             routes![get_users, get_users_by_type, get_user, post_user],
         )
+        .mount(
+            API_OPERATORS,
+            routes![get_operators, post_operator, modify_operator],
+        )
+        .mount(API_SESSION, routes![post_session, delete_session])
+        .mount(
+            API_STORAGE,
+            routes![
+                get_index_report,
+                rebuild_index,
+                get_cache_stats,
+                rebuild_results,
+                get_backup,
+                restore_backup
+            ],
+        )
         .mount(
             API_VOTINGS,
             routes![
@@ -133,7 +169,8 @@ fn rocket() -> Rocket<Build> {
                 post_vote,
                 get_full_vote,
                 close_vote,
-                modify_voting
+                modify_voting,
+                rotate_invite_code
             ],
         );
 
@@ -147,7 +184,9 @@ fn build_admin_routes() -> Vec<rocket::Route> {
         render_admin_panel,
         render_admin_manage_panel,
         render_voting_admin_panel,
-        render_votings_admin_panel
+        render_votings_admin_panel,
+        render_operators_admin_panel,
+        render_login_admin_panel
     ];
     match std::env::var(ENVIRONMENT) {
         Ok(e) => match e.as_str() {
@@ -155,7 +194,9 @@ fn build_admin_routes() -> Vec<rocket::Route> {
                 render_dev_admin_panel,
                 render_admin_manage_panel,
                 render_voting_dev_admin_panel,
-                render_votings_dev_admin_panel
+                render_votings_dev_admin_panel,
+                render_operators_admin_panel,
+                render_login_admin_panel
             ],
             _ => productive_admin_routes,
         },
@@ -166,17 +207,22 @@ fn build_admin_routes() -> Vec<rocket::Route> {
 #[cfg(not(feature = "templates"))]
 fn rocket() -> Rocket<Build> {
     print_file_dir();
-    rocket::build()
+    build()
         .register(
             "/",
             catchers![
                 unprocessable_content,
                 unauthorized,
+                unauthenticated,
+                forbidden,
+                too_many_requests,
                 not_found,
                 internal_server_error
             ],
         )
         .attach(CORS)
+        .attach(RateLimit)
+        .mount("/", routes![preflight])
         .mount("/info", routes![version_handler])
         .mount(
             API_BALLOTS,
@@ -186,7 +232,8 @@ fn rocket() -> Rocket<Build> {
                 get_ballots_by_voted_on,
                 get_ballots_sorted,
                 get_ballots_by_voter,
-                get_ballots_by_candidate
+                get_ballots_by_candidate,
+                get_totals
             ],
         )
         .mount(
@@ -197,6 +244,22 @@ fn rocket() -> Rocket<Build> {
             API_USERS,
             routes![get_users, get_users_by_type, get_user, post_user],
         )
+        .mount(
+            API_OPERATORS,
+            routes![get_operators, post_operator, modify_operator],
+        )
+        .mount(API_SESSION, routes![post_session, delete_session])
+        .mount(
+            API_STORAGE,
+            routes![
+                get_index_report,
+                rebuild_index,
+                get_cache_stats,
+                rebuild_results,
+                get_backup,
+                restore_backup
+            ],
+        )
         .mount(
             API_VOTINGS,
             routes![
@@ -204,7 +267,8 @@ fn rocket() -> Rocket<Build> {
                 get_full_vote,
                 post_vote,
                 close_vote,
-                modify_voting
+                modify_voting,
+                rotate_invite_code
             ],
         )
 }
@@ -222,11 +286,11 @@ fn print_file_dir() {
 }
 #[cfg(test)]
 mod test {
-    use super::rocket;
     use rocket::http::Status;
     use rocket::local::blocking::Client;
     use rocket::uri;
     use voters_verdict::{
+        aggregate::CandidateResult,
         ballots::{Table, TableRow},
         common::Criterion,
         config::{FILE_DIR, MANIFEST_DIR},
@@ -238,41 +302,222 @@ mod test {
         }
     }
 
-    fn build_test_client() -> Client {
+    /// The rocket on a database seeded with the fixtures of `test-data/`,
+    /// none without a database to test against.
+    fn rocket() -> Option<rocket::Rocket<rocket::Build>> {
+        #[cfg(feature = "db")]
+        if !prepare_database() {
+            return None;
+        }
+        Some(super::rocket())
+    }
+
+    /// Seeds the database once, false if there is none to test against.
+    #[cfg(feature = "db")]
+    fn prepare_database() -> bool {
+        #[cfg(all(
+            feature = "postgres",
+            not(any(feature = "sqlx_sqlite", feature = "diesel_sqlite"))
+        ))]
+        if std::env::var(voters_verdict::config::POSTGRES_CONNECTION).is_err() {
+            eprintln!(
+                "Skipped without {}.",
+                voters_verdict::config::POSTGRES_CONNECTION
+            );
+            return false;
+        }
+        static SEEDED: std::sync::Once = std::sync::Once::new();
+        SEEDED.call_once(|| {
+            let database = voters_verdict::db::Database::default();
+            #[cfg(any(feature = "sqlx_sqlite", feature = "diesel_sqlite"))]
+            let database_url = {
+                let path = std::env::temp_dir().join(format!(
+                    "voters-verdict-machine-{}.sqlite",
+                    std::process::id()
+                ));
+                let _ = std::fs::remove_file(&path);
+                let database_url = path.to_string_lossy().to_string();
+                std::env::set_var(voters_verdict::config::SQLITE_CONNECTION, &database_url);
+                database_url
+            };
+            // A schema per test run in the database of VOTERS_VERDICT_POSTGRES_URL.
+            #[cfg(all(
+                feature = "postgres",
+                not(any(feature = "sqlx_sqlite", feature = "diesel_sqlite"))
+            ))]
+            let database_url = {
+                let connection = voters_verdict::config::POSTGRES_CONNECTION;
+                let database_url = rocket::async_test(voters_verdict::db::postgres::use_schema(
+                    &std::env::var(connection).expect("The url is checked above."),
+                    &format!("voters_verdict_machine_{}", std::process::id()),
+                    true,
+                ))
+                .expect("The schema is created.");
+                std::env::set_var(connection, &database_url);
+                database_url
+            };
+            rocket::async_test(database.seed(
+                &database_url,
+                include_str!("../../test-data/voters-verdict.sql"),
+            ))
+            .expect("The fixtures are seeded.");
+        });
+        true
+    }
+
+    fn test_client() -> Option<Client> {
+        Some(Client::tracked(rocket()?).expect("valid rocket instance"))
+    }
+
+    fn build_test_client() -> Option<Client> {
         let cargo_manifest_dir = get_env_manifest_dir();
         std::env::set_var(FILE_DIR, cargo_manifest_dir.to_owned() + "/test-data/");
-        Client::tracked(rocket()).expect("valid rocket instance")
+        test_client()
     }
 
     #[cfg(feature = "templates")]
     #[test]
     fn redirect_to_votings() {
-        let client = Client::tracked(rocket()).expect("valid rocket instance");
+        let Some(client) = test_client() else {
+            return;
+        };
         let response = client.get(uri!(super::login)).dispatch();
         assert_eq!(response.status(), Status::SeeOther);
     }
 
+    #[test]
+    fn post_without_csrf_token() {
+        let Some(client) = test_client() else {
+            return;
+        };
+        let response = client
+            .post(uri!("/api/v1/criteria/"))
+            .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+            .header(rocket::http::ContentType::JSON)
+            .body("{}")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn post_with_csrf_token_reaches_authentication() {
+        use voters_verdict::csrf::{CSRF_COOKIE, CSRF_HEADER};
+        let Some(client) = test_client() else {
+            return;
+        };
+        let response = client
+            .post(uri!("/api/v1/criteria/"))
+            .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+            .header(rocket::http::ContentType::JSON)
+            .header(rocket::http::Header::new(CSRF_HEADER, "same"))
+            .cookie(rocket::http::Cookie::new(CSRF_COOKIE, "same"))
+            .body("{}")
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[cfg(feature = "admin")]
+    #[test]
+    fn issues_csrf_cookie_and_accepts_its_token() {
+        use voters_verdict::{
+            authentication::SESSION_COOKIE,
+            config::ADMIN_TOKEN,
+            csrf::{CSRF_COOKIE, CSRF_HEADER},
+        };
+        std::env::set_var(ADMIN_TOKEN, "csrf-test-admin");
+        let Some(client) = build_test_client() else {
+            return;
+        };
+        let response = client.get(uri!("/admin/login")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let cookies: Vec<&str> = response.headers().get("set-cookie").collect();
+        assert!(cookies.iter().any(|c| c.starts_with(CSRF_COOKIE)));
+        assert!(cookies.iter().any(|c| c.starts_with("cv-")));
+        let token = client
+            .cookies()
+            .get(CSRF_COOKIE)
+            .map(|c| c.value().to_string())
+            .expect("The CSRF cookie is kept.");
+
+        let response = client
+            .post(uri!("/api/v1/session"))
+            .header(rocket::http::Header::new(
+                "Authorization",
+                "Bearer csrf-test-admin",
+            ))
+            .header(rocket::http::Header::new(CSRF_HEADER, token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(client.cookies().get(SESSION_COOKIE).is_some());
+    }
+
+    #[test]
+    fn index_report_with_wrong_token() {
+        let Some(client) = build_test_client() else {
+            return;
+        };
+        let response = client
+            .get(uri!("/api/v1/storage/index"))
+            .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn cache_stats_with_wrong_token() {
+        let Some(client) = build_test_client() else {
+            return;
+        };
+        let response = client
+            .get(uri!("/api/v1/storage/cache"))
+            .header(rocket::http::Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn preflight_without_allowed_origin() {
+        let Some(client) = test_client() else {
+            return;
+        };
+        let response = client
+            .options(uri!("/api/v1/ballots/Voting"))
+            .header(rocket::http::Header::new("Origin", "https://evil.org"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        assert!(response
+            .headers()
+            .get_one("Access-Control-Allow-Origin")
+            .is_none());
+    }
+
     #[cfg(feature = "templates")]
     mod rendered_ballots {
         use super::*;
 
         #[test]
         fn get_ballots_by_voting_on() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/ballots/Voting")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
 
         #[test]
         fn get_ballots_by_voting_by_user() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/ballots/Voting/voters/Obama")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
 
         #[test]
         fn get_ballots_by_voting_by_sort_sum() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/ballots/Voting/results?sort=sum"))
                 .dispatch();
@@ -280,7 +525,9 @@ mod test {
         }
         #[test]
         fn get_ballots_by_voting_by_sort_weight() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/ballots/Voting/results?sort=weight"))
                 .dispatch();
@@ -289,7 +536,9 @@ mod test {
 
         #[test]
         fn get_ballots_by_voting_by_candidates() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/ballots/Voting/candidates/John"))
                 .dispatch();
@@ -309,9 +558,25 @@ mod test {
             assert_eq!(table.rows.len(), rows);
             assert_eq!(table.rows.get(0).unwrap().sum, value);
         }
+        fn it_should_have_sorted_candidates(
+            response: rocket::local::blocking::LocalResponse,
+            best: &str,
+            sum: i64,
+        ) {
+            let table = response
+                .into_json::<Table<Criterion, CandidateResult>>()
+                .unwrap();
+            assert_eq!(table.headers.len(), 2);
+            assert_eq!(table.rows.len(), 2);
+            assert_eq!(table.rows[0].candidate, best);
+            assert_eq!(table.rows[0].sum, sum);
+            assert_eq!(table.rows[0].points.len(), 2);
+        }
         #[test]
         fn get_ballots_by_voting_on() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/ballots/Voting")).dispatch();
             assert_eq!(response.status(), Status::Ok);
 
@@ -320,7 +585,9 @@ mod test {
 
         #[test]
         fn get_ballots_by_voting_by_lowercase_user() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/voters/Michelle"))
                 .dispatch();
@@ -329,7 +596,9 @@ mod test {
         }
         #[test]
         fn get_ballots_by_voting_by_uppercase_user() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/voters/michelle"))
                 .dispatch();
@@ -338,28 +607,34 @@ mod test {
         }
         #[test]
         fn get_ballots_by_voting_by_sort_sum() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/results?sort=sum"))
                 .dispatch();
             assert_eq!(response.status(), Status::Ok);
 
-            it_should_have_sorted_entries(response, 3, 12);
+            it_should_have_sorted_candidates(response, "Joe", 26);
         }
         #[test]
         fn get_ballots_by_voting_by_sort_weight() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/results?sort=weight"))
                 .dispatch();
             assert_eq!(response.status(), Status::Ok);
 
-            it_should_have_sorted_entries(response, 3, 12);
+            it_should_have_sorted_candidates(response, "Joe", 26);
         }
 
         #[test]
         fn get_ballots_by_voting_by_lowercase_candidates() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/candidates/doe"))
                 .dispatch();
@@ -371,7 +646,9 @@ mod test {
 
         #[test]
         fn get_ballots_by_voting_by_uppercase_candidates() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client
                 .get(uri!("/api/v1/ballots/Voting/candidates/Doe"))
                 .dispatch();
@@ -385,14 +662,18 @@ mod test {
         use super::*;
         #[test]
         fn get_users() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/users")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
 
         #[test]
         fn get_user() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/users/Obama")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
@@ -402,34 +683,71 @@ mod test {
         use super::*;
         #[test]
         fn get_criterias() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/criteria")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
 
         #[test]
         fn get_criteria() {
-            let client = build_test_client();
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/criteria/Style")).dispatch();
             assert_eq!(response.status(), Status::Ok);
         }
     }
     mod api_votings {
         use super::*;
+        use voters_verdict::config::ADMIN_TOKEN;
+
+        /// The raw votings hold the invite code and need an operator.
+        fn get_as_admin(uri: rocket::http::uri::Origin) -> Option<Status> {
+            std::env::set_var(ADMIN_TOKEN, "csrf-test-admin");
+            let client = build_test_client()?;
+            let response = client
+                .get(uri)
+                .header(rocket::http::Header::new(
+                    "Authorization",
+                    "Bearer csrf-test-admin",
+                ))
+                .dispatch();
+            Some(response.status())
+        }
+
         #[test]
         fn get_raw_vote() {
-            let client = build_test_client();
+            let Some(status) = get_as_admin(uri!("/api/v1/votings/raw/Voting")) else {
+                return;
+            };
+            assert_eq!(status, Status::Ok);
+        }
+
+        #[test]
+        fn get_raw_vote_unauthorized() {
+            let Some(client) = build_test_client() else {
+                return;
+            };
             let response = client.get(uri!("/api/v1/votings/raw/Voting")).dispatch();
-            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.status(), Status::Unauthorized);
+        }
+
+        #[test]
+        fn get_raw_vote_not_found() {
+            let Some(status) = get_as_admin(uri!("/api/v1/votings/raw/Nope")) else {
+                return;
+            };
+            assert_eq!(status, Status::NotFound);
         }
 
         #[test]
         fn get_full_vote() {
-            let client = build_test_client();
-            let response = client
-                .get(uri!("/api/v1/votings/raw/Voting?full"))
-                .dispatch();
-            assert_eq!(response.status(), Status::Ok);
+            let Some(status) = get_as_admin(uri!("/api/v1/votings/raw/Voting?full")) else {
+                return;
+            };
+            assert_eq!(status, Status::Ok);
         }
     }
 }
@@ -0,0 +1,398 @@
+use crate::{
+    authentication::{can, Authorized},
+    ballots::TableRow,
+    config::CACHE_TTL,
+    error::VoteErrorKind,
+    persistence::{Kind, PutMode, Storage, StorageBackend},
+};
+use rocket::{
+    debug, get,
+    serde::{json::Json, Deserialize, Serialize},
+    warn,
+};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// The kinds, which are loaded several times per request.
+const CACHED_KINDS: [Kind; 3] = [Kind::Voting, Kind::Candidate, Kind::Criterion];
+/// The generations are pruned after this many drops.
+const PRUNE_EVERY: u64 = 1024;
+
+/// A parsed entry, one value per type it was loaded as.
+struct Cached {
+    loaded_at: Instant,
+    values: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Keeps parsed votings, candidates and criteria in the process. Entries are
+/// dropped on every write through the storage and after `ttl`, so writes of
+/// other servers on a shared storage show up as well. Without `ttl` nothing
+/// is cached.
+///
+/// A read, which started before a write dropped the entry, may still return
+/// the old entry. So every drop bumps the generation of the entry, and an
+/// entry is only inserted, if its generation didn't change since the read
+/// started. Generations older than `ttl` are folded into `cleared`.
+pub struct EntityCache {
+    entries: RwLock<HashMap<(Kind, String), Cached>>,
+    /// The last drop of an entry, by its lowercase id, and when it happened.
+    generations: RwLock<HashMap<(Kind, String), (u64, Instant)>>,
+    /// Every entry with an older generation is dropped, by the last `clear`
+    /// or by pruning.
+    cleared: AtomicU64,
+    /// Counts every drop, so a generation never repeats.
+    drops: AtomicU64,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl EntityCache {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            generations: RwLock::new(HashMap::new()),
+            cleared: AtomicU64::new(0),
+            drops: AtomicU64::new(0),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+    fn from_env() -> Self {
+        let seconds = match std::env::var(CACHE_TTL) {
+            Ok(v) => v.parse::<u64>().unwrap_or_else(|_| {
+                warn!("{} is not a number, using 60", CACHE_TTL);
+                60
+            }),
+            Err(_) => 60,
+        };
+        EntityCache::new((seconds > 0).then(|| Duration::from_secs(seconds)))
+    }
+    /// A copy of the entry, if it was loaded as `T` before.
+    pub fn get<T: Clone + 'static>(&self, kind: Kind, id: &str) -> Option<T> {
+        let ttl = match self.ttl {
+            Some(ttl) if CACHED_KINDS.contains(&kind) => ttl,
+            _ => return None,
+        };
+        let hit = self.entries.read().ok().and_then(|entries| {
+            let cached = entries.get(&(kind, id.to_string()))?;
+            if cached.loaded_at.elapsed() > ttl {
+                return None;
+            }
+            cached
+                .values
+                .iter()
+                .find_map(|v| v.downcast_ref::<T>())
+                .cloned()
+        });
+        match hit {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        hit
+    }
+    /// Read before loading an entry and passed to `insert`.
+    pub fn generation(&self, kind: Kind, id: &str) -> u64 {
+        let dropped = self
+            .generations
+            .read()
+            .ok()
+            .and_then(|g| g.get(&(kind, id.to_lowercase())).map(|(g, _)| *g))
+            .unwrap_or_default();
+        std::cmp::max(dropped, self.cleared.load(Ordering::SeqCst))
+    }
+    /// Skips the entry, if it was dropped since `generation` was read.
+    pub fn insert<T: Send + Sync + 'static>(
+        &self,
+        kind: Kind,
+        id: &str,
+        generation: u64,
+        value: T,
+    ) {
+        if self.ttl.is_none() || !CACHED_KINDS.contains(&kind) {
+            return;
+        }
+        // The write lock is held while comparing, so a drop can't slip in
+        // between.
+        if let Ok(mut entries) = self.entries.write() {
+            if self.generation(kind, id) != generation {
+                debug!("Not caching {:?} {}, it changed while loading", kind, id);
+                return;
+            }
+            let cached = entries
+                .entry((kind, id.to_string()))
+                .or_insert_with(|| Cached {
+                    loaded_at: Instant::now(),
+                    values: vec![],
+                });
+            cached.values.retain(|v| !v.is::<T>());
+            cached.values.push(Arc::new(value));
+        }
+    }
+    /// Drops `id` in any case, the storages don't agree on case sensitivity.
+    pub fn invalidate(&self, kind: Kind, id: &str) {
+        let ttl = match self.ttl {
+            Some(ttl) if CACHED_KINDS.contains(&kind) => ttl,
+            _ => return,
+        };
+        if let Ok(mut entries) = self.entries.write() {
+            let generation = self.drops.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Ok(mut generations) = self.generations.write() {
+                generations.insert((kind, id.to_lowercase()), (generation, Instant::now()));
+                if generation % PRUNE_EVERY == 0 {
+                    self.prune(&mut generations, ttl);
+                }
+            }
+            entries.retain(|(k, i), _| *k != kind || !i.eq_ignore_ascii_case(id));
+        }
+    }
+    /// Removes the generations older than `ttl`. A read, which started
+    /// before one of them, is skipped like after a `clear`.
+    fn prune(&self, generations: &mut HashMap<(Kind, String), (u64, Instant)>, ttl: Duration) {
+        let mut cleared = self.cleared.load(Ordering::SeqCst);
+        generations.retain(|_, (generation, dropped_at)| {
+            let expired = dropped_at.elapsed() > ttl;
+            if expired {
+                cleared = std::cmp::max(cleared, *generation);
+            }
+            !expired
+        });
+        self.cleared.store(cleared, Ordering::SeqCst);
+    }
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.write() {
+            let generation = self.drops.fetch_add(1, Ordering::SeqCst) + 1;
+            self.cleared.store(generation, Ordering::SeqCst);
+            if let Ok(mut generations) = self.generations.write() {
+                generations.clear();
+            }
+            entries.clear();
+        }
+    }
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.read().map(|e| e.len()).unwrap_or_default(),
+        }
+    }
+}
+
+static CACHE: OnceLock<EntityCache> = OnceLock::new();
+
+/// The cache shared by every backend.
+pub fn cache() -> &'static EntityCache {
+    CACHE.get_or_init(EntityCache::from_env)
+}
+
+/// Wraps the chosen backend and drops cached entries it writes, whichever
+/// backend it is.
+pub struct CachedStorage {
+    inner: Storage,
+}
+
+impl CachedStorage {
+    pub fn new(inner: Storage) -> Self {
+        Self { inner }
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for CachedStorage {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        self.inner.get(kind, id).await
+    }
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let stored = self.inner.put(kind, id, entry, mode).await;
+        debug!("Invalidating {:?} {}", kind, id);
+        cache().invalidate(kind, id);
+        stored
+    }
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        self.inner.list(kind).await
+    }
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let deleted = self.inner.delete(kind, id).await;
+        cache().invalidate(kind, id);
+        deleted
+    }
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        self.inner.index(kind).await
+    }
+    fn keeps_index(&self) -> bool {
+        self.inner.keeps_index()
+    }
+    async fn rebuild_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        self.inner.rebuild_index(kind, ids).await
+    }
+    async fn verify(&self) -> Result<(), VoteErrorKind<'static>> {
+        self.inner.verify().await
+    }
+    async fn find(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        self.inner.find(kind, id).await
+    }
+    async fn results(
+        &self,
+        voting: &str,
+        filter: Option<(&str, &str)>,
+        order_by: &str,
+    ) -> Result<Option<Vec<TableRow>>, VoteErrorKind<'static>> {
+        self.inner.results(voting, filter, order_by).await
+    }
+    fn computes_results(&self) -> bool {
+        self.inner.computes_results()
+    }
+    async fn for_each_ballot(
+        &self,
+        voting: &str,
+        each: &mut (dyn for<'i, 'e> FnMut(&'i str, &'e str) + Send),
+    ) -> Result<bool, VoteErrorKind<'static>> {
+        self.inner.for_each_ballot(voting, each).await
+    }
+}
+
+#[get("/cache")]
+pub async fn get_cache_stats(_authorized: Authorized<can::ManageStorage>) -> Json<CacheStats> {
+    Json(cache().stats())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = EntityCache::new(Some(Duration::from_secs(60)));
+        assert_eq!(cache.get::<String>(Kind::Voting, "voting"), None);
+        cache.insert(Kind::Voting, "voting", 0, String::from("Voting"));
+        cache.insert(Kind::Voting, "voting", 0, 7_u32);
+        assert_eq!(
+            cache.get::<String>(Kind::Voting, "voting"),
+            Some(String::from("Voting"))
+        );
+        assert_eq!(cache.get::<u32>(Kind::Voting, "voting"), Some(7));
+        cache.invalidate(Kind::Voting, "Voting");
+        assert_eq!(cache.get::<u32>(Kind::Voting, "voting"), None);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                entries: 0
+            }
+        );
+
+        cache.insert(Kind::Ballot, "voting-obama-joe", 0, String::from("{}"));
+        assert_eq!(cache.stats().entries, 0);
+        let disabled = EntityCache::new(None);
+        disabled.insert(Kind::Voting, "voting", 0, String::from("Voting"));
+        assert_eq!(disabled.get::<String>(Kind::Voting, "voting"), None);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let cache = EntityCache::new(Some(Duration::from_millis(1)));
+        cache.insert(Kind::Candidate, "candidate_joe", 0, String::from("Joe"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(cache.get::<String>(Kind::Candidate, "candidate_joe"), None);
+    }
+
+    #[test]
+    fn skips_entries_dropped_while_loading() {
+        let cache = EntityCache::new(Some(Duration::from_secs(60)));
+        let generation = cache.generation(Kind::Voting, "voting");
+        cache.invalidate(Kind::Voting, "Voting");
+        cache.insert(Kind::Voting, "voting", generation, String::from("Old"));
+        assert_eq!(cache.get::<String>(Kind::Voting, "voting"), None);
+
+        let generation = cache.generation(Kind::Voting, "voting");
+        cache.insert(Kind::Voting, "voting", generation, String::from("New"));
+        assert_eq!(
+            cache.get::<String>(Kind::Voting, "voting"),
+            Some(String::from("New"))
+        );
+
+        let generation = cache.generation(Kind::Candidate, "candidate_joe");
+        cache.clear();
+        cache.insert(Kind::Candidate, "candidate_joe", generation, 1_u8);
+        assert_eq!(cache.get::<u8>(Kind::Candidate, "candidate_joe"), None);
+    }
+
+    #[test]
+    fn prunes_generations() {
+        let cache = EntityCache::new(Some(Duration::from_millis(1)));
+        cache.invalidate(Kind::Ballot, "voting-obama-joe");
+        assert!(cache.generations.read().unwrap().is_empty());
+
+        let generation = cache.generation(Kind::Voting, "voting");
+        cache.invalidate(Kind::Voting, "voting");
+        std::thread::sleep(Duration::from_millis(5));
+        for i in 1..PRUNE_EVERY {
+            cache.invalidate(Kind::Candidate, &format!("candidate_{}", i));
+        }
+        let generations = cache.generations.read().unwrap();
+        assert!(generations.len() < PRUNE_EVERY as usize);
+        assert!(!generations.contains_key(&(Kind::Voting, String::from("voting"))));
+        drop(generations);
+        cache.insert(Kind::Voting, "voting", generation, String::from("Old"));
+        assert_eq!(cache.get::<String>(Kind::Voting, "voting"), None);
+
+        cache.clear();
+        assert!(cache.generations.read().unwrap().is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn invalidates_on_writes() {
+        let storage = CachedStorage::new(Arc::new(MemoryStorage::new()));
+        let generation = cache().generation(Kind::Criterion, "cached_criterion");
+        cache().insert(Kind::Criterion, "cached_criterion", generation, 1_u8);
+        storage
+            .put(
+                Kind::Criterion,
+                "cached_criterion",
+                String::from("{}"),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache().get::<u8>(Kind::Criterion, "cached_criterion"), None);
+
+        let generation = cache().generation(Kind::Criterion, "cached_criterion");
+        cache().insert(Kind::Criterion, "cached_criterion", generation, 2_u8);
+        storage
+            .delete(Kind::Criterion, "cached_criterion")
+            .await
+            .unwrap();
+        assert_eq!(cache().get::<u8>(Kind::Criterion, "cached_criterion"), None);
+    }
+}
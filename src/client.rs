@@ -0,0 +1,279 @@
+use crate::{
+    config::{
+        REMOTE_CLIENT_CERT, REMOTE_CLIENT_KEY, REMOTE_CONNECT_TIMEOUT, REMOTE_PINNED_CERTS,
+        REMOTE_RETRIES, REMOTE_RETRY_BACKOFF, REMOTE_TIMEOUT, SELF_CERT,
+    },
+    error::VoteErrorKind,
+};
+use reqwest::{header, RequestBuilder, StatusCode};
+use rocket::{error, warn};
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+/// The longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Timeouts, retries and TLS of the client of a storage behind HTTP, the
+/// remote storage or an S3 bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientConfig {
+    /// Limit of a whole request, connecting included.
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// Attempts after the first one, if the storage isn't reachable or
+    /// answers 429 or 5xx.
+    pub retries: u32,
+    /// First wait before another attempt, it doubles with every further one.
+    pub backoff: Duration,
+    /// A DER encoded root certificate, e.g. of a self signed storage.
+    pub self_cert: Option<String>,
+    /// Paths of the PEM certificate and PKCS#8 key the client identifies with.
+    pub identity: Option<(String, String)>,
+    /// SHA-256 fingerprints of the DER certificates the storage may present,
+    /// lowercase hex without colons. Any other certificate is refused during
+    /// the handshake, a pinned one is trusted without `self_cert`.
+    pub pinned_certs: Vec<String>,
+}
+impl ClientConfig {
+    pub fn from_env() -> Result<Self, VoteErrorKind<'static>> {
+        fn read(var_key: &str, default: u64) -> u64 {
+            match std::env::var(var_key) {
+                Ok(v) => v.parse::<u64>().unwrap_or_else(|_| {
+                    warn!("{} is not a number, using {}", var_key, default);
+                    default
+                }),
+                Err(_) => default,
+            }
+        }
+        Ok(Self {
+            timeout: Duration::from_secs(read(REMOTE_TIMEOUT, 10)),
+            connect_timeout: Duration::from_secs(read(REMOTE_CONNECT_TIMEOUT, 5)),
+            retries: read(REMOTE_RETRIES, 2) as u32,
+            backoff: Duration::from_millis(read(REMOTE_RETRY_BACKOFF, 200)),
+            self_cert: std::env::var(SELF_CERT).ok(),
+            identity: match (
+                std::env::var(REMOTE_CLIENT_CERT),
+                std::env::var(REMOTE_CLIENT_KEY),
+            ) {
+                (Ok(cert), Ok(key)) => Some((cert, key)),
+                (Err(_), Err(_)) => None,
+                _ => {
+                    return Err(VoteErrorKind::Internal(format!(
+                        "{} and {} have to be set together.",
+                        REMOTE_CLIENT_CERT, REMOTE_CLIENT_KEY
+                    )))
+                }
+            },
+            pinned_certs: match std::env::var(REMOTE_PINNED_CERTS) {
+                Ok(pins) => ClientConfig::parse_pins(&pins)?,
+                Err(_) => vec![],
+            },
+        })
+    }
+    /// Reads comma separated SHA-256 fingerprints, like `openssl x509
+    /// -fingerprint -sha256` prints them.
+    pub fn parse_pins(pins: &str) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        pins.split(',')
+            .map(|pin| pin.trim().split_once('=').map_or(pin.trim(), |(_, p)| p))
+            .filter(|pin| !pin.is_empty())
+            .map(|pin| {
+                let pin = pin.replace(':', "").to_lowercase();
+                match pin.len() == 64 && pin.chars().all(|c| c.is_ascii_hexdigit()) {
+                    true => Ok(pin),
+                    false => Err(VoteErrorKind::Internal(format!(
+                        "{} isn't a SHA-256 fingerprint.",
+                        pin
+                    ))),
+                }
+            })
+            .collect()
+    }
+    /// A rustls config, which checks the certificate of the storage against
+    /// the pins during the handshake, before a request is sent.
+    fn pinned_tls(&self) -> Result<rustls::ClientConfig, VoteErrorKind<'static>> {
+        let builder = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedCerts(self.pinned_certs.clone())));
+        let (cert, key) = match &self.identity {
+            Some(identity) => identity,
+            None => return Ok(builder.with_no_client_auth()),
+        };
+        let certs = rustls_pemfile::certs(&mut std::fs::read(cert)?.as_slice())?;
+        let key = rustls_pemfile::pkcs8_private_keys(&mut std::fs::read(key)?.as_slice())?
+            .into_iter()
+            .next();
+        match key {
+            Some(key) if !certs.is_empty() => builder
+                .with_client_auth_cert(
+                    certs.into_iter().map(rustls::Certificate).collect(),
+                    rustls::PrivateKey(key),
+                )
+                .map_err(|e| {
+                    error!("{:?}", e);
+                    VoteErrorKind::Internal(String::from(
+                        "Client certificate or key couldn't be loaded, the key has to be PKCS#8.",
+                    ))
+                }),
+            _ => Err(VoteErrorKind::Internal(String::from(
+                "Client certificate or key couldn't be loaded, the key has to be PKCS#8.",
+            ))),
+        }
+    }
+    /// One client for every request, so connections are reused.
+    pub fn build_client(&self) -> Result<reqwest::Client, VoteErrorKind<'static>> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .tcp_keepalive(Duration::from_secs(60));
+        if let Some(cert) = &self.self_cert {
+            let buf = std::fs::read(cert)?;
+            let cert = reqwest::Certificate::from_der(&buf).map_err(|e| {
+                error!("{:?}", e);
+                VoteErrorKind::Internal(String::from("Self signed cert couldn't be loaded."))
+            })?;
+            builder = builder.add_root_certificate(cert);
+        }
+        match &self.identity {
+            Some((cert, key)) if self.pinned_certs.is_empty() => {
+                let identity =
+                    reqwest::Identity::from_pkcs8_pem(&std::fs::read(cert)?, &std::fs::read(key)?)
+                        .map_err(|e| {
+                            error!("{:?}", e);
+                            VoteErrorKind::Internal(String::from(
+                        "Client certificate or key couldn't be loaded, the key has to be PKCS#8.",
+                    ))
+                        })?;
+                builder = builder.identity(identity);
+            }
+            _ => {}
+        }
+        if !self.pinned_certs.is_empty() {
+            builder = builder.use_preconfigured_tls(self.pinned_tls()?);
+        }
+        builder.build().map_err(|e| {
+            error!("{:?}", e);
+            VoteErrorKind::Internal(String::from("Storage client couldn't be built."))
+        })
+    }
+    /// Sends the request and tries again with a growing back-off, while the
+    /// storage isn't reachable or answers 429 or 5xx. A write with
+    /// `If-None-Match` or `If-Match` isn't idempotent, if its first attempt
+    /// was written but the answer got lost, another attempt answers `412`. It
+    /// is only tried again, if it can't have reached the storage: the
+    /// connection failed or the storage answered 429.
+    /// `storage` names the storage in the errors.
+    pub async fn execute(
+        &self,
+        request: RequestBuilder,
+        storage: &str,
+    ) -> Result<reqwest::Response, VoteErrorKind<'static>> {
+        let (client, request) = request.build_split();
+        let mut request = request.map_err(|e| {
+            error!("{:?}", e);
+            VoteErrorKind::Internal(format!("{} storage request is invalid.", storage))
+        })?;
+        let conditional = request.headers().contains_key(header::IF_NONE_MATCH)
+            || request.headers().contains_key(header::IF_MATCH);
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let next = request.try_clone();
+            let answer = client.execute(request).await;
+            let retry = match &answer {
+                Ok(response) => {
+                    response.status() == StatusCode::TOO_MANY_REQUESTS
+                        || (!conditional && response.status().is_server_error())
+                }
+                Err(e) if is_unpinned(e) => false,
+                Err(e) => e.is_connect() || (!conditional && e.is_timeout()),
+            };
+            match next {
+                Some(next) if retry && attempt < self.retries => {
+                    warn!(
+                        "{} storage failed attempt {}, retrying in {:?}.",
+                        storage,
+                        attempt + 1,
+                        backoff
+                    );
+                    rocket::tokio::time::sleep(backoff).await;
+                    backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                    attempt += 1;
+                    request = next;
+                }
+                _ => {
+                    return answer.map_err(|e| {
+                        error!("{:?}", e);
+                        VoteErrorKind::Internal(match e {
+                            e if is_unpinned(&e) => {
+                                format!("{} storage certificate isn't pinned.", storage)
+                            }
+                            e if e.is_timeout() => format!("{} storage timed out.", storage),
+                            _ => format!("{} storage not reachable.", storage),
+                        })
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Trusts exactly the certificates of `ClientConfig::pinned_certs`.
+struct PinnedCerts(Vec<String>);
+impl rustls::client::ServerCertVerifier for PinnedCerts {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        let fingerprint = hex::encode(Sha256::digest(&end_entity.0));
+        if self.0.contains(&fingerprint) {
+            return Ok(rustls::client::ServerCertVerified::assertion());
+        }
+        error!("Storage presented the certificate {}.", fingerprint);
+        Err(rustls::Error::InvalidCertificate(
+            rustls::CertificateError::ApplicationVerificationFailure,
+        ))
+    }
+}
+
+/// Whether the handshake failed, as the certificate of the storage isn't
+/// pinned. The connectors wrap the rustls error in `io::Error`s.
+fn is_unpinned(e: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        let mut inner: &(dyn std::error::Error + 'static) = err;
+        while let Some(wrapped) = inner
+            .downcast_ref::<std::io::Error>()
+            .and_then(|io| io.get_ref())
+        {
+            inner = wrapped;
+        }
+        if inner.is::<rustls::Error>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_pins() {
+        let pins = ClientConfig::parse_pins(
+            "sha256 Fingerprint=D0:B1:A2:D6:FE:C9:AF:8E:24:BB:5A:A2:9F:91:D5:0B:F7:71:15:CF:0F:B0:FE:98:45:FC:2B:78:E2:DE:40:17, ",
+        )
+        .unwrap();
+        assert_eq!(
+            pins,
+            vec!["d0b1a2d6fec9af8e24bb5aa29f91d50bf77115cf0fb0fe9845fc2b78e2de4017"]
+        );
+        assert!(ClientConfig::parse_pins("d0b1").is_err());
+    }
+}
@@ -1,7 +1,16 @@
-use crate::{persistence::ToPersistence, serialize::FromStorage};
+use crate::{
+    authentication::UserRole, error::VoteErrorKind, persistence::ToPersistence,
+    serialize::FromStorage,
+};
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
 use chrono::{prelude::*, DateTime};
-#[cfg(feature = "diesel_sqlite")]
-use diesel::prelude::*;
+use regex::Regex;
 use rocket::{
     debug, error,
     serde::{json::Json, Deserialize, Serialize},
@@ -49,11 +58,91 @@ pub trait Selfaware {
     fn get_name(&self) -> String;
 }
 
+/// A typed column value. It is bound as parameter and never spliced into SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbValue {
+    Text(String),
+    Integer(i64),
+    Real(f64),
+    Bool(bool),
+    Null,
+}
+impl DbValue {
+    /// Goes through the string representation, so `0.3f32` stays `0.3`.
+    pub fn from_f32(value: f32) -> Self {
+        DbValue::Real(value.to_string().parse::<f64>().unwrap_or_default())
+    }
+}
+impl std::fmt::Display for DbValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbValue::Text(t) => write!(f, "{}", t),
+            DbValue::Integer(i) => write!(f, "{}", i),
+            DbValue::Real(r) => write!(f, "{}", r),
+            DbValue::Bool(b) => write!(f, "{}", b),
+            DbValue::Null => Ok(()),
+        }
+    }
+}
+
+/// The columns of a row by name, typed as the backend reads them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbRow(pub Vec<(String, DbValue)>);
+impl DbRow {
+    pub fn get(&self, column: &str) -> Option<&DbValue> {
+        self.0
+            .iter()
+            .find(|(name, _)| name == column)
+            .map(|(_, value)| value)
+    }
+    /// The value as text, empty for `NULL` or a missing column.
+    pub fn text(&self, column: &str) -> String {
+        self.get(column).map(DbValue::to_string).unwrap_or_default()
+    }
+    pub fn integer(&self, column: &str) -> Result<i64, crate::error::FromErrorKind> {
+        match self.get(column) {
+            Some(DbValue::Integer(i)) => Ok(*i),
+            Some(DbValue::Text(t)) => t.trim().parse().map_err(|_| not_a(column, "number")),
+            _ => Err(not_a(column, "number")),
+        }
+    }
+    pub fn real(&self, column: &str) -> Result<f64, crate::error::FromErrorKind> {
+        match self.get(column) {
+            Some(DbValue::Real(r)) => Ok(*r),
+            Some(DbValue::Integer(i)) => Ok(*i as f64),
+            Some(DbValue::Text(t)) => t.trim().parse().map_err(|_| not_a(column, "number")),
+            _ => Err(not_a(column, "number")),
+        }
+    }
+    /// SQLite has no booleans, they are read back as integers.
+    pub fn bool(&self, column: &str) -> bool {
+        match self.get(column) {
+            Some(DbValue::Bool(b)) => *b,
+            Some(DbValue::Integer(i)) => *i != 0,
+            Some(DbValue::Text(t)) => t == "true" || t == "1",
+            _ => false,
+        }
+    }
+    pub fn timestamp(&self, column: &str) -> Result<DateTime<Utc>, crate::error::FromErrorKind> {
+        self.text(column)
+            .parse::<DateTime<Utc>>()
+            .map_err(|_| not_a(column, "timestamp"))
+    }
+}
+fn not_a(column: &str, kind: &str) -> crate::error::FromErrorKind {
+    crate::error::FromErrorKind::Serialize(format!("Column {} isn't a {}.", column, kind))
+}
+/// Reads a type out of a row of its table.
+pub trait FromDbRow: Sized {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind>;
+}
+
 pub trait Table {
     fn get_identity_column_name() -> String;
     fn get_table(insert: bool) -> String;
     fn get_db_columns() -> String;
-    fn to_db_row(&self) -> String;
+    /// One value per column of `get_db_columns`, in the same order.
+    fn to_db_values(&self) -> Vec<DbValue>;
 }
 pub trait QueryableExt: IdGenerator + Table + Empty + Selfaware {}
 #[rocket::async_trait]
@@ -184,18 +273,7 @@ impl From<Criterion> for VoteKind {
 pub struct Votings {
     pub votings: Vec<Voting>,
 }
-#[cfg(feature = "sqlx_sqlite")]
-impl Votings {
-    fn values(&self) -> String {
-        self.votings
-            .to_vec()
-            .iter()
-            .map(|v| v.values())
-            .reduce(|acc, e| acc + &e)
-            .unwrap()
-    }
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for Votings {
     fn get_identity_column_name() -> String {
         Voting::get_identity_column_name()
@@ -206,18 +284,9 @@ impl Table for Votings {
     fn get_db_columns() -> String {
         Voting::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-impl std::str::FromStr for Votings {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let _result: Vec<&str> = v.split(",-o-,").collect();
-        Ok(Self {
-            votings: vec![], //TODO
-        })
+    /// A collection has no single row, every voting is stored on its own.
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![]
     }
 }
 impl ToJson for Votings {
@@ -304,10 +373,46 @@ pub struct CreateVoting {
     pub candidates: Vec<String>,
     pub criterias: Vec<String>,
     pub styles: Option<VotingStyles>,
+    /// Left empty, the server generates one with `generate_invite_code`.
+    #[serde(default)]
     pub invite_code: String,
 }
 #[derive(Clone, Debug, Serialize, PartialEq, Deserialize)]
 #[serde(crate = "rocket::serde")]
+pub struct InviteCodeRotation {
+    pub rotated_at: DateTime<Utc>,
+    pub rotated_by: String,
+}
+const INVITE_CODE_LENGTH: usize = 16;
+const INVITE_CODE_ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+/// Whether an invite code chosen by an operator is as long as a generated one,
+/// starts with a letter and only holds letters and digits.
+pub fn is_valid_invite_code(code: &str) -> bool {
+    code.len() >= INVITE_CODE_LENGTH
+        && code.starts_with(|c: char| c.is_ascii_alphabetic())
+        && code.bytes().all(|b| INVITE_CODE_ALPHABET.contains(&b))
+}
+/// 16 characters out of 62 give roughly 95 bits of entropy. The first one
+/// is always a letter, like `is_valid_invite_code` requires.
+pub fn generate_invite_code() -> String {
+    let mut code = String::with_capacity(INVITE_CODE_LENGTH);
+    while code.len() < INVITE_CODE_LENGTH {
+        // Rejection sampling keeps the distribution uniform.
+        let byte = (OsRng.next_u32() & 0xff) as usize;
+        if byte >= 248 {
+            continue;
+        }
+        let c = INVITE_CODE_ALPHABET[byte % INVITE_CODE_ALPHABET.len()] as char;
+        if code.is_empty() && !c.is_ascii_alphabetic() {
+            continue;
+        }
+        code.push(c);
+    }
+    code
+}
+#[derive(Clone, Debug, Serialize, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub struct Voting {
     pub name: String,
     pub expires_at: Option<DateTime<Utc>>,
@@ -316,27 +421,39 @@ pub struct Voting {
     pub categories: Vec<Criterion>,
     pub styles: VotingStyles,
     pub invite_code: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub invite_code_rotations: Vec<InviteCodeRotation>,
+}
+impl Voting {
+    /// Replaces the invite code, which invalidates the old one immediately.
+    /// Only the time and the operator are kept in the history, never the code.
+    pub fn rotate_invite_code(&mut self, rotated_by: &str) -> String {
+        self.invite_code = generate_invite_code();
+        self.invite_code_rotations.push(InviteCodeRotation {
+            rotated_at: Utc::now(),
+            rotated_by: rotated_by.to_string(),
+        });
+        self.invite_code.clone()
+    }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Voting {
     fn properties(in_parenthesis: bool) -> String {
         if in_parenthesis {
             String::from(
-                "( name, expires_at, created_at, candidates, categories, styles, invite_code )",
+                "( name, expires_at, created_at, candidates, categories, styles, invite_code, owner, invite_code_rotations )",
             )
         } else {
             String::from(
-                "name, expires_at, created_at, candidates, categories, styles, invite_code",
+                "name, expires_at, created_at, candidates, categories, styles, invite_code, owner, invite_code_rotations",
             )
         }
     }
-    fn values(&self) -> String {
-        debug!("Voting values");
-        VotingTable::from(self).values()
-    }
 }
 
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for Voting {
     fn get_identity_column_name() -> String {
         String::from("name")
@@ -347,8 +464,8 @@ impl Table for Voting {
     fn get_db_columns() -> String {
         Voting::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
+    fn to_db_values(&self) -> Vec<DbValue> {
+        VotingTable::from(self).to_db_values()
     }
 }
 #[cfg(feature = "db")]
@@ -360,8 +477,7 @@ impl From<Vec<VotingTable>> for Votings {
     }
 }
 
-// #[cfg(not(feature = "diesel_sqlite"))]
-// #[derive(Debug, Serialize, PartialEq, Deserialize)]
+// // #[derive(Debug, Serialize, PartialEq, Deserialize)]
 // #[serde(crate = "rocket::serde")]
 // pub struct VotingTable {
 //     pub name: String,
@@ -372,7 +488,7 @@ impl From<Vec<VotingTable>> for Votings {
 //     pub styles: String,
 //     pub invite_code: String,
 // }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl VotingTable {
     fn get_name(&self) -> String {
         self.name.to_lowercase().to_owned()
@@ -380,23 +496,8 @@ impl VotingTable {
     fn properties(in_parenthesis: bool) -> String {
         Voting::properties(in_parenthesis)
     }
-    fn values(&self) -> String {
-        debug!("VotingTable values");
-        vec![
-            "\"".to_owned() + &self.name.clone() + "\"",
-            self.expires_at.to_string(),
-            self.created_at.to_string(),
-            self.candidates.clone(),
-            self.categories.clone(),
-            self.styles.clone(),
-            self.invite_code.clone(),
-        ]
-        .into_iter()
-        .reduce(|acc, e| acc + ", '" + &e + "'")
-        .unwrap()
-    }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 #[derive(Debug, Serialize, PartialEq, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct VotingTable {
@@ -407,22 +508,11 @@ pub struct VotingTable {
     pub categories: String,
     pub styles: String,
     pub invite_code: String,
+    pub owner: String,
+    pub invite_code_rotations: String,
 }
 
-#[cfg(feature = "diesel_sqlite")]
-#[derive(Debug, Serialize, PartialEq, Deserialize, Queryable, Selectable, Insertable)]
-#[diesel(table_name = votings )]
-#[serde(crate = "rocket::serde")]
-pub struct VotingTable {
-    pub name: String,
-    pub expires_at: DateTime<Utc>,
-    pub created_at: DateTime<Utc>,
-    pub candidates: String,
-    pub categories: String,
-    pub styles: String,
-    pub invite_code: String,
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for VotingTable {
     fn get_identity_column_name() -> String {
         String::from("name")
@@ -433,60 +523,66 @@ impl Table for VotingTable {
     fn get_db_columns() -> String {
         Voting::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-
-impl std::str::FromStr for Voting {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let result: Vec<&str> = v.split(",-o-,").collect();
-        if result.len() >= 7 {
-            debug!("We got all 7 rows back!:");
-            Ok(Self {
-                name: from_optional_str(result.get(0).copied()),
-                expires_at: Some(DateTime::from_str(result.get(1).copied().unwrap()).unwrap()),
-                created_at: Some(DateTime::from_str(result.get(2).copied().unwrap()).unwrap()),
-                candidates: match rocket::serde::json::from_str::<Vec<Candidate>>(
-                    &from_optional_str(result.get(3).copied()),
-                ) {
-                    Ok(stringified) => stringified,
-                    Err(e) => {
-                        error!("{:?}", e);
-                        vec![]
-                    }
-                },
-                categories: match rocket::serde::json::from_str::<Vec<Criterion>>(
-                    &from_optional_str(result.get(4).copied()),
-                ) {
-                    Ok(stringified) => stringified,
-                    Err(e) => {
-                        error!("{:?}", e);
-                        vec![]
-                    }
-                },
-                styles: match rocket::serde::json::from_str::<VotingStyles>(&from_optional_str(
-                    result.get(5).copied(),
-                )) {
-                    Ok(stringified) => stringified,
-                    Err(e) => {
-                        error!("{:?}", e);
-                        VotingStyles::default()
-                    }
-                },
-                invite_code: from_optional_str(result.get(6).copied()),
-            })
-        } else {
-            debug!("Nope, sth didnt wor.");
-            Err(crate::error::FromErrorKind::Serialize(String::from(
-                "Voting serialize failed.",
-            )))
-        }
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Text(self.name.clone()),
+            DbValue::Text(self.expires_at.to_string()),
+            DbValue::Text(self.created_at.to_string()),
+            DbValue::Text(self.candidates.clone()),
+            DbValue::Text(self.categories.clone()),
+            DbValue::Text(self.styles.clone()),
+            DbValue::Text(self.invite_code.clone()),
+            DbValue::Text(self.owner.clone()),
+            DbValue::Text(self.invite_code_rotations.clone()),
+        ]
+    }
+}
+
+#[cfg(feature = "db")]
+impl FromDbRow for Voting {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
+        Ok(Self {
+            name: row.text("name"),
+            expires_at: Some(row.timestamp("expires_at")?),
+            created_at: Some(row.timestamp("created_at")?),
+            candidates: match rocket::serde::json::from_str::<Vec<Candidate>>(
+                &row.text("candidates"),
+            ) {
+                Ok(stringified) => stringified,
+                Err(e) => {
+                    error!("{:?}", e);
+                    vec![]
+                }
+            },
+            categories: match rocket::serde::json::from_str::<Vec<Criterion>>(
+                &row.text("categories"),
+            ) {
+                Ok(stringified) => stringified,
+                Err(e) => {
+                    error!("{:?}", e);
+                    vec![]
+                }
+            },
+            styles: match rocket::serde::json::from_str::<VotingStyles>(&row.text("styles")) {
+                Ok(stringified) => stringified,
+                Err(e) => {
+                    error!("{:?}", e);
+                    VotingStyles::default()
+                }
+            },
+            invite_code: row.text("invite_code"),
+            owner: match row.text("owner") {
+                o if o.is_empty() => None,
+                o => Some(o),
+            },
+            invite_code_rotations: rocket::serde::json::from_str::<Vec<InviteCodeRotation>>(
+                &row.text("invite_code_rotations"),
+            )
+            .unwrap_or_default(),
+        })
     }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl From<&VotingTable> for Voting {
     fn from(v: &VotingTable) -> Self {
         Self {
@@ -515,10 +611,18 @@ impl From<&VotingTable> for Voting {
                 }
             },
             invite_code: String::from(&v.invite_code),
+            owner: match v.owner.is_empty() {
+                true => None,
+                false => Some(v.owner.clone()),
+            },
+            invite_code_rotations: rocket::serde::json::from_str::<Vec<InviteCodeRotation>>(
+                &v.invite_code_rotations,
+            )
+            .unwrap_or_default(),
         }
     }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl From<VotingTable> for Voting {
     fn from(v: VotingTable) -> Self {
         Self {
@@ -547,22 +651,18 @@ impl From<VotingTable> for Voting {
                 }
             },
             invite_code: v.invite_code,
+            owner: match v.owner.is_empty() {
+                true => None,
+                false => Some(v.owner),
+            },
+            invite_code_rotations: rocket::serde::json::from_str::<Vec<InviteCodeRotation>>(
+                &v.invite_code_rotations,
+            )
+            .unwrap_or_default(),
         }
     }
 }
-#[cfg(feature = "diesel_sqlite")]
-table! {
-    votings (name) {
-        name -> Text,
-        expires_at -> TimestamptzSqlite,
-        created_at -> TimestamptzSqlite,
-        candidates -> Text,
-        categories -> Text,
-        styles -> Text,
-        invite_code -> Text,
-    }
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl From<&Voting> for VotingTable {
     fn from(v: &Voting) -> Self {
         Self {
@@ -591,6 +691,14 @@ impl From<&Voting> for VotingTable {
                 }
             },
             invite_code: v.invite_code.clone(),
+            owner: v.owner.clone().unwrap_or_default(),
+            invite_code_rotations: match rocket::serde::json::to_string(&v.invite_code_rotations) {
+                Ok(stringified) => stringified,
+                Err(e) => {
+                    error!("{:?}", e);
+                    String::from("[]")
+                }
+            },
         }
     }
 }
@@ -604,6 +712,8 @@ impl Voting {
             categories: vec![],
             styles: VotingStyles::default(),
             invite_code: String::from("access"),
+            owner: None,
+            invite_code_rotations: vec![],
         }
     }
 }
@@ -624,6 +734,8 @@ impl Empty for Voting {
             categories: vec![],
             styles: VotingStyles::default(),
             invite_code: String::from("access"),
+            owner: None,
+            invite_code_rotations: vec![],
         }
     }
 }
@@ -640,7 +752,6 @@ impl Fill for Voting {}
 #[serde(crate = "rocket::serde")]
 pub struct NonVoter<'r>(&'r str);
 
-#[cfg(not(feature = "diesel_sqlite"))]
 #[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct Candidate {
@@ -657,43 +768,18 @@ impl Candidate {
             String::from("voter, id, label")
         }
     }
-    fn values(&self) -> String {
-        vec![
-            match self.voter {
-                true => String::from("true"),
-                false => String::from("false"),
-            },
-            "\"".to_owned() + &self.get_id() + "\"",
-            "\"".to_owned() + &self.label.clone() + "\"",
-        ]
-        .into_iter()
-        .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-        .unwrap()
-    }
 }
-impl std::str::FromStr for Candidate {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let result: Vec<&str> = v.split(",-o-,").collect();
+#[cfg(feature = "db")]
+impl FromDbRow for Candidate {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
         Ok(Self {
-            voter: true, // TODO from_optional_str(result.get(0).copied()),
-            id: Some(from_optional_str(result.get(1).copied())),
-            label: from_optional_str(result.get(2).copied()),
+            voter: row.bool("voter"),
+            id: Some(row.text("id")),
+            label: row.text("label"),
         })
     }
 }
-#[cfg(feature = "diesel_sqlite")]
-#[derive(Debug, Clone, Serialize, PartialEq, Deserialize, Queryable, Insertable)]
-#[diesel(table_name = candidates)]
-#[serde(crate = "rocket::serde")]
-pub struct Candidate {
-    pub voter: bool,
-    pub id: Option<String>,
-    pub label: String,
-}
-
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for Candidate {
     fn get_identity_column_name() -> String {
         String::from("id")
@@ -708,16 +794,12 @@ impl Table for Candidate {
     fn get_db_columns() -> String {
         Candidate::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-#[cfg(feature = "diesel_sqlite")]
-table! {
-    candidates (id) {
-        id -> Nullable<Text>,
-        label -> Text,
-        voter -> Bool,
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Bool(self.voter),
+            DbValue::Text(self.get_id()),
+            DbValue::Text(self.label.clone()),
+        ]
     }
 }
 impl Candidate {
@@ -866,6 +948,151 @@ impl Empty for Candidates {
     }
 }
 
+/////////////////////////////////////////////
+//                                         //
+//               OPERATOR                  //
+//                                         //
+/////////////////////////////////////////////
+
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Operator {
+    pub name: String,
+    pub password_hash: String,
+    pub role: UserRole,
+}
+
+impl Operator {
+    pub fn new(name: &str, password: &str, role: UserRole) -> Result<Self, VoteErrorKind<'static>> {
+        Ok(Self {
+            name: name.to_lowercase(),
+            password_hash: Operator::hash_password(password)?,
+            role,
+        })
+    }
+    pub fn is_valid_name(name: &str) -> bool {
+        let re = Regex::new(r"^[a-z0-9\-]{3,32}$").unwrap();
+        re.is_match(name)
+    }
+    pub fn set_password(&mut self, password: &str) -> Result<(), VoteErrorKind<'static>> {
+        self.password_hash = Operator::hash_password(password)?;
+        Ok(())
+    }
+    /// Takes as long as `verify` of a stored operator, so the time of a failed
+    /// login doesn't tell whether the operator exists.
+    pub fn verify_unknown(password: &str) {
+        static DUMMY: std::sync::OnceLock<Option<String>> = std::sync::OnceLock::new();
+        let dummy = DUMMY.get_or_init(|| {
+            let mut bytes = [0u8; 16];
+            OsRng.fill_bytes(&mut bytes);
+            Operator::hash_password(&format!("{:x?}", bytes)).ok()
+        });
+        if let Some(password_hash) = dummy {
+            let unknown = Operator {
+                name: String::new(),
+                password_hash: password_hash.clone(),
+                role: UserRole::Maintainer,
+            };
+            unknown.verify(password);
+        }
+    }
+    pub fn verify(&self, password: &str) -> bool {
+        match PasswordHash::new(&self.password_hash) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+    fn hash_password(password: &str) -> Result<String, VoteErrorKind<'static>> {
+        if password.len() < 8 {
+            return Err(VoteErrorKind::Conflict(String::from(
+                "Password needs at least 8 characters.",
+            )));
+        }
+        let salt = SaltString::generate(&mut OsRng);
+        match Argon2::default().hash_password(password.as_bytes(), &salt) {
+            Ok(hash) => Ok(hash.to_string()),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(VoteErrorKind::Internal(String::from("Hashing failed.")))
+            }
+        }
+    }
+    #[cfg(feature = "db")]
+    fn properties(in_parenthesis: bool) -> String {
+        if in_parenthesis {
+            String::from("( name, password_hash, role )")
+        } else {
+            String::from("name, password_hash, role")
+        }
+    }
+}
+#[cfg(feature = "db")]
+impl Table for Operator {
+    fn get_identity_column_name() -> String {
+        String::from("name")
+    }
+    fn get_table(insert: bool) -> String {
+        if insert {
+            String::from("operators ") + &Operator::properties(true)
+        } else {
+            String::from("operators")
+        }
+    }
+    fn get_db_columns() -> String {
+        Operator::properties(false)
+    }
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Text(self.name.clone()),
+            DbValue::Text(self.password_hash.clone()),
+            DbValue::Text(self.role.as_str().to_string()),
+        ]
+    }
+}
+#[cfg(feature = "db")]
+impl FromDbRow for Operator {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
+        Ok(Self {
+            name: row.text("name"),
+            password_hash: row.text("password_hash"),
+            role: row.text("role").parse::<UserRole>()?,
+        })
+    }
+}
+
+impl Empty for Operator {
+    fn empty() -> Self {
+        Self {
+            name: String::new(),
+            password_hash: String::new(),
+            role: UserRole::Maintainer,
+        }
+    }
+}
+
+impl IdGenerator for Operator {
+    fn get_id(&self) -> String {
+        self.generate_id()
+    }
+    fn generate_id(&self) -> String {
+        self.name.to_lowercase()
+    }
+}
+
+impl Selfaware for Operator {
+    fn get_type(&self) -> String {
+        String::from("Operator")
+    }
+
+    fn get_name(&self) -> String {
+        self.name.to_lowercase()
+    }
+}
+
+impl Fill for Operator {}
+
 /////////////////////////////////////////////
 //                                         //
 //            CAST BALLOT                  //
@@ -878,23 +1105,9 @@ pub struct CastBallots {
     pub voting: Option<String>,
     pub ballots: Vec<KnownBallots>,
 }
-#[cfg(feature = "diesel_sqlite")]
-table! {
-    ballots (voter) {
-        voter -> Text,
-        human_identifier -> Text,
-        candidate -> Text,
-        sum -> SmallInt,
-        weighted -> Float,
-        mean -> Float,
-        notes -> Text,
-        votes -> Text,
-        voted_on -> TimestamptzSqlite,
-    }
-}
 impl CastBallots {
     pub fn properties(in_parenthesis: bool) -> String {
-        let raw = "human_identifier, candidate, voter, sum, weighted, mean, notes, votes, voted_on";
+        let raw = "human_identifier, candidate, voter, sum, weighted, mean, notes, votes, voted_on, voting";
         if in_parenthesis {
             "( ".to_owned() + raw + " )"
         } else {
@@ -903,7 +1116,7 @@ impl CastBallots {
     }
 }
 
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for CastBallots {
     fn get_identity_column_name() -> String {
         String::from("human_identifier")
@@ -918,20 +1131,10 @@ impl Table for CastBallots {
     fn get_db_columns() -> String {
         CastBallots::properties(false)
     }
-    fn to_db_row(&self) -> String {
+    /// Ballots are stored as `BallotsTable` rows.
+    fn to_db_values(&self) -> Vec<DbValue> {
         error!("NOT IMPLEMENTED");
-        String::new()
-    }
-}
-impl std::str::FromStr for CastBallots {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let result: Vec<&str> = v.split(",-o-,").collect();
-        Ok(Self {
-            voting: Some(from_optional_str(result.get(0).copied())),
-            ballots: vec![], // TODO result.get(0).unwrap()
-        })
+        vec![]
     }
 }
 impl ToJson for CastBallots {
@@ -1100,7 +1303,6 @@ impl Vote {
             .unwrap()
     }
 }
-#[cfg(not(feature = "diesel_sqlite"))]
 #[derive(Serialize, Clone, Debug, PartialEq, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct VoteTable {
@@ -1114,25 +1316,8 @@ impl VoteTable {
     fn properties(in_parenthesis: bool) -> String {
         Vote::properties(in_parenthesis)
     }
-    fn values(&self) -> String {
-        vec![
-            "\"".to_owned() + &self.name.clone() + "\"",
-            self.point.to_string(),
-        ]
-        .into_iter()
-        .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-        .unwrap()
-    }
 }
-#[cfg(feature = "diesel_sqlite")]
-#[derive(Serialize, Clone, Debug, PartialEq, Deserialize, Queryable, Insertable)]
-#[diesel(table_name = votes)]
-#[serde(crate = "rocket::serde")]
-pub struct VoteTable {
-    pub name: String,
-    pub point: i16,
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for VoteTable {
     fn get_identity_column_name() -> String {
         VoteTable::get_identity_column_name()
@@ -1143,15 +1328,11 @@ impl Table for VoteTable {
     fn get_db_columns() -> String {
         VoteTable::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-#[cfg(feature = "diesel_sqlite")]
-table! {
-    votes (name) {
-        name -> Text,
-        point -> SmallInt
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Text(self.name.clone()),
+            DbValue::Integer(i64::from(self.point)),
+        ]
     }
 }
 impl From<Vote> for VoteTable {
@@ -1192,17 +1373,9 @@ impl Criteria {
     fn properties(in_parenthesis: bool) -> String {
         Criterion::properties(in_parenthesis)
     }
-    #[cfg(feature = "sqlx_sqlite")]
-    fn values(&self) -> String {
-        self.criterias
-            .iter()
-            .map(|c| c.to_db_row())
-            .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-            .unwrap()
-    }
 }
 
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for Criteria {
     fn get_identity_column_name() -> String {
         String::from("name")
@@ -1213,18 +1386,9 @@ impl Table for Criteria {
     fn get_db_columns() -> String {
         Criterion::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-impl std::str::FromStr for Criteria {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let _result: Vec<&str> = v.split(",-o-,").collect();
-        Ok(Self {
-            criterias: vec![], // TODO result.get(0).unwrap()
-        })
+    /// A collection has no single row, every criterion is stored on its own.
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![]
     }
 }
 impl ToJson for Criteria {
@@ -1272,22 +1436,8 @@ impl Criterion {
             String::from("name, min, max, weight")
         }
     }
-    fn values(&self) -> String {
-        vec![
-            "\"".to_owned() + &self.name.clone() + "\"",
-            self.min.to_string(),
-            self.max.to_string(),
-            match self.weight {
-                Some(w) => w.to_string(),
-                None => String::from("1.0"),
-            },
-        ]
-        .into_iter()
-        .reduce(|acc, e| acc + ", " + &e)
-        .unwrap()
-    }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for Criterion {
     fn get_identity_column_name() -> String {
         String::from("name")
@@ -1302,34 +1452,31 @@ impl Table for Criterion {
     fn get_db_columns() -> String {
         Criterion::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![
+            DbValue::Text(self.name.clone()),
+            DbValue::Integer(i64::from(self.min)),
+            DbValue::Integer(i64::from(self.max)),
+            DbValue::from_f32(self.weight.unwrap_or(1.0)),
+        ]
     }
 }
 
-impl std::str::FromStr for Criterion {
-    type Err = crate::error::FromErrorKind;
-
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("Raw criterion str: {:?}", v);
-        let result: Vec<&str> = v.split(DELIMITER).collect();
-        debug!("Split: {:?}", result);
+#[cfg(feature = "db")]
+impl FromDbRow for Criterion {
+    fn from_db_row(row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
+        let points = |column| {
+            i8::try_from(row.integer(column)?)
+                .map_err(|_| not_a(column, "point between -128 and 127"))
+        };
         Ok(Self {
-            name: from_optional_str(Some(result.get(0).expect("This index exist.").trim())),
-            min: i8::from_str(&from_optional_str(Some(
-                result.get(1).expect("This index exist.").trim(),
-            )))
-            .unwrap(),
-            max: i8::from_str(&from_optional_str(Some(
-                result.get(2).expect("This index exist.").trim(),
-            )))
-            .unwrap(),
-            weight: Some(
-                f32::from_str(&from_optional_str(Some(
-                    result.get(3).expect("This index exist.").trim(),
-                )))
-                .unwrap(),
-            ),
+            name: row.text("name"),
+            min: points("min")?,
+            max: points("max")?,
+            weight: match row.get("weight") {
+                None | Some(DbValue::Null) => None,
+                Some(_) => Some(row.real("weight")? as f32),
+            },
         })
     }
 }
@@ -1344,7 +1491,6 @@ impl From<&CriterionRow> for Criterion {
         }
     }
 }
-#[cfg(not(feature = "diesel_sqlite"))]
 #[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct CriterionRow {
@@ -1353,22 +1499,7 @@ pub struct CriterionRow {
     pub max: i16,
     pub weight: Option<f32>,
 }
-impl CriterionRow {
-    fn values(&self) -> String {
-        Criterion::from(self).values()
-    }
-}
-#[cfg(feature = "diesel_sqlite")]
-#[derive(Debug, Clone, Serialize, PartialEq, Deserialize, Queryable, Insertable)]
-#[diesel(table_name = criteria)]
-#[serde(crate = "rocket::serde")]
-pub struct CriterionRow {
-    pub name: String,
-    pub min: i16,
-    pub max: i16,
-    pub weight: Option<f32>,
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for CriterionRow {
     fn get_identity_column_name() -> String {
         Criterion::get_identity_column_name()
@@ -1379,17 +1510,8 @@ impl Table for CriterionRow {
     fn get_db_columns() -> String {
         Criterion::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
-    }
-}
-#[cfg(feature = "diesel_sqlite")]
-table! {
-    criteria (name) {
-        name -> Text,
-        min -> SmallInt,
-        max -> SmallInt,
-        weight -> Nullable<Float>,
+    fn to_db_values(&self) -> Vec<DbValue> {
+        Criterion::from(self).to_db_values()
     }
 }
 
@@ -1478,17 +1600,7 @@ pub async fn get_users_internal() -> Users {
 pub struct EmojiCategories {
     pub emojis: Vec<EmojiCategory>,
 }
-impl EmojiCategories {
-    fn values(&self) -> String {
-        self.emojis
-            .to_vec()
-            .iter()
-            .map(|e| e.values())
-            .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-            .unwrap()
-    }
-}
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(feature = "db")]
 impl Table for EmojiCategories {
     fn get_identity_column_name() -> String {
         EmojiCategory::get_identity_column_name()
@@ -1503,17 +1615,16 @@ impl Table for EmojiCategories {
     fn get_db_columns() -> String {
         EmojiCategory::properties(false)
     }
-    fn to_db_row(&self) -> String {
-        self.values()
+    /// A collection has no single row, every category is stored on its own.
+    fn to_db_values(&self) -> Vec<DbValue> {
+        vec![]
     }
 }
-impl std::str::FromStr for EmojiCategories {
-    type Err = crate::error::FromErrorKind;
-    fn from_str(v: &str) -> Result<Self, Self::Err> {
-        debug!("{:?}", v);
-        let _result: Vec<&str> = v.split(",-o-,").collect();
+#[cfg(feature = "db")]
+impl FromDbRow for EmojiCategories {
+    fn from_db_row(_row: &DbRow) -> Result<Self, crate::error::FromErrorKind> {
         Ok(Self {
-            emojis: vec![], // TODO result.get(0).unwrap()
+            emojis: vec![], // TODO
         })
     }
 }
@@ -1548,19 +1659,6 @@ impl EmojiCategory {
             String::from("name, emojis")
         }
     }
-    fn values(&self) -> String {
-        vec![
-            self.name.clone(),
-            self.emojis
-                .clone()
-                .into_iter()
-                .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-                .unwrap(),
-        ]
-        .into_iter()
-        .reduce(|acc, e| accumulate_strings_with_delimiter(&acc, &e))
-        .unwrap()
-    }
 }
 impl IdGenerator for EmojiCategories {
     fn get_id(&self) -> String {
@@ -3,8 +3,35 @@ pub const DATE_DEPLOY: &str = "VOTERS_VERDICT_DATE_DEPLOY";
 pub const ASSET_DIR: &str = "VOTERS_VERDICT_ASSET_DIR";
 pub const MANIFEST_DIR: &str = "CARGO_MANIFEST_DIR";
 pub const FILE_DIR: &str = "VOTERS_VERDICT_FILE_DIR";
+pub const BALLOT_LAYOUT: &str = "VOTERS_VERDICT_BALLOT_LAYOUT";
 pub const MAINTAINER_TOKEN: &str = "VOTERS_VERDICT_MAINTAINER_TOKEN";
 pub const ADMIN_TOKEN: &str = "VOTERS_VERDICT_ADMIN_TOKEN";
 pub const STORAGE_MODE: &str = "VOTERS_VERDICT_STORAGE_MODE";
+pub const CACHE_TTL: &str = "VOTERS_VERDICT_CACHE_TTL";
 pub const SQLITE_CONNECTION: &str = "DATABASE_URL";
+pub const POSTGRES_CONNECTION: &str = "VOTERS_VERDICT_POSTGRES_URL";
 pub const REMOTE_STORAGE: &str = "VOTERS_VERDICT_REMOTE_STORAGE";
+pub const REMOTE_AUTH: &str = "VOTERS_VERDICT_REMOTE_AUTH";
+pub const REMOTE_CREDENTIALS: &str = "VOTERS_VERDICT_REMOTE_CREDENTIALS";
+pub const REMOTE_TIMEOUT: &str = "VOTERS_VERDICT_REMOTE_TIMEOUT";
+pub const REMOTE_CONNECT_TIMEOUT: &str = "VOTERS_VERDICT_REMOTE_CONNECT_TIMEOUT";
+pub const REMOTE_RETRIES: &str = "VOTERS_VERDICT_REMOTE_RETRIES";
+pub const REMOTE_RETRY_BACKOFF: &str = "VOTERS_VERDICT_REMOTE_RETRY_BACKOFF";
+pub const SELF_CERT: &str = "VOTERS_VERDICT_SELF_CERT";
+pub const REMOTE_CLIENT_CERT: &str = "VOTERS_VERDICT_REMOTE_CLIENT_CERT";
+pub const REMOTE_CLIENT_KEY: &str = "VOTERS_VERDICT_REMOTE_CLIENT_KEY";
+pub const REMOTE_PINNED_CERTS: &str = "VOTERS_VERDICT_REMOTE_PINNED_CERTS";
+pub const S3_ENDPOINT: &str = "VOTERS_VERDICT_S3_ENDPOINT";
+pub const S3_BUCKET: &str = "VOTERS_VERDICT_S3_BUCKET";
+pub const S3_REGION: &str = "VOTERS_VERDICT_S3_REGION";
+pub const S3_PREFIX: &str = "VOTERS_VERDICT_S3_PREFIX";
+pub const S3_ACCESS_KEY: &str = "VOTERS_VERDICT_S3_ACCESS_KEY";
+pub const S3_SECRET_KEY: &str = "VOTERS_VERDICT_S3_SECRET_KEY";
+pub const RATE_LIMIT_ATTEMPTS: &str = "VOTERS_VERDICT_RATE_LIMIT_ATTEMPTS";
+pub const RATE_LIMIT_BACKOFF: &str = "VOTERS_VERDICT_RATE_LIMIT_BACKOFF";
+pub const RATE_LIMIT_MAX_BACKOFF: &str = "VOTERS_VERDICT_RATE_LIMIT_MAX_BACKOFF";
+pub const RATE_LIMIT_MAX_TRACKED: &str = "VOTERS_VERDICT_RATE_LIMIT_MAX_TRACKED";
+pub const CORS_ORIGINS: &str = "VOTERS_VERDICT_CORS_ORIGINS";
+pub const CORS_METHODS: &str = "VOTERS_VERDICT_CORS_METHODS";
+pub const CORS_HEADERS: &str = "VOTERS_VERDICT_CORS_HEADERS";
+pub const CORS_ROUTES: &str = "VOTERS_VERDICT_CORS_ROUTES";
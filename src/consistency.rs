@@ -0,0 +1,218 @@
+use crate::{
+    authentication::{can, Authorized},
+    csrf::CsrfVerified,
+    error::VoteErrorKind,
+    persistence::{Kind, Storage, StorageBackend},
+};
+use rocket::{
+    error, get,
+    http::Status,
+    post,
+    serde::{json::Json, Deserialize, Serialize},
+    State,
+};
+
+/// How the index of a kind differs from its entries.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IndexReport {
+    pub kind: String,
+    /// Entries, which aren't in the index.
+    pub orphans: Vec<String>,
+    /// Ids of the index without an entry.
+    pub missing: Vec<String>,
+    /// Ids listed more than once in the index.
+    pub duplicates: Vec<String>,
+    /// Entries, which aren't valid JSON.
+    pub unparsable: Vec<String>,
+    pub rebuilt: bool,
+}
+impl IndexReport {
+    pub fn is_consistent(&self) -> bool {
+        self.orphans.is_empty() && self.missing.is_empty() && self.duplicates.is_empty()
+    }
+    /// The ids of the index, which still have an entry, in their order and the
+    /// orphans after them.
+    fn rebuilt_index(&self, index: Vec<String>) -> Vec<String> {
+        let mut ids: Vec<String> = vec![];
+        for id in index {
+            if !self.missing.contains(&id) && !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids.extend(self.orphans.iter().cloned());
+        ids
+    }
+}
+
+/// Compares the index of `kind` with its entries. Backends without an index of
+/// their own are only checked for unparsable entries.
+pub async fn check_kind(
+    storage: &dyn StorageBackend,
+    kind: Kind,
+    rebuild: bool,
+) -> Result<IndexReport, VoteErrorKind<'static>> {
+    let index = storage.index(kind).await?;
+    let mut report = IndexReport {
+        kind: kind.dir().to_string(),
+        ..Default::default()
+    };
+    let entries = match storage.keeps_index() {
+        true => {
+            let mut entries = storage.list(kind).await?;
+            entries.sort();
+            entries
+        }
+        false => index.clone(),
+    };
+    for (position, id) in index.iter().enumerate() {
+        if index[..position].contains(id) && !report.duplicates.contains(id) {
+            report.duplicates.push(id.clone());
+        }
+        if !entries.contains(id) && !report.missing.contains(id) {
+            report.missing.push(id.clone());
+        }
+    }
+    for id in entries {
+        if !index.contains(&id) {
+            report.orphans.push(id.clone());
+        }
+        let parsed = match storage.get(kind, &id).await {
+            Ok(raw) => rocket::serde::json::from_str::<rocket::serde::json::Value>(&raw).is_ok(),
+            Err(_) => false,
+        };
+        if !parsed {
+            report.unparsable.push(id);
+        }
+    }
+    if rebuild && !report.is_consistent() {
+        storage
+            .rebuild_index(kind, report.rebuilt_index(index))
+            .await?;
+        report.rebuilt = true;
+    }
+    Ok(report)
+}
+
+/// Checks every kind of the storage and rebuilds inconsistent indexes, if
+/// `rebuild` is set.
+pub async fn check_storage(
+    storage: &dyn StorageBackend,
+    rebuild: bool,
+) -> Result<Vec<IndexReport>, VoteErrorKind<'static>> {
+    let mut reports = vec![];
+    for kind in Kind::ALL {
+        reports.push(check_kind(storage, kind, rebuild).await?);
+    }
+    Ok(reports)
+}
+
+async fn check(storage: &Storage, rebuild: bool) -> Result<Json<Vec<IndexReport>>, Status> {
+    match check_storage(storage.as_ref(), rebuild).await {
+        Ok(reports) => Ok(Json(reports)),
+        Err(VoteErrorKind::Conflict(e)) => {
+            error!("{}", e);
+            Err(Status::Conflict)
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/index")]
+pub async fn get_index_report(
+    _authorized: Authorized<can::ManageStorage>,
+    storage: &State<Storage>,
+) -> Result<Json<Vec<IndexReport>>, Status> {
+    check(storage, false).await
+}
+
+#[post("/index")]
+pub async fn rebuild_index(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageStorage>,
+    storage: &State<Storage>,
+) -> Result<Json<Vec<IndexReport>>, Status> {
+    authorized.user.audit("rebuilt", "the index");
+    check(storage, true).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{memory::MemoryStorage, persistence::PutMode};
+
+    fn test_storage() -> MemoryStorage {
+        let test_data =
+            std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/";
+        MemoryStorage::seeded(&test_data, &test_data)
+    }
+
+    #[rocket::async_test]
+    async fn reports_drift_of_the_index() {
+        let storage = test_storage();
+        storage
+            .put(
+                Kind::Ballot,
+                "voting-obama-doe",
+                String::from("{"),
+                PutMode::Replace,
+            )
+            .await
+            .unwrap();
+        storage
+            .put(
+                Kind::Ballot,
+                "voting-michelle-doe",
+                String::from("{}"),
+                PutMode::Replace,
+            )
+            .await
+            .unwrap();
+        let report = check_kind(&storage, Kind::Ballot, false).await.unwrap();
+        assert_eq!(report.orphans, vec!["voting-michelle-doe"]);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unparsable, vec!["voting-obama-doe"]);
+        assert!(!report.rebuilt);
+
+        let report = check_kind(&storage, Kind::Candidate, false).await.unwrap();
+        assert_eq!(report.missing, vec!["candidate_test"]);
+        assert!(report.orphans.is_empty());
+        let report = check_kind(&storage, Kind::Voting, false).await.unwrap();
+        assert!(report.is_consistent());
+    }
+
+    #[rocket::async_test]
+    async fn rebuilds_the_index() {
+        let storage = test_storage();
+        storage
+            .put(
+                Kind::Ballot,
+                "voting-michelle-doe",
+                String::from("{}"),
+                PutMode::Replace,
+            )
+            .await
+            .unwrap();
+        let reports = check_storage(&storage, true).await.unwrap();
+        assert!(reports.iter().any(|r| r.rebuilt));
+        assert_eq!(
+            storage.index(Kind::Ballot).await.unwrap(),
+            vec![
+                "voting-obama-doe",
+                "voting-obama-joe",
+                "voting-michelle-joe",
+                "voting-michelle-doe"
+            ]
+        );
+        assert!(!storage
+            .index(Kind::Candidate)
+            .await
+            .unwrap()
+            .contains(&String::from("candidate_test")));
+        let reports = check_storage(&storage, false).await.unwrap();
+        assert!(reports.iter().all(|r| r.is_consistent()));
+    }
+}
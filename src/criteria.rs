@@ -5,7 +5,9 @@ use rocket::{
 };
 
 use crate::{
-    common::{Criteria, Criterion, Empty, Fill},
+    authentication::{can, Authorized},
+    common::{Criteria, Criterion, Empty, Fill, IdGenerator},
+    csrf::CsrfVerified,
     persistence::ToPersistence,
     serialize::ToStorage,
     validator::compare_pattern_file_names,
@@ -35,9 +37,19 @@ pub async fn get_criterion(criterion: &str) -> Result<Json<Vec<Criterion>>, NotF
 }
 
 #[post("/", format = "application/json", data = "<criterion>")]
-pub async fn post_criterion(criterion: Json<Criterion>) -> Result<String, Conflict<String>> {
-    match criterion.into_inner().save().await {
-        Ok(done) => Ok(done),
+pub async fn post_criterion(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageCriteria>,
+    criterion: Json<Criterion>,
+) -> Result<String, Conflict<String>> {
+    let payload = criterion.into_inner();
+    match payload.save().await {
+        Ok(done) => {
+            authorized
+                .user
+                .audit("created criterion", &payload.get_id());
+            Ok(done)
+        }
         Err(e) => Err(Conflict(e.to_string())),
     }
 }
@@ -45,15 +57,39 @@ pub async fn post_criterion(criterion: Json<Criterion>) -> Result<String, Confli
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{authentication::ElevatedUser, persistence::skips_without_storage};
+    /// The criteria of the fixtures sorted, without the one `post_criterion`
+    /// may have saved meanwhile.
+    async fn fixture_criteria() -> Vec<String> {
+        let mut criteria = super::get_criterias().await.unwrap().into_inner();
+        criteria.retain(|c| !c.to_lowercase().starts_with("test_"));
+        criteria.sort();
+        criteria
+    }
+    #[cfg(not(feature = "db"))]
     #[rocket::async_test]
     async fn get_criterias() {
-        let response = super::get_criterias().await.unwrap();
         assert_eq!(
-            response.into_inner(),
+            fixture_criteria().await,
             vec![
                 String::from("style_0_10_15"),
-                String::from("weather_0_15_15"),
                 String::from("style_10_15_50"),
+                String::from("weather_0_15_15"),
+            ]
+        );
+    }
+    // The database keeps one criterion per name.
+    #[cfg(feature = "db")]
+    #[rocket::async_test]
+    async fn get_criterias() {
+        if skips_without_storage() {
+            return;
+        }
+        assert_eq!(
+            fixture_criteria().await,
+            vec![
+                String::from("Style_0_10_15"),
+                String::from("Weather_0_15_15"),
             ]
         );
     }
@@ -68,6 +104,8 @@ mod test {
             true
         );
     }
+    // Criteria names are unique in the database, there is only one "Style".
+    #[cfg(not(feature = "db"))]
     #[rocket::async_test]
     async fn get_single_criterion() {
         let response = super::get_criterion("Style_10").await.unwrap();
@@ -81,6 +119,8 @@ mod test {
             }]
         );
     }
+    // Criteria names are unique in the database, there is only one "Style".
+    #[cfg(not(feature = "db"))]
     #[rocket::async_test]
     async fn get_multiple_criterion() {
         let response = super::get_criterion("Style").await.unwrap();
@@ -104,25 +144,42 @@ mod test {
     }
     #[rocket::async_test]
     async fn post_criterion() {
+        if skips_without_storage() {
+            return;
+        }
         let criterion = Criterion {
             name: String::from("test"),
             min: 1,
             max: 2,
             weight: Some(80.0),
         };
-        let response = super::post_criterion(Json(criterion)).await.unwrap();
+        let response = super::post_criterion(
+            CsrfVerified::new(),
+            Authorized::new(ElevatedUser::new_admin()),
+            Json(criterion),
+        )
+        .await
+        .unwrap();
         assert_eq!(response, "Saved and index updated.");
     }
 
     #[rocket::async_test]
     async fn post_criterion_conflict() {
+        if skips_without_storage() {
+            return;
+        }
         let criterion = Criterion {
             name: String::from("weather"),
             min: 0,
             max: 15,
             weight: Some(15.0),
         };
-        let response = super::post_criterion(Json(criterion)).await;
+        let response = super::post_criterion(
+            CsrfVerified::new(),
+            Authorized::new(ElevatedUser::new_admin()),
+            Json(criterion),
+        )
+        .await;
         assert_eq!(
             response,
             Err(Conflict(String::from("Criterion already exist.")))
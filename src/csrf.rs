@@ -0,0 +1,201 @@
+use crate::{error::VoteErrorKind, plumping::CorsPolicy};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use rocket::{
+    http::{Cookie, SameSite, Status},
+    request::{FromRequest, Outcome},
+    response::status::Unauthorized,
+    Request,
+};
+
+pub const CSRF_COOKIE: &str = "voters-verdict-csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/////////////////////////////////////////////
+//                                         //
+//               ISSUE                     //
+//                                         //
+/////////////////////////////////////////////
+
+/// Request guard for rendered pages. It reuses the token of the cookie or
+/// sets a new one, the page hands it to the JS via `csrf_token`.
+pub struct CsrfToken(pub String);
+
+pub(crate) fn generate_csrf_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(cookie) = req.cookies().get(CSRF_COOKIE) {
+            return Outcome::Success(CsrfToken(cookie.value().to_string()));
+        }
+        let token = generate_csrf_token();
+        req.cookies().add(
+            Cookie::build((CSRF_COOKIE, token.clone()))
+                .path("/")
+                .same_site(SameSite::Strict)
+                .http_only(true),
+        );
+        Outcome::Success(CsrfToken(token))
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//               VERIFY                    //
+//                                         //
+/////////////////////////////////////////////
+
+/// Request guard for every POST and PUT route. The `X-CSRF-Token` header has
+/// to match the cookie, which another site can neither read nor send along
+/// with a header of its own.
+pub struct CsrfVerified(());
+impl CsrfVerified {
+    #[cfg(test)]
+    pub(crate) fn new() -> Self {
+        Self(())
+    }
+}
+
+pub(crate) fn is_same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfVerified {
+    type Error = VoteErrorKind<'r>;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let csrf_error =
+            VoteErrorKind::Unauthorized(Unauthorized(String::from("Missing or wrong CSRF token.")));
+        let cookie = req
+            .cookies()
+            .get(CSRF_COOKIE)
+            .map(|c| c.value().to_string());
+        match (cookie, req.headers().get_one(CSRF_HEADER)) {
+            (Some(c), Some(h)) if !c.is_empty() && is_same_token(&c, h) => {
+                Outcome::Success(CsrfVerified(()))
+            }
+            _ => Outcome::Error((Status::Forbidden, csrf_error)),
+        }
+    }
+}
+
+/// Request guard for ballots, which other origins may post as well. A request
+/// of an origin the CORS policy allows for the route is accepted without the
+/// token, it can't carry the strict cookie anyway. Ballots are authorized by
+/// the invite code header, not by a cookie of the voter, and the header only
+/// passes the preflight of allowed origins. With the origins `*` every site is
+/// allowed, so the token is checked then.
+pub struct CsrfOrAllowedOrigin(());
+impl CsrfOrAllowedOrigin {
+    #[cfg(test)]
+    pub(crate) fn new() -> Self {
+        Self(())
+    }
+}
+
+fn is_allowed_origin(req: &Request<'_>) -> bool {
+    match (
+        req.rocket().state::<CorsPolicy>(),
+        req.headers().get_one("Origin"),
+    ) {
+        (Some(policy), Some(origin)) if !policy.allows_any_origin() => policy
+            .allowed_origin(origin, req.uri().path().as_str())
+            .is_some(),
+        _ => false,
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfOrAllowedOrigin {
+    type Error = VoteErrorKind<'r>;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if is_allowed_origin(req) {
+            return Outcome::Success(CsrfOrAllowedOrigin(()));
+        }
+        CsrfVerified::from_request(req)
+            .await
+            .map(|_| CsrfOrAllowedOrigin(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csrf_tokens_are_random() {
+        let token = generate_csrf_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_csrf_token());
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(is_same_token("abc", "abc"));
+        assert!(!is_same_token("abc", "abd"));
+        assert!(!is_same_token("abc", "abcd"));
+    }
+
+    #[rocket::async_test]
+    async fn accepts_allowed_origins_without_token() {
+        use rocket::{http::Header, local::asynchronous::Client, post, routes};
+        #[post("/api/v1/ballots/<_voting>")]
+        fn ballot(_voting: &str, _csrf: CsrfOrAllowedOrigin) {}
+        let policy = CorsPolicy {
+            origins: vec![String::from("https://ballots.example")],
+            methods: vec![],
+            headers: vec![],
+            routes: vec![String::from("/api/v1/ballots")],
+        };
+        let rocket = rocket::build().manage(policy).mount("/", routes![ballot]);
+        let client = Client::untracked(rocket).await.unwrap();
+        for (origin, status) in [
+            ("https://ballots.example", Status::Ok),
+            ("https://evil.example", Status::Forbidden),
+        ] {
+            let response = client
+                .post("/api/v1/ballots/voting")
+                .header(Header::new("Origin", origin))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), status);
+        }
+        let response = client.post("/api/v1/ballots/voting").dispatch().await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let any = CorsPolicy {
+            origins: vec![String::from("*")],
+            methods: vec![],
+            headers: vec![],
+            routes: vec![String::from("/api/v1/ballots")],
+        };
+        let rocket = rocket::build().manage(any).mount("/", routes![ballot]);
+        let client = Client::untracked(rocket).await.unwrap();
+        let response = client
+            .post("/api/v1/ballots/voting")
+            .header(Header::new("Origin", "https://evil.example"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .post("/api/v1/ballots/voting")
+            .header(Header::new("Origin", "https://evil.example"))
+            .header(Header::new(CSRF_HEADER, "same"))
+            .cookie(Cookie::new(CSRF_COOKIE, "same"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
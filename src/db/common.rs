@@ -1,23 +1,26 @@
-#[cfg(feature = "diesel_sqlite")]
-use super::sqlite::{establish_connection, query_voting, EmptySelectable};
 use crate::{
-    common::{
-        Candidate, CastBallots, Criteria, Criterion, EmojiCategories, Empty, QueryableExt, Voting,
-        Votings,
-    },
+    common::{Candidate, Criterion, EmojiCategories, FromDbRow, Operator, QueryableExt, Voting},
     error::VoteErrorKind,
 };
+use crate::{
+    common::{DbValue, IdGenerator},
+    db::{Connection, Database},
+};
 use rocket::{debug, error};
-use std::collections::BTreeMap;
 #[rocket::async_trait]
-pub trait Query: QueryableExt + std::str::FromStr + std::marker::Sync {
+pub trait Query: QueryableExt + FromDbRow + std::marker::Sync {
     fn get_dir() -> String {
         debug!("db::query::get_dir");
         String::from("dummy")
     }
-    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind> {
+    async fn is_unique(&self, database: Database, id: &str) -> Result<String, VoteErrorKind> {
         debug!("db::query::is_inque");
-        match crate::db::sqlx_sqlite::is_unique(
+        let mut conn = match database.connection().await {
+            Ok(c) => c,
+            Err(_) => return Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        };
+        match crate::db::is_unique(
+            &mut conn,
             &Self::get_table(false),
             &Self::get_identity_column_name(),
             id,
@@ -34,23 +37,22 @@ pub trait Query: QueryableExt + std::str::FromStr + std::marker::Sync {
             Err(_) => Err(VoteErrorKind::NotFound(String::from("Not found"))),
         }
     }
-    async fn index(&self) -> Result<Vec<String>, String> {
+    async fn index(&self, database: Database) -> Result<Vec<String>, String> {
         debug!("db::query::index");
-        self.list_rows(false).await
+        self.list_rows(database, false).await
     }
 
-    #[cfg(feature = "diesel_sqlite")]
-    async fn select<T: EmptySelectable>(&self, id: &str) -> Result<T, String> {
-        let conn = establish_connection();
-        match query_voting(&mut conn, id).await {
-            Ok(r) => Ok(r),
-            Err(_) => Err(String::from("No hit.")),
-        }
-    }
-    #[cfg(feature = "sqlx_sqlite")]
-    async fn list_rows(&self, all: bool) -> Result<Vec<String>, String> {
+    async fn list_rows(&self, database: Database, all: bool) -> Result<Vec<String>, String> {
         debug!("db::query::list_rows");
-        match crate::db::sqlx_sqlite::list_rows(
+        let mut conn = match database.connection().await {
+            Ok(c) => c,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(String::from("No entries"));
+            }
+        };
+        match crate::db::list_rows(
+            &mut conn,
             &Self::get_table(false),
             &Self::get_db_columns(),
             &Self::get_identity_column_name(),
@@ -65,70 +67,190 @@ pub trait Query: QueryableExt + std::str::FromStr + std::marker::Sync {
             }
         }
     }
-    async fn create(&self) -> Result<String, VoteErrorKind> {
+    async fn create(&self, database: Database) -> Result<String, VoteErrorKind<'static>> {
         debug!("db::query::create");
-        self.save().await
+        self.save(database).await
     }
 
-    async fn update_index(&self) -> Result<String, VoteErrorKind> {
+    async fn update_index(&self, database: Database) -> Result<String, VoteErrorKind<'static>> {
         debug!("db::query::unpdate_index");
-        self.save().await
+        self.save(database).await
     }
 
-    #[cfg(feature = "sqlx_sqlite")]
-    async fn update(&self, new_pairs: BTreeMap<&str, &String>) -> Result<String, VoteErrorKind> {
+    /// Replaces every column of the row and its relations within one
+    /// transaction.
+    async fn update(&self, database: Database) -> Result<String, VoteErrorKind<'static>> {
         debug!("db::query::update");
-        match crate::db::sqlx_sqlite::update(
-            &Self::get_table(true),
-            new_pairs,
+        let mut tx = database.begin().await?;
+        match crate::db::update(
+            &mut tx,
+            &Self::get_table(false),
+            &Self::get_db_columns(),
+            &self.to_db_values(),
             &Self::get_identity_column_name(),
-            &self.get_name(),
+            &self.get_id(),
         )
         .await
         {
-            Ok(_) => Ok(self.get_id()),
+            Ok(_) => {
+                self.save_relations(&mut tx).await?;
+                tx.commit().await?;
+                Ok(self.get_id())
+            }
             Err(er) => {
                 error!("{:?}", er);
                 Err(er)
             }
         }
     }
-    #[cfg(feature = "sqlx_sqlite")]
-    async fn save(&self) -> Result<String, VoteErrorKind> {
+    /// Deletes the row and its relations within one transaction.
+    async fn delete(&self, database: Database) -> Result<String, VoteErrorKind<'static>> {
+        debug!("db::query::delete");
+        let mut tx = database.begin().await?;
+        self.delete_relations(&mut tx).await?;
+        crate::db::delete(
+            &mut tx,
+            &Self::get_table(false),
+            &Self::get_identity_column_name(),
+            &self.get_id(),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(String::from("Deleted."))
+    }
+    /// Rows of the join tables, which belong to this row. Nothing by default.
+    async fn save_relations(&self, _conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        Ok(())
+    }
+    /// Deletes the rows `save_relations` wrote. Nothing by default.
+    async fn delete_relations(&self, _conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        Ok(())
+    }
+    /// Checks for an existing row and inserts within one transaction.
+    async fn save(&self, database: Database) -> Result<String, VoteErrorKind<'static>> {
         debug!("db::query::save");
-        let found = crate::db::sqlx_sqlite::select(
+        let mut tx = database.begin().await?;
+        let saved = self.save_in(&mut tx).await?;
+        tx.commit().await?;
+        Ok(saved)
+    }
+    /// Saves on the given connection. Pass a transaction to commit several
+    /// rows, like a ballot and its aggregate, together.
+    async fn save_in(&self, conn: &mut Connection) -> Result<String, VoteErrorKind<'static>> {
+        let found = crate::db::select(
+            conn,
             &Self::get_table(false),
             &Self::get_db_columns(),
             &Self::get_identity_column_name(),
             &self.get_name(),
         )
         .await?;
-        if found.is_empty() {
-            match crate::db::sqlx_sqlite::save(&Self::get_table(true), &self.to_db_row()).await {
-                Ok(_) => Ok(self.get_id()),
+        if found.is_none() {
+            match crate::db::save(
+                conn,
+                &Self::get_table(false),
+                &Self::get_db_columns(),
+                &self.to_db_values(),
+            )
+            .await
+            {
+                Ok(_) => {
+                    self.save_relations(conn).await?;
+                    Ok(String::from("Saved and index updated."))
+                }
                 Err(er) => {
                     error!("{:?}", er);
                     Err(er)
                 }
             }
         } else {
-            Err(VoteErrorKind::Conflict(String::from("Already exist.")))
+            Err(VoteErrorKind::Conflict(self.get_type() + " already exist."))
         }
     }
 }
 
-impl QueryableExt for Votings {}
 impl QueryableExt for Voting {}
-impl QueryableExt for CastBallots {}
 impl QueryableExt for Candidate {}
-impl QueryableExt for Criteria {}
+impl QueryableExt for Operator {}
 impl QueryableExt for Criterion {}
 impl QueryableExt for EmojiCategories {}
 
-impl Query for Votings {}
-impl Query for Voting {}
-impl Query for CastBallots {}
+/// Candidates and criteria are kept in `voting_candidates` and
+/// `voting_criteria` as well, so results can be computed in SQL.
+#[rocket::async_trait]
+impl Query for Voting {
+    async fn save_relations(&self, conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        let candidates: Vec<Vec<DbValue>> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .map(|(position, c)| {
+                vec![
+                    DbValue::Text(self.name.clone()),
+                    DbValue::Text(c.get_id()),
+                    DbValue::Integer(position as i64),
+                ]
+            })
+            .collect();
+        crate::db::replace_rows(
+            conn,
+            "voting_candidates",
+            "voting",
+            &self.name,
+            "voting, candidate, position",
+            &candidates,
+        )
+        .await?;
+        let criteria: Vec<Vec<DbValue>> = self
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(position, c)| {
+                vec![
+                    DbValue::Text(self.name.clone()),
+                    DbValue::Text(c.name.clone()),
+                    match c.weight {
+                        Some(w) => DbValue::from_f32(w),
+                        None => DbValue::Null,
+                    },
+                    DbValue::Integer(position as i64),
+                ]
+            })
+            .collect();
+        crate::db::replace_rows(
+            conn,
+            "voting_criteria",
+            "voting",
+            &self.name,
+            "voting, criterion, weight, position",
+            &criteria,
+        )
+        .await?;
+        Ok(())
+    }
+    async fn delete_relations(&self, conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+        crate::db::replace_rows(
+            conn,
+            "voting_candidates",
+            "voting",
+            &self.name,
+            "voting, candidate, position",
+            &[],
+        )
+        .await?;
+        crate::db::replace_rows(
+            conn,
+            "voting_criteria",
+            "voting",
+            &self.name,
+            "voting, criterion, weight, position",
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+}
 impl Query for Candidate {}
-impl Query for Criteria {}
+impl Query for Operator {}
 impl Query for Criterion {}
 impl Query for EmojiCategories {}
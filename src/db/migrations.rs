@@ -0,0 +1,274 @@
+use crate::error::VoteErrorKind;
+use chrono::Utc;
+use rocket::{info, warn};
+use rocket_db_pools::sqlx::{self, sqlite::SqliteConnection, Connection, Executor, Row};
+
+/// Applied migrations are recorded here, one row per migration.
+pub const SCHEMA_VERSION_TABLE: &str = "schema_version";
+/// Kept by the diesel CLI, databases set up with it are adopted.
+const DIESEL_MIGRATIONS_TABLE: &str = "__diesel_schema_migrations";
+
+pub struct Migration {
+    /// Name of the directory in `migrations/`.
+    pub version: &'static str,
+    pub up: &'static str,
+}
+impl Migration {
+    /// The version diesel stores, the timestamp without dashes.
+    fn diesel_version(&self) -> String {
+        self.version
+            .split('_')
+            .next()
+            .unwrap_or_default()
+            .replace('-', "")
+    }
+}
+
+macro_rules! migration {
+    ($version:literal) => {
+        Migration {
+            version: $version,
+            up: include_str!(concat!("../../migrations/", $version, "/up.sql")),
+        }
+    };
+}
+
+/// Every migration of `migrations/` in the order they are applied.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!("2023-12-20-155335_create_votes"),
+    migration!("2026-10-19-090000_create_operators"),
+    migration!("2026-10-19-100000_add_voting_owner"),
+    migration!("2026-10-19-110000_add_invite_code_rotations"),
+    migration!("2026-10-19-120000_fix_votings_and_ballots"),
+    migration!("2026-10-19-130000_normalise_votings_and_votes"),
+];
+
+pub fn latest_version() -> &'static str {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or_default()
+}
+
+async fn table_exists(
+    conn: &mut SqliteConnection,
+    table: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let rows = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_all(conn)
+        .await?;
+    Ok(!rows.is_empty())
+}
+
+async fn applied_versions(
+    conn: &mut SqliteConnection,
+    table: &str,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let rows = sqlx::query(&format!("SELECT version FROM {} ORDER BY version", table))
+        .fetch_all(conn)
+        .await?;
+    Ok(rows
+        .iter()
+        .filter_map(|r| r.try_get::<String, usize>(0).ok())
+        .collect())
+}
+
+async fn record_version(
+    conn: &mut SqliteConnection,
+    version: &str,
+) -> Result<(), VoteErrorKind<'static>> {
+    sqlx::query(&format!(
+        "INSERT INTO {} ( version, applied_at ) VALUES ( ?, ? )",
+        SCHEMA_VERSION_TABLE
+    ))
+    .bind(version)
+    .bind(Utc::now().to_string())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// The newest applied migration, `None` for an empty database.
+pub async fn current_version(
+    conn: &mut SqliteConnection,
+) -> Result<Option<String>, VoteErrorKind<'static>> {
+    if !table_exists(conn, SCHEMA_VERSION_TABLE).await? {
+        return Ok(None);
+    }
+    Ok(applied_versions(conn, SCHEMA_VERSION_TABLE).await?.pop())
+}
+
+/// Records the migrations the diesel CLI already ran, so they aren't run twice.
+async fn adopt_diesel_migrations(
+    conn: &mut SqliteConnection,
+) -> Result<(), VoteErrorKind<'static>> {
+    if !table_exists(conn, DIESEL_MIGRATIONS_TABLE).await? {
+        return Ok(());
+    }
+    let diesel_versions = applied_versions(conn, DIESEL_MIGRATIONS_TABLE).await?;
+    for migration in MIGRATIONS {
+        if diesel_versions.contains(&migration.diesel_version()) {
+            info!("Adopting migration {} of diesel.", migration.version);
+            record_version(conn, migration.version).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies every pending migration in its own transaction and returns the
+/// versions, which were applied.
+pub async fn run(conn: &mut SqliteConnection) -> Result<Vec<&'static str>, VoteErrorKind<'static>> {
+    if !table_exists(conn, SCHEMA_VERSION_TABLE).await? {
+        let mut tx = conn.begin().await?;
+        tx.execute(
+            format!(
+                "CREATE TABLE {} ( version TEXT PRIMARY KEY NOT NULL, applied_at TIMESTAMP NOT NULL )",
+                SCHEMA_VERSION_TABLE
+            )
+            .as_str(),
+        )
+        .await?;
+        adopt_diesel_migrations(&mut tx).await?;
+        tx.commit().await?;
+    }
+    let applied = applied_versions(conn, SCHEMA_VERSION_TABLE).await?;
+    if let Some(unknown) = applied
+        .iter()
+        .find(|v| !MIGRATIONS.iter().any(|m| &m.version == v))
+    {
+        warn!(
+            "The database knows migration {}, which this build doesn't.",
+            unknown
+        );
+    }
+    let mut newly_applied = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|v| v == migration.version) {
+            continue;
+        }
+        info!("Applying migration {}", migration.version);
+        let mut tx = conn.begin().await?;
+        tx.execute(migration.up).await?;
+        record_version(&mut tx, migration.version).await?;
+        tx.commit().await?;
+        newly_applied.push(migration.version);
+    }
+    Ok(newly_applied)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn memory_connection() -> SqliteConnection {
+        SqliteConnection::connect("sqlite::memory:").await.unwrap()
+    }
+
+    #[test]
+    fn every_migration_is_embedded() {
+        let mut dirs: Vec<String> = std::fs::read_dir("migrations")
+            .unwrap()
+            .map(|d| d.unwrap())
+            .filter(|d| d.path().is_dir())
+            .map(|d| d.file_name().to_string_lossy().to_string())
+            .collect();
+        dirs.sort();
+        let versions: Vec<&str> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(dirs, versions);
+    }
+
+    #[rocket::async_test]
+    async fn migrates_once_and_records_the_version() {
+        let mut conn = memory_connection().await;
+        assert_eq!(current_version(&mut conn).await.unwrap(), None);
+        assert_eq!(run(&mut conn).await.unwrap().len(), MIGRATIONS.len());
+        assert_eq!(
+            current_version(&mut conn).await.unwrap(),
+            Some(latest_version().to_string())
+        );
+        assert!(run(&mut conn).await.unwrap().is_empty());
+
+        conn.execute("INSERT INTO votings ( name, expires_at, created_at, candidates, categories, styles, invite_code ) VALUES ( 'a', '', '', '', '', '', '' )")
+            .await
+            .unwrap();
+        assert!(conn
+            .execute("INSERT INTO votings ( name, expires_at, created_at, candidates, categories, styles, invite_code ) VALUES ( 'a', '', '', '', '', '', '' )")
+            .await
+            .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn fixes_existing_ballots() {
+        let mut conn = memory_connection().await;
+        let diesel_migrations = &MIGRATIONS[..4];
+        for migration in diesel_migrations {
+            conn.execute(migration.up).await.unwrap();
+        }
+        conn.execute("INSERT INTO ballots ( human_identifier, voter, candidate, sum, weighted, mean, notes, votes, voted_on ) VALUES ( 'Final-anna-bob', 'anna', 'bob', 3, 3.0, 3.0, '', '[{\"name\":\"taste\",\"point\":3}]', '' )")
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO votings ( name, expires_at, created_at, candidates, categories, styles, invite_code ) VALUES ( 'summer', '', '', '', '', '', '' ), ( 'Summer-Fest', '', '', '', '', '', '' )")
+            .await
+            .unwrap();
+        conn.execute("INSERT INTO ballots ( human_identifier, voter, candidate, sum, weighted, mean, notes, votes, voted_on ) VALUES ( 'summer-fest-anna-bob', 'anna', 'bob', 1, 1.0, 1.0, 'first', '[]', '' ), ( 'summer-fest-anna-bob', 'anna', 'bob', 2, 2.0, 2.0, 'second', '[]', '' )")
+            .await
+            .unwrap();
+        conn.execute(
+            format!(
+                "CREATE TABLE {} ( version VARCHAR(50) PRIMARY KEY NOT NULL, run_on TIMESTAMP )",
+                DIESEL_MIGRATIONS_TABLE
+            )
+            .as_str(),
+        )
+        .await
+        .unwrap();
+        for migration in diesel_migrations {
+            sqlx::query(&format!(
+                "INSERT INTO {} ( version ) VALUES ( ? )",
+                DIESEL_MIGRATIONS_TABLE
+            ))
+            .bind(migration.diesel_version())
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            run(&mut conn).await.unwrap().len(),
+            MIGRATIONS.len() - diesel_migrations.len()
+        );
+        let voting: String =
+            sqlx::query("SELECT voting FROM ballots WHERE human_identifier = 'Final-anna-bob'")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap()
+                .get(0);
+        assert_eq!(voting, "final");
+        let row = sqlx::query(
+            "SELECT voting, notes FROM ballots WHERE human_identifier = 'summer-fest-anna-bob'",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(row.get::<String, usize>(0), "summer-fest");
+        assert_eq!(row.get::<String, usize>(1), "second");
+        // Looked up by the lowercase id of the voting, like `select_ballots`.
+        let counted: i64 = sqlx::query("SELECT count(*) FROM ballots WHERE voting = ?")
+            .bind("Summer-Fest".to_lowercase())
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(counted, 1);
+        let quarantined: String = sqlx::query("SELECT notes FROM ballots_duplicates")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(quarantined, "first");
+        let point: i64 = sqlx::query("SELECT point FROM votes WHERE ballot = 'Final-anna-bob'")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(point, 3);
+    }
+}
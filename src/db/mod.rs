@@ -1,5 +1,333 @@
+#[cfg(feature = "postgres")]
+use crate::config::POSTGRES_CONNECTION;
+#[cfg(any(feature = "sqlx_sqlite", feature = "diesel_sqlite"))]
+use crate::config::SQLITE_CONNECTION;
+use crate::error::VoteErrorKind;
+use rocket::{fairing::AdHoc, figment::Figment};
+use std::ops::{Deref, DerefMut};
+
 pub mod common;
+#[cfg(feature = "sqlx_sqlite")]
+pub mod migrations;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 #[cfg(feature = "diesel_sqlite")]
 pub mod sqlite;
 #[cfg(feature = "sqlx_sqlite")]
 pub mod sqlx_sqlite;
+pub mod statements;
+pub mod storage;
+
+use statements::BallotRow;
+
+#[cfg(not(any(
+    feature = "diesel_sqlite",
+    feature = "sqlx_sqlite",
+    feature = "postgres"
+)))]
+compile_error!("The feature db needs one of diesel_sqlite, sqlx_sqlite or postgres.");
+
+/// The databases built in, the storage mode picks the one `Query` and
+/// `DatabaseStorage` run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Database {
+    #[cfg(feature = "sqlx_sqlite")]
+    SqlxSqlite,
+    #[cfg(feature = "diesel_sqlite")]
+    DieselSqlite,
+    #[cfg(feature = "postgres")]
+    Postgres,
+}
+/// SQLite is preferred to PostgreSQL and sqlx to diesel, if several are built
+/// in.
+impl Default for Database {
+    #[cfg(feature = "sqlx_sqlite")]
+    fn default() -> Self {
+        Database::SqlxSqlite
+    }
+    #[cfg(all(feature = "diesel_sqlite", not(feature = "sqlx_sqlite")))]
+    fn default() -> Self {
+        Database::DieselSqlite
+    }
+    #[cfg(all(
+        feature = "postgres",
+        not(any(feature = "sqlx_sqlite", feature = "diesel_sqlite"))
+    ))]
+    fn default() -> Self {
+        Database::Postgres
+    }
+}
+
+/// A connection out of the pool of a backend, or a transaction on one.
+pub enum Handle<P, T> {
+    Pooled(P),
+    Transaction(T),
+}
+impl<C: ?Sized, P: Deref<Target = C>, T: Deref<Target = C>> Deref for Handle<P, T> {
+    type Target = C;
+    fn deref(&self) -> &C {
+        match self {
+            Handle::Pooled(conn) => conn,
+            Handle::Transaction(tx) => tx,
+        }
+    }
+}
+impl<C: ?Sized, P: DerefMut<Target = C>, T: DerefMut<Target = C>> DerefMut for Handle<P, T> {
+    fn deref_mut(&mut self) -> &mut C {
+        match self {
+            Handle::Pooled(conn) => conn,
+            Handle::Transaction(tx) => tx,
+        }
+    }
+}
+
+/// The connection `Query` runs on, of the backend of a `Database`. The ones
+/// of PostgreSQL are boxed, they are more than twice the size of the others.
+pub enum Connection {
+    #[cfg(feature = "sqlx_sqlite")]
+    SqlxSqlite(Handle<sqlx_sqlite::PooledConnection, sqlx_sqlite::Transaction>),
+    #[cfg(feature = "diesel_sqlite")]
+    DieselSqlite(Handle<sqlite::PooledConnection, sqlite::Transaction>),
+    #[cfg(feature = "postgres")]
+    Postgres(Box<Handle<postgres::PooledConnection, postgres::Transaction>>),
+}
+
+/// Runs the function of the backend the connection belongs to.
+macro_rules! on_backend {
+    ($conn:expr, $function:ident($($arg:expr),*)) => {
+        match $conn {
+            #[cfg(feature = "sqlx_sqlite")]
+            Connection::SqlxSqlite(conn) => sqlx_sqlite::$function(conn, $($arg),*).await,
+            #[cfg(feature = "diesel_sqlite")]
+            Connection::DieselSqlite(conn) => sqlite::$function(conn, $($arg),*).await,
+            #[cfg(feature = "postgres")]
+            Connection::Postgres(conn) => postgres::$function(conn, $($arg),*).await,
+        }
+    };
+}
+
+impl Database {
+    /// The name of the storage mode of the database.
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => "sqlite",
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => "diesel",
+            #[cfg(feature = "postgres")]
+            Database::Postgres => "postgres",
+        }
+    }
+    /// The environment variable holding the url of the database.
+    pub fn variable(self) -> &'static str {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => SQLITE_CONNECTION,
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => SQLITE_CONNECTION,
+            #[cfg(feature = "postgres")]
+            Database::Postgres => POSTGRES_CONNECTION,
+        }
+    }
+    /// Rocket config with `databases.voters_verdict.url` of the database.
+    pub fn figment(self) -> Result<Figment, VoteErrorKind<'static>> {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => sqlx_sqlite::figment(),
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => sqlite::figment(),
+            #[cfg(feature = "postgres")]
+            Database::Postgres => postgres::figment(),
+        }
+    }
+    /// Creates and migrates the pool of the database.
+    pub fn stage(self) -> AdHoc {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => sqlx_sqlite::stage(),
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => sqlite::stage(),
+            #[cfg(feature = "postgres")]
+            Database::Postgres => postgres::stage(),
+        }
+    }
+    /// Migrates the database of `database_url` and runs `script` on it, like
+    /// the fixtures of `test-data/`.
+    pub async fn seed(
+        self,
+        database_url: &str,
+        script: &str,
+    ) -> Result<(), VoteErrorKind<'static>> {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => sqlx_sqlite::seed(database_url, script).await,
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => sqlite::seed(database_url, script).await,
+            #[cfg(feature = "postgres")]
+            Database::Postgres => postgres::seed(database_url, script).await,
+        }
+    }
+    /// A connection out of the shared pool of the database.
+    pub async fn connection(self) -> Result<Connection, VoteErrorKind<'static>> {
+        Ok(match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => {
+                Connection::SqlxSqlite(Handle::Pooled(sqlx_sqlite::connection().await?))
+            }
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => {
+                Connection::DieselSqlite(Handle::Pooled(sqlite::connection().await?))
+            }
+            #[cfg(feature = "postgres")]
+            Database::Postgres => {
+                Connection::Postgres(Box::new(Handle::Pooled(postgres::connection().await?)))
+            }
+        })
+    }
+    /// A transaction, rolled back unless `Connection::commit` is called.
+    pub async fn begin(self) -> Result<Connection, VoteErrorKind<'static>> {
+        Ok(match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Database::SqlxSqlite => {
+                Connection::SqlxSqlite(Handle::Transaction(sqlx_sqlite::begin().await?))
+            }
+            #[cfg(feature = "diesel_sqlite")]
+            Database::DieselSqlite => {
+                Connection::DieselSqlite(Handle::Transaction(sqlite::begin().await?))
+            }
+            #[cfg(feature = "postgres")]
+            Database::Postgres => {
+                Connection::Postgres(Box::new(Handle::Transaction(postgres::begin().await?)))
+            }
+        })
+    }
+}
+
+impl Connection {
+    /// Commits a transaction, a pooled connection has nothing to commit.
+    pub async fn commit(self) -> Result<(), VoteErrorKind<'static>> {
+        match self {
+            #[cfg(feature = "sqlx_sqlite")]
+            Connection::SqlxSqlite(Handle::Transaction(tx)) => Ok(tx.commit().await?),
+            #[cfg(feature = "diesel_sqlite")]
+            Connection::DieselSqlite(Handle::Transaction(tx)) => Ok(tx.commit().await?),
+            #[cfg(feature = "postgres")]
+            Connection::Postgres(handle) => match *handle {
+                Handle::Transaction(tx) => Ok(tx.commit().await?),
+                Handle::Pooled(_) => Ok(()),
+            },
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
+        }
+    }
+}
+
+pub async fn is_unique(
+    conn: &mut Connection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    on_backend!(conn, is_unique(table, identity_column, name))
+}
+
+pub async fn update(
+    conn: &mut Connection,
+    table: &str,
+    columns: &str,
+    values: &[crate::common::DbValue],
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    on_backend!(conn, update(table, columns, values, identity_column, name))
+}
+
+pub async fn delete(
+    conn: &mut Connection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    on_backend!(conn, delete(table, identity_column, name))
+}
+
+pub async fn select(
+    conn: &mut Connection,
+    table: &str,
+    object_columns: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<Option<crate::common::DbRow>, VoteErrorKind<'static>> {
+    on_backend!(conn, select(table, object_columns, identity_column, name))
+}
+
+pub async fn list_rows(
+    conn: &mut Connection,
+    table: &str,
+    object_columns: &str,
+    column_name: &str,
+    all: bool,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    on_backend!(conn, list_rows(table, object_columns, column_name, all))
+}
+
+pub async fn save(
+    conn: &mut Connection,
+    table: &str,
+    columns: &str,
+    values: &[crate::common::DbValue],
+) -> Result<String, VoteErrorKind<'static>> {
+    on_backend!(conn, save(table, columns, values))
+}
+
+pub async fn replace_rows(
+    conn: &mut Connection,
+    table: &str,
+    owner_column: &str,
+    owner: &str,
+    columns: &str,
+    rows: &[Vec<crate::common::DbValue>],
+) -> Result<u64, VoteErrorKind<'static>> {
+    on_backend!(
+        conn,
+        replace_rows(table, owner_column, owner, columns, rows)
+    )
+}
+
+pub async fn select_ballots(
+    conn: &mut Connection,
+    voting: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Result<Vec<BallotRow>, VoteErrorKind<'static>> {
+    on_backend!(conn, select_ballots(voting, filter, order_by))
+}
+
+/// The fixtures of `test-data/` as rows of the latest schema.
+#[cfg(test)]
+pub const TEST_SEED: &str = include_str!("../../test-data/voters-verdict.sql");
+
+/// Whether tests on rows of `database` skip, as PostgreSQL runs only with
+/// VOTERS_VERDICT_POSTGRES_URL.
+#[cfg(test)]
+pub fn skips_without(database: Database) -> bool {
+    #[cfg(feature = "postgres")]
+    if database == Database::Postgres && postgres::test_database_url().is_none() {
+        eprintln!("Skipped without {}.", POSTGRES_CONNECTION);
+        return true;
+    }
+    false
+}
+
+/// A fresh database file per test run and backend, so tests writing rows
+/// don't touch the fixtures.
+#[cfg(all(test, any(feature = "diesel_sqlite", feature = "sqlx_sqlite")))]
+pub fn test_database(backend: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "voters-verdict-{}-{}.sqlite",
+        backend,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
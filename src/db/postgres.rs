@@ -0,0 +1,716 @@
+use crate::{
+    common::{DbRow, DbValue},
+    db::statements::*,
+    error::{FromErrorKind, VoteErrorKind},
+    persistence::PersistenceMode,
+};
+use chrono::Utc;
+use rocket::{
+    debug, error,
+    fairing::AdHoc,
+    figment::Figment,
+    info,
+    tokio::{
+        runtime::{Builder, Runtime},
+        sync::Mutex,
+    },
+    warn,
+};
+use rocket_db_pools::{
+    sqlx,
+    sqlx::{
+        pool::PoolConnection,
+        postgres::{PgArguments, PgConnectOptions, PgConnection, PgPoolOptions, PgRow},
+        Column, Executor, Postgres, Row, TypeInfo, ValueRef,
+    },
+};
+use std::{future::Future, str::FromStr, sync::OnceLock};
+
+type PgQuery<'q> = sqlx::query::Query<'q, Postgres, PgArguments>;
+/// The connection the queries of this backend run on.
+pub type Connection = PgConnection;
+pub type PooledConnection = PoolConnection<Postgres>;
+pub type Transaction = sqlx::Transaction<'static, Postgres>;
+
+/// Applied migrations are recorded here, one row per migration.
+pub const SCHEMA_VERSION_TABLE: &str = "schema_version";
+/// Taken by the instance migrating, others sharing the database wait for it.
+const MIGRATION_LOCK: i64 = 0x766f7465;
+
+/// Every migration of `migrations_postgres/` in the order they are applied,
+/// `(version, up.sql)`.
+pub const MIGRATIONS: &[(&str, &str)] = &[(
+    "2026-10-19-140000_create_schema",
+    include_str!("../../migrations_postgres/2026-10-19-140000_create_schema/up.sql"),
+)];
+
+/////////////////////////////////////////////
+//                                         //
+//               POOL                      //
+//                                         //
+/////////////////////////////////////////////
+
+/// The pool, shared with the `Query` trait like the one of the SQLite
+/// backends.
+static POOL: OnceLock<sqlx::PgPool> = OnceLock::new();
+
+/// Connections belong to the runtime they are opened on, they are dead once
+/// it stops. Opened here, they serve every runtime, like the ones of several
+/// rockets in tests.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("postgres-pool")
+            .enable_all()
+            .build()
+            .expect("The runtime of the PostgreSQL pool starts.")
+    })
+}
+
+async fn on_pool_runtime<T, F>(future: F) -> Result<T, VoteErrorKind<'static>>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T, VoteErrorKind<'static>>> + Send + 'static,
+{
+    match runtime().spawn(future).await {
+        Ok(result) => result,
+        Err(e) => Err(VoteErrorKind::Internal(e.to_string())),
+    }
+}
+
+/// Rocket config with `databases.voters_verdict.url` taken from
+/// `VOTERS_VERDICT_POSTGRES_URL`.
+pub fn figment() -> Result<Figment, VoteErrorKind<'static>> {
+    Ok(rocket::Config::figment().merge((
+        "databases.voters_verdict.url",
+        PersistenceMode::to_conform_path()?,
+    )))
+}
+
+/// Creates the pool out of `databases.voters_verdict.url` and migrates it.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("PostgreSQL pool", |rocket| async {
+        let database_url = match rocket
+            .figment()
+            .extract_inner::<String>("databases.voters_verdict.url")
+        {
+            Ok(url) => url,
+            Err(e) => {
+                error!("No database configured: {:?}", e);
+                return Err(rocket);
+            }
+        };
+        let max_connections = rocket
+            .figment()
+            .extract_inner::<u32>("databases.voters_verdict.max_connections")
+            .ok();
+        let create = async move {
+            let mut options = PgPoolOptions::new();
+            if let Some(max_connections) = max_connections {
+                options = options.max_connections(max_connections);
+            }
+            create_pool_with(options, &database_url).await
+        };
+        match init_pool(create).await {
+            Ok(_) => Ok(rocket),
+            Err(e) => {
+                error!("Migrating the database failed: {:?}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/// Returns the shared pool. Outside of a rocket, like in the admin binary or
+/// in tests, the pool is created once on first use.
+pub async fn pool() -> Result<&'static sqlx::PgPool, VoteErrorKind<'static>> {
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+    #[cfg(not(test))]
+    let create = {
+        let database_url = PersistenceMode::database_url(super::Database::Postgres)?;
+        async move { create_pool(&database_url).await }
+    };
+    // A schema per test run, so tests writing rows don't touch the fixtures.
+    #[cfg(test)]
+    let create = async {
+        let schema = format!("voters_verdict_test_{}", std::process::id());
+        let database_url = test_database_url().ok_or_else(|| {
+            VoteErrorKind::Internal(format!(
+                "The tests of the postgres feature run against {}.",
+                crate::config::POSTGRES_CONNECTION
+            ))
+        })?;
+        let pool = create_pool(&use_schema(&database_url, &schema, true).await?).await?;
+        pool.execute(crate::db::TEST_SEED).await?;
+        Ok(pool)
+    };
+    init_pool(create).await
+}
+
+/// Creates the pool on the runtime of the pool, once.
+async fn init_pool<F>(create: F) -> Result<&'static sqlx::PgPool, VoteErrorKind<'static>>
+where
+    F: Future<Output = Result<sqlx::PgPool, VoteErrorKind<'static>>> + Send + 'static,
+{
+    // Only one caller creates and migrates the pool.
+    static INIT: Mutex<()> = Mutex::const_new(());
+    let _guard = INIT.lock().await;
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+    let pool = on_pool_runtime(create).await?;
+    Ok(POOL.get_or_init(|| pool))
+}
+
+/// The database the tests run against, tests skip without it.
+#[cfg(test)]
+pub fn test_database_url() -> Option<String> {
+    std::env::var(crate::config::POSTGRES_CONNECTION).ok()
+}
+
+async fn create_pool(database_url: &str) -> Result<sqlx::PgPool, VoteErrorKind<'static>> {
+    create_pool_with(PgPoolOptions::new(), database_url).await
+}
+
+async fn create_pool_with(
+    options: PgPoolOptions,
+    database_url: &str,
+) -> Result<sqlx::PgPool, VoteErrorKind<'static>> {
+    let pool = options
+        .connect_with(PgConnectOptions::from_str(database_url)?)
+        .await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// `database_url` with `schema` as search path, so several instances or test
+/// runs can share one database. Creates the schema, a `fresh` one drops the
+/// existing first.
+pub async fn use_schema(
+    database_url: &str,
+    schema: &str,
+    fresh: bool,
+) -> Result<String, VoteErrorKind<'static>> {
+    if !schema
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(VoteErrorKind::Internal(String::from(
+            "Schema names need to match [a-zA-Z0-9_].",
+        )));
+    }
+    let mut conn = <PgConnection as sqlx::Connection>::connect_with(&PgConnectOptions::from_str(
+        database_url,
+    )?)
+    .await?;
+    if fresh {
+        conn.execute(format!("DROP SCHEMA IF EXISTS {} CASCADE", schema).as_str())
+            .await?;
+    }
+    conn.execute(format!("CREATE SCHEMA IF NOT EXISTS {}", schema).as_str())
+        .await?;
+    let delimiter = if database_url.contains('?') { '&' } else { '?' };
+    Ok(format!(
+        "{}{}options=-c%20search_path%3D{}",
+        database_url, delimiter, schema
+    ))
+}
+
+/// Migrates the database of `database_url` and runs `script` on it, like the
+/// fixtures of `test-data/`.
+pub async fn seed(database_url: &str, script: &str) -> Result<(), VoteErrorKind<'static>> {
+    let pool = create_pool(database_url).await?;
+    pool.execute(script).await?;
+    pool.close().await;
+    Ok(())
+}
+
+async fn migrate(pool: &sqlx::PgPool) -> Result<(), VoteErrorKind<'static>> {
+    let applied = run_migrations(pool).await?;
+    info!("Schema version {}, applied {:?}", latest_version(), applied);
+    Ok(())
+}
+
+pub async fn connection() -> Result<PooledConnection, VoteErrorKind<'static>> {
+    let pool = pool().await?;
+    on_pool_runtime(async move { Ok(pool.acquire().await?) }).await
+}
+
+pub async fn begin() -> Result<Transaction, VoteErrorKind<'static>> {
+    let pool = pool().await?;
+    on_pool_runtime(async move { Ok(pool.begin().await?) }).await
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          MIGRATIONS                     //
+//                                         //
+/////////////////////////////////////////////
+
+pub fn latest_version() -> &'static str {
+    MIGRATIONS.last().map(|m| m.0).unwrap_or_default()
+}
+
+/// Applies every pending migration and returns the versions, which were
+/// applied. PostgreSQL migrates its schema transactional, all or nothing.
+pub async fn run_migrations(
+    pool: &sqlx::PgPool,
+) -> Result<Vec<&'static str>, VoteErrorKind<'static>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *tx)
+        .await?;
+    tx.execute(
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ( version TEXT PRIMARY KEY NOT NULL, applied_at TEXT NOT NULL )",
+            SCHEMA_VERSION_TABLE
+        )
+        .as_str(),
+    )
+    .await?;
+    let applied: Vec<String> = sqlx::query(&format!(
+        "SELECT version FROM {} ORDER BY version",
+        SCHEMA_VERSION_TABLE
+    ))
+    .fetch_all(&mut *tx)
+    .await?
+    .iter()
+    .filter_map(|r| r.try_get::<String, usize>(0).ok())
+    .collect();
+    if let Some(unknown) = applied
+        .iter()
+        .find(|v| !MIGRATIONS.iter().any(|m| &m.0 == v))
+    {
+        warn!(
+            "The database knows migration {}, which this build doesn't.",
+            unknown
+        );
+    }
+    let mut newly_applied = Vec::new();
+    for (version, up) in MIGRATIONS {
+        if applied.iter().any(|v| v == version) {
+            continue;
+        }
+        info!("Applying migration {}", version);
+        tx.execute(*up).await?;
+        sqlx::query(&format!(
+            "INSERT INTO {} ( version, applied_at ) VALUES ( $1, $2 )",
+            SCHEMA_VERSION_TABLE
+        ))
+        .bind(*version)
+        .bind(Utc::now().to_string())
+        .execute(&mut *tx)
+        .await?;
+        newly_applied.push(*version);
+    }
+    tx.commit().await?;
+    Ok(newly_applied)
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          TYPED VALUES                   //
+//                                         //
+/////////////////////////////////////////////
+
+fn bind_value<'q>(query: PgQuery<'q>, value: &DbValue) -> PgQuery<'q> {
+    match value {
+        DbValue::Text(t) => query.bind(t.clone()),
+        DbValue::Integer(i) => query.bind(*i),
+        DbValue::Real(r) => query.bind(*r),
+        DbValue::Bool(b) => query.bind(*b),
+        // A typed NULL, PostgreSQL casts float to text or integer columns on
+        // insert, but text not to numbers.
+        DbValue::Null => query.bind(Option::<f64>::None),
+    }
+}
+
+/// Maps a column by its PostgreSQL type.
+fn get_db_value(row: &PgRow, column_name: &str) -> DbValue {
+    let type_name = match row.try_get_raw(column_name) {
+        Ok(raw) if raw.is_null() => return DbValue::Null,
+        Ok(raw) => raw.type_info().name().to_string(),
+        Err(e) => {
+            error!("{:?}", e);
+            return DbValue::Null;
+        }
+    };
+    let value = match type_name.as_str() {
+        "BOOL" => row.try_get::<bool, &str>(column_name).map(DbValue::Bool),
+        "INT2" => row
+            .try_get::<i16, &str>(column_name)
+            .map(|i| DbValue::Integer(i64::from(i))),
+        "INT4" => row
+            .try_get::<i32, &str>(column_name)
+            .map(|i| DbValue::Integer(i64::from(i))),
+        "INT8" => row.try_get::<i64, &str>(column_name).map(DbValue::Integer),
+        "FLOAT4" => row
+            .try_get::<f32, &str>(column_name)
+            .map(|r| DbValue::Real(f64::from(r))),
+        "FLOAT8" => row.try_get::<f64, &str>(column_name).map(DbValue::Real),
+        _ => row.try_get::<String, &str>(column_name).map(DbValue::Text),
+    };
+    match value {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{:?}", e);
+            DbValue::Null
+        }
+    }
+}
+
+/// Every column of the row, typed by `get_db_value`.
+impl<'r> sqlx::FromRow<'r, PgRow> for DbRow {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(DbRow(
+            row.columns()
+                .iter()
+                .map(|c| (c.name().to_string(), get_db_value(row, c.name())))
+                .collect(),
+        ))
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          QUERIES                        //
+//                                         //
+/////////////////////////////////////////////
+
+pub async fn is_unique(
+    conn: &mut PgConnection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let sql_string = Dialect::Postgres.placeholders(&is_unique_statement(table, identity_column));
+    info!("IS_UNIQUE SQL: {:?}", sql_string);
+    match sqlx::query(&sql_string).bind(name).fetch_all(conn).await {
+        Ok(r) => Ok(r.len() != 1),
+        Err(_e) => Ok(true),
+    }
+}
+
+pub async fn update(
+    conn: &mut PgConnection,
+    table: &str,
+    columns: &str,
+    values: &[DbValue],
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let column_names: Vec<&str> = get_columns(columns).iter().map(|c| c.trim()).collect();
+    if values.is_empty() || column_names.len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string =
+        Dialect::Postgres.placeholders(&update_statement(table, &column_names, identity_column));
+    info!("UPDATE SQL: {:?}", sql_string);
+    let query = values
+        .iter()
+        .fold(sqlx::query(&sql_string), |query, value| {
+            bind_value(query, value)
+        });
+    let rows = query
+        .bind(cleanup_name(table.trim(), name))
+        .execute(conn)
+        .await?
+        .rows_affected();
+
+    if rows > 0 {
+        Ok(true)
+    } else {
+        Err(FromErrorKind::MultipleRows(true).into())
+    }
+}
+
+/// Deletes the row of `name`, the rows of its join tables are up to the caller.
+pub async fn delete(
+    conn: &mut PgConnection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let sql_string = Dialect::Postgres.placeholders(&delete_row_statement(table, identity_column));
+    info!("DELETE SQL: {:?}", sql_string);
+    let rows = sqlx::query(&sql_string)
+        .bind(cleanup_name(table.trim(), name))
+        .execute(conn)
+        .await?
+        .rows_affected();
+
+    if rows > 0 {
+        Ok(true)
+    } else {
+        Err(FromErrorKind::MultipleRows(false).into())
+    }
+}
+
+pub async fn select(
+    conn: &mut PgConnection,
+    table: &str,
+    object_columns: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<Option<DbRow>, VoteErrorKind<'static>> {
+    let sql_string =
+        Dialect::Postgres.placeholders(&select_statement(table, object_columns, identity_column));
+    info!("SELECT SQL: {:?}", sql_string);
+    match sqlx::query_as::<_, DbRow>(&sql_string)
+        .bind(cleanup_name(table.trim(), name))
+        .fetch_optional(conn)
+        .await
+    {
+        Ok(r) => Ok(r),
+        Err(e) => {
+            warn!("Selecting one failed: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+pub async fn list_rows(
+    conn: &mut PgConnection,
+    table: &str,
+    object_columns: &str,
+    column_name: &str,
+    all: bool,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let sql_string = format!("SELECT {} FROM {} ", object_columns, table.trim());
+    info!("SELECT_ALL SQL: {}", sql_string);
+    let rows = sqlx::query_as::<_, DbRow>(&sql_string)
+        .fetch_all(conn)
+        .await?;
+    let result: Vec<String> = rows
+        .iter()
+        .map(|r| list_entry(r, object_columns, column_name, all))
+        .collect();
+    debug!("Extracted list_rows: {:?}", result);
+    Ok(result)
+}
+
+pub async fn save(
+    conn: &mut PgConnection,
+    table: &str,
+    columns: &str,
+    values: &[DbValue],
+) -> Result<String, VoteErrorKind<'static>> {
+    if values.is_empty() || get_columns(columns).len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string =
+        Dialect::Postgres.placeholders(&insert_statement(table, columns, values.len()));
+    info!("INSERT SQL: {}", sql_string);
+    let query = values
+        .iter()
+        .fold(sqlx::query(&sql_string), |query, value| {
+            bind_value(query, value)
+        });
+    match query.execute(conn).await {
+        Ok(_) => Ok(String::from(table.trim())),
+        Err(e) => {
+            error!("{:?}", e);
+            Err(VoteErrorKind::DB(e))
+        }
+    }
+}
+
+/// Replaces every row of `owner` in a join table, like the candidates of a
+/// voting or the votes of a ballot.
+pub async fn replace_rows(
+    conn: &mut PgConnection,
+    table: &str,
+    owner_column: &str,
+    owner: &str,
+    columns: &str,
+    rows: &[Vec<DbValue>],
+) -> Result<u64, VoteErrorKind<'static>> {
+    let sql_string = Dialect::Postgres.placeholders(&delete_statement(table, owner_column));
+    info!("DELETE SQL: {}", sql_string);
+    sqlx::query(&sql_string)
+        .bind(owner)
+        .execute(&mut *conn)
+        .await?;
+    let mut inserted = 0;
+    for row in rows {
+        save(&mut *conn, table, columns, row).await?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+/// Ballots of a voting, optionally only the ones of a voter or candidate.
+pub async fn select_ballots(
+    conn: &mut PgConnection,
+    voting: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Result<Vec<BallotRow>, VoteErrorKind<'static>> {
+    if !is_valid_ballot_query(filter.map(|(c, _)| c), order_by) {
+        return Err(VoteErrorKind::Internal(String::from(
+            "Unknown column for ballots.",
+        )));
+    }
+    let sql_string = select_ballots_statement(Dialect::Postgres, filter.map(|(c, _)| c), order_by);
+    info!("SELECT BALLOTS SQL: {}", sql_string);
+    let mut query = sqlx::query(&sql_string).bind(voting.to_lowercase());
+    if let Some((_, value)) = filter {
+        for _ in 0..BALLOT_FILTER_BINDS {
+            query = query.bind(value.to_string());
+        }
+    }
+    let rows = query.fetch_all(conn).await?;
+    Ok(rows
+        .iter()
+        .map(|r| BallotRow {
+            voting: r.try_get("voting").unwrap_or_default(),
+            voter: r.try_get("voter").unwrap_or_default(),
+            candidate: r.try_get("candidate").unwrap_or_default(),
+            sum: r.try_get("sum").unwrap_or_default(),
+            weighted: r.try_get("weighted").unwrap_or_default(),
+            mean: r.try_get("mean").unwrap_or_default(),
+            notes: r.try_get("notes").unwrap_or_default(),
+            votes: r.try_get("votes").unwrap_or_else(|_| String::from("[]")),
+            voted_on: r.try_get("voted_on").unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::{skips_without, Database};
+
+    #[test]
+    fn every_migration_is_embedded() {
+        let mut dirs: Vec<String> = std::fs::read_dir("migrations_postgres")
+            .unwrap()
+            .map(|d| d.unwrap())
+            .filter(|d| d.path().is_dir())
+            .map(|d| d.file_name().to_string_lossy().to_string())
+            .collect();
+        dirs.sort();
+        let versions: Vec<&str> = MIGRATIONS.iter().map(|m| m.0).collect();
+        assert_eq!(dirs, versions);
+    }
+
+    #[rocket::async_test]
+    async fn quotes_are_stored_as_data() {
+        if skips_without(Database::Postgres) {
+            return;
+        }
+        let mut tx = begin().await.unwrap();
+        let label = String::from("O'Brien'); DROP TABLE candidates; --");
+        let values = vec![
+            DbValue::Bool(true),
+            DbValue::Text(String::from("voter_obrien")),
+            DbValue::Text(label.clone()),
+        ];
+        save(&mut tx, "candidates", "voter, id, label", &values)
+            .await
+            .unwrap();
+        let row = select(
+            &mut tx,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "Voter_OBrien",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.get("voter"), Some(&DbValue::Bool(true)));
+        assert_eq!(row.text("label"), label);
+
+        let new_label = String::from("Robert'); --");
+        let values = [DbValue::Text(new_label.clone())];
+        assert!(update(
+            &mut tx,
+            "candidates",
+            "label",
+            &values,
+            "id",
+            "voter_obrien"
+        )
+        .await
+        .unwrap());
+        let row = select(
+            &mut tx,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_obrien",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.text("label"), new_label);
+        tx.rollback().await.unwrap();
+
+        let mut conn = connection().await.unwrap();
+        let row = select(
+            &mut conn,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_obrien",
+        )
+        .await
+        .unwrap();
+        assert!(row.is_none());
+    }
+
+    #[rocket::async_test]
+    async fn migrates_once_and_computes_results() {
+        if skips_without(Database::Postgres) {
+            return;
+        }
+        let pool = pool().await.unwrap();
+        assert!(on_pool_runtime(run_migrations(pool))
+            .await
+            .unwrap()
+            .is_empty());
+
+        let mut conn = connection().await.unwrap();
+        let criteria = list_rows(
+            &mut conn,
+            "criteria",
+            "name, min, max, weight",
+            "name",
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(criteria.contains(&String::from("Style_0_10_15")));
+
+        let ranked = select_ballots(&mut conn, "Voting", None, "sum")
+            .await
+            .unwrap();
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0].sum, 12);
+        assert_eq!(ranked[1].weighted, 21.0);
+        assert_eq!(ranked[1].mean, 7.0);
+        assert!(ranked[1].votes.contains("\"Style\""));
+
+        let by_voter = select_ballots(&mut conn, "voting", Some(("voter", "OBAMA")), "id")
+            .await
+            .unwrap();
+        assert_eq!(by_voter.len(), 2);
+        assert!(
+            select_ballots(&mut conn, "voting", Some(("notes", "")), "id")
+                .await
+                .is_err()
+        );
+    }
+}
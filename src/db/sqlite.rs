@@ -1,52 +1,672 @@
 use crate::{
-    ballots::BallotsTable,
-    common::{Empty, Voting, VotingTable, Votings},
+    common::{DbRow, DbValue},
+    db::statements::*,
+    error::{FromErrorKind, VoteErrorKind},
     persistence::PersistenceMode,
-    // db::common::QueryableExt
 };
-use diesel::{Connection, SqliteConnection};
-use rocket_sync_db_pools::{database, diesel};
-type Result<T, E = rocket::response::Debug<diesel::result::Error>> = std::result::Result<T, E>;
-pub trait EmptySelectable: Empty + diesel::Selectable<DB> {}
-
-async fn query_votings(conn: &mut SqliteConnection) -> Result<Votings> {
-    use crate::common::votings::dsl::votings;
-    use diesel::RunQueryDsl;
-    let rows: Vec<VotingTable> = crate::common::votings::table
-        .load(conn)
-        .expect("Error loading posts");
-    Ok(Votings::from(rows))
-}
-pub async fn query_voting<T: EmptySelectable>(conn: &mut SqliteConnection, id: &str) -> Result<T> {
-    use crate::common::votings::dsl::votings;
-    use diesel::{OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
-    let voting = crate::common::votings::table
-        .find(id)
-        .select(T::as_select())
-        .first(conn);
-    match voting {
-        Ok(v) => Ok(v),
-        Err(_) => Ok(T::empty()),
-    }
-}
-
-fn query_ballots(conn: &mut SqliteConnection, ballots: crate::common::CastBallots) -> BallotsTable {
-    use crate::ballots::ballots::dsl::*;
-    use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
-    conn.transaction(|conn| {
-        diesel::insert_into(crate::ballots::ballots::table)
-            .values(crate::ballots::ballots::table::from(ballots))
-            .execute(conn)?;
+use diesel::{
+    connection::{AnsiTransactionManager, SimpleConnection, TransactionManager},
+    deserialize,
+    migration::MigrationSource,
+    query_builder::{BoxedSqlQuery, SqlQuery},
+    r2d2::{self, ConnectionManager, CustomizeConnection, Pool},
+    row::NamedRow,
+    sql_types::{BigInt, Bool, Double, Nullable, Text},
+    sqlite::Sqlite,
+    QueryableByName, RunQueryDsl,
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use rocket::{
+    debug, error,
+    fairing::AdHoc,
+    figment::Figment,
+    info,
+    serde::json::{self, Value},
+    warn,
+};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Mutex, OnceLock},
+};
+
+type SqliteQuery<'q> = BoxedSqlQuery<'q, Sqlite, SqlQuery>;
+/// The connection the queries of this backend run on.
+pub type Connection = diesel::SqliteConnection;
+pub type PooledConnection = r2d2::PooledConnection<ConnectionManager<Connection>>;
+
+/// Every migration of `migrations/`, applied by `diesel_migrations`.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+/// Kept by the sqlx backend, databases set up with it are adopted.
+const SCHEMA_VERSION_TABLE: &str = "schema_version";
+
+/////////////////////////////////////////////
+//                                         //
+//               POOL                      //
+//                                         //
+/////////////////////////////////////////////
 
-        crate::ballots::ballots::table
-            .order(crate::ballots::ballots::voted_on.desc())
-            .select(BallotsTable::as_select())
-            .first(conn)
+/// The r2d2 pool, shared with the `Query` trait like the one of the sqlx
+/// backend.
+static POOL: OnceLock<Pool<ConnectionManager<Connection>>> = OnceLock::new();
+
+/// SQLite doesn't enforce `REFERENCES` unless it is told so per connection.
+#[derive(Debug)]
+struct ConnectionOptions;
+impl CustomizeConnection<Connection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut Connection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Rocket config with `databases.voters_verdict.url` taken from `DATABASE_URL`.
+pub fn figment() -> Result<Figment, VoteErrorKind<'static>> {
+    Ok(rocket::Config::figment().merge((
+        "databases.voters_verdict.url",
+        PersistenceMode::to_conform_path()?,
+    )))
+}
+
+/// Creates the pool out of `databases.voters_verdict.url` and migrates it.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("SQLite pool", |rocket| async {
+        let database_url = match rocket
+            .figment()
+            .extract_inner::<String>("databases.voters_verdict.url")
+        {
+            Ok(url) => url,
+            Err(e) => {
+                error!("No database configured: {:?}", e);
+                return Err(rocket);
+            }
+        };
+        match create_pool(&database_url) {
+            Ok(pool) => {
+                let _ = POOL.set(pool);
+                Ok(rocket)
+            }
+            Err(e) => {
+                error!("Migrating the database failed: {:?}", e);
+                Err(rocket)
+            }
+        }
     })
-    .expect("Error while saving post")
 }
-pub fn establish_connection() -> SqliteConnection {
-    let database_url = PersistenceMode::to_conform_path();
-    SqliteConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+
+fn create_pool(
+    database_url: &str,
+) -> Result<Pool<ConnectionManager<Connection>>, VoteErrorKind<'static>> {
+    let pool = Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
+        .build(ConnectionManager::<Connection>::new(database_url))?;
+    migrate(&mut *pool.get()?)?;
+    Ok(pool)
+}
+
+/// Returns the shared pool. Outside of a rocket, like in the admin binary or
+/// in tests, the pool is created once on first use.
+pub async fn pool() -> Result<&'static Pool<ConnectionManager<Connection>>, VoteErrorKind<'static>>
+{
+    match POOL.get() {
+        Some(pool) => Ok(pool),
+        None => init_pool(),
+    }
+}
+
+fn init_pool() -> Result<&'static Pool<ConnectionManager<Connection>>, VoteErrorKind<'static>> {
+    // Only one caller creates and migrates the pool.
+    static INIT: Mutex<()> = Mutex::new(());
+    let _guard = INIT.lock().map_err(internal)?;
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+    #[cfg(not(test))]
+    let pool = create_pool(&PersistenceMode::database_url(
+        super::Database::DieselSqlite,
+    )?)?;
+    #[cfg(test)]
+    let pool = {
+        let pool = create_pool(&crate::db::test_database("diesel").to_string_lossy())?;
+        pool.get()?.batch_execute(crate::db::TEST_SEED)?;
+        pool
+    };
+    Ok(POOL.get_or_init(|| pool))
+}
+
+/// Migrates the database of `database_url` and runs `script` on it, like the
+/// fixtures of `test-data/`.
+pub async fn seed(database_url: &str, script: &str) -> Result<(), VoteErrorKind<'static>> {
+    create_pool(database_url)?.get()?.batch_execute(script)?;
+    Ok(())
+}
+
+/// Records the migrations the sqlx backend already ran, then applies the
+/// pending ones and records them for the sqlx backend as well.
+fn migrate(conn: &mut Connection) -> Result<(), VoteErrorKind<'static>> {
+    let migrations = MigrationSource::<Sqlite>::migrations(&MIGRATIONS).map_err(internal)?;
+    if table_exists(conn, SCHEMA_VERSION_TABLE)? {
+        let applied = conn.applied_migrations().map_err(internal)?;
+        let recorded = text_column(
+            conn,
+            &format!("SELECT version AS value FROM {}", SCHEMA_VERSION_TABLE),
+        )?;
+        for migration in migrations.iter() {
+            let version = migration.name().version();
+            if recorded.contains(&migration.name().to_string()) && !applied.contains(&version) {
+                info!("Adopting migration {} of sqlx.", migration.name());
+                diesel::sql_query(
+                    "INSERT INTO __diesel_schema_migrations ( version ) VALUES ( ? )",
+                )
+                .bind::<Text, _>(version.to_string())
+                .execute(conn)?;
+            }
+        }
+    } else {
+        conn.batch_execute(&format!(
+            "CREATE TABLE {} ( version TEXT PRIMARY KEY NOT NULL, applied_at TIMESTAMP NOT NULL )",
+            SCHEMA_VERSION_TABLE
+        ))?;
+    }
+    let applied: Vec<String> = conn
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(internal)?
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+    for migration in migrations.iter() {
+        if applied.contains(&migration.name().version().to_string()) {
+            diesel::sql_query(format!(
+                "INSERT OR IGNORE INTO {} ( version, applied_at ) VALUES ( ?, ? )",
+                SCHEMA_VERSION_TABLE
+            ))
+            .bind::<Text, _>(migration.name().to_string())
+            .bind::<Text, _>(chrono::Utc::now().to_string())
+            .execute(conn)?;
+        }
+    }
+    info!(
+        "Schema version {}, applied {:?}",
+        migrations
+            .last()
+            .map(|m| m.name().to_string())
+            .unwrap_or_default(),
+        applied
+    );
+    Ok(())
+}
+
+fn internal<E: std::fmt::Display>(error: E) -> VoteErrorKind<'static> {
+    VoteErrorKind::Internal(error.to_string())
+}
+
+pub async fn connection() -> Result<PooledConnection, VoteErrorKind<'static>> {
+    Ok(pool().await?.get()?)
+}
+
+/// A transaction on a pooled connection, rolled back unless committed.
+pub struct Transaction {
+    conn: PooledConnection,
+    open: bool,
+}
+impl Transaction {
+    pub async fn commit(mut self) -> Result<(), diesel::result::Error> {
+        self.open = false;
+        AnsiTransactionManager::commit_transaction(&mut *self.conn)
+    }
+}
+impl Deref for Transaction {
+    type Target = Connection;
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+impl DerefMut for Transaction {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if self.open {
+            if let Err(e) = AnsiTransactionManager::rollback_transaction(&mut *self.conn) {
+                error!("Rolling back failed: {:?}", e);
+            }
+        }
+    }
+}
+
+pub async fn begin() -> Result<Transaction, VoteErrorKind<'static>> {
+    let mut conn = connection().await?;
+    AnsiTransactionManager::begin_transaction(&mut *conn)?;
+    Ok(Transaction { conn, open: true })
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          TYPED VALUES                   //
+//                                         //
+/////////////////////////////////////////////
+
+/// A row of `json_array(..)`, diesel only loads rows of known columns.
+struct JsonRow {
+    value: String,
+}
+impl QueryableByName<Sqlite> for JsonRow {
+    fn build<'a>(row: &impl NamedRow<'a, Sqlite>) -> deserialize::Result<Self> {
+        Ok(Self {
+            value: NamedRow::get::<Text, String>(row, "value")?,
+        })
+    }
+}
+
+fn bind_value<'q>(query: SqliteQuery<'q>, value: &DbValue) -> SqliteQuery<'q> {
+    match value {
+        DbValue::Text(t) => query.bind::<Text, _>(t.clone()),
+        DbValue::Integer(i) => query.bind::<BigInt, _>(*i),
+        DbValue::Real(r) => query.bind::<Double, _>(*r),
+        DbValue::Bool(b) => query.bind::<Bool, _>(*b),
+        DbValue::Null => query.bind::<Nullable<Text>, _>(Option::<String>::None),
+    }
+}
+
+fn text_column(
+    conn: &mut Connection,
+    sql_string: &str,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    Ok(diesel::sql_query(sql_string)
+        .load::<JsonRow>(conn)?
+        .into_iter()
+        .map(|r| r.value)
+        .collect())
+}
+
+fn table_exists(conn: &mut Connection, table: &str) -> Result<bool, VoteErrorKind<'static>> {
+    Ok(diesel::sql_query(
+        "SELECT name AS value FROM sqlite_master WHERE type = 'table' AND name = ?",
+    )
+    .bind::<Text, _>(table)
+    .load::<JsonRow>(conn)?
+    .len()
+        == 1)
+}
+
+/// Columns declared as `BOOLEAN`, SQLite stores them as integers.
+fn bool_columns(conn: &mut Connection, table: &str) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    Ok(diesel::sql_query(
+        "SELECT name AS value FROM pragma_table_info(?) WHERE upper(type) = 'BOOLEAN'",
+    )
+    .bind::<Text, _>(table.trim().to_string())
+    .load::<JsonRow>(conn)?
+    .into_iter()
+    .map(|r| r.value)
+    .collect())
+}
+
+/// Maps a value of `json_array` by its JSON type, booleans by the declared
+/// type of the column.
+fn get_db_value(value: &Value, declared_bool: bool) -> DbValue {
+    match value {
+        Value::Null => DbValue::Null,
+        Value::Bool(b) => DbValue::Bool(*b),
+        Value::Number(n) if declared_bool => DbValue::Bool(n.as_i64().unwrap_or_default() != 0),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => DbValue::Integer(i),
+            None => DbValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => DbValue::Text(s.clone()),
+        other => DbValue::Text(other.to_string()),
+    }
+}
+
+/// Selects the columns as one JSON array per row and maps them to `DbValue`s.
+fn select_values(
+    conn: &mut Connection,
+    query: SqliteQuery,
+    table: &str,
+    columns: &[&str],
+) -> Result<Vec<DbRow>, VoteErrorKind<'static>> {
+    let booleans = bool_columns(conn, table)?;
+    let rows = query.load::<JsonRow>(conn)?;
+    let mut result = Vec::new();
+    for row in rows {
+        let values: Vec<Value> = json::from_str(&row.value)?;
+        result.push(DbRow(
+            columns
+                .iter()
+                .zip(values.iter())
+                .map(|(c, v)| {
+                    (
+                        c.to_string(),
+                        get_db_value(v, booleans.iter().any(|b| b == c)),
+                    )
+                })
+                .collect(),
+        ));
+    }
+    Ok(result)
+}
+
+fn json_columns(columns: &[&str]) -> String {
+    format!("json_array( {} ) AS value", columns.join(", "))
+}
+
+fn trimmed_columns(object_columns: &str) -> Vec<&str> {
+    get_columns(object_columns)
+        .iter()
+        .map(|c| c.trim())
+        .collect()
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          QUERIES                        //
+//                                         //
+/////////////////////////////////////////////
+
+pub async fn is_unique(
+    conn: &mut Connection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let sql_string = select_statement(
+        table,
+        &format!("CAST({} AS TEXT) AS value", identity_column),
+        identity_column,
+    );
+    info!("IS_UNIQUE SQL: {:?}", sql_string);
+    match diesel::sql_query(&sql_string)
+        .bind::<Text, _>(name)
+        .load::<JsonRow>(conn)
+    {
+        Ok(r) => Ok(r.len() != 1),
+        Err(_e) => Ok(true),
+    }
+}
+
+// UPDATE table_name
+// SET column1 = ?, column2 = ?, ...
+// WHERE condition = ?;
+pub async fn update(
+    conn: &mut Connection,
+    table: &str,
+    columns: &str,
+    values: &[DbValue],
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let column_names: Vec<&str> = get_columns(columns).iter().map(|c| c.trim()).collect();
+    if values.is_empty() || column_names.len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string = update_statement(table, &column_names, identity_column);
+    info!("UPDATE SQL: {:?}", sql_string);
+    let query = values.iter().fold(
+        diesel::sql_query(&sql_string).into_boxed::<Sqlite>(),
+        |query, value| bind_value(query, value),
+    );
+    let rows = query
+        .bind::<Text, _>(cleanup_name(table.trim(), name).to_string())
+        .execute(conn)?;
+
+    if rows > 0 {
+        Ok(true)
+    } else {
+        Err(FromErrorKind::MultipleRows(true).into())
+    }
+}
+
+/// Deletes the row of `name`, the rows of its join tables are up to the caller.
+pub async fn delete(
+    conn: &mut Connection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let sql_string = delete_row_statement(table, identity_column);
+    info!("DELETE SQL: {:?}", sql_string);
+    let rows = diesel::sql_query(&sql_string)
+        .bind::<Text, _>(cleanup_name(table.trim(), name).to_string())
+        .execute(conn)?;
+
+    if rows > 0 {
+        Ok(true)
+    } else {
+        Err(FromErrorKind::MultipleRows(false).into())
+    }
+}
+
+pub async fn select(
+    conn: &mut Connection,
+    table: &str,
+    object_columns: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<Option<DbRow>, VoteErrorKind<'static>> {
+    let columns = trimmed_columns(object_columns);
+    let sql_string = select_statement(table, &json_columns(&columns), identity_column);
+    info!("SELECT SQL: {:?}", sql_string);
+    let query = diesel::sql_query(&sql_string)
+        .into_boxed::<Sqlite>()
+        .bind::<Text, _>(cleanup_name(table.trim(), name).to_string());
+    match select_values(conn, query, table, &columns) {
+        Ok(rows) => Ok(rows.into_iter().next()),
+        Err(e) => {
+            warn!("Selecting one failed: {}", e.to_string());
+            Err(e)
+        }
+    }
+}
+
+pub async fn list_rows(
+    conn: &mut Connection,
+    table: &str,
+    object_columns: &str,
+    column_name: &str,
+    all: bool,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let columns = trimmed_columns(object_columns);
+    let sql_string = format!("SELECT {} FROM {} ", json_columns(&columns), table.trim());
+    info!("SELECT_ALL SQL: {}", sql_string);
+    let query = diesel::sql_query(&sql_string).into_boxed::<Sqlite>();
+    let rows = select_values(conn, query, table, &columns)?;
+    let result: Vec<String> = rows
+        .iter()
+        .map(|r| list_entry(r, object_columns, column_name, all))
+        .collect();
+    debug!("Extracted list_rows: {:?}", result);
+    Ok(result)
+}
+
+pub async fn save(
+    conn: &mut Connection,
+    table: &str,
+    columns: &str,
+    values: &[DbValue],
+) -> Result<String, VoteErrorKind<'static>> {
+    if values.is_empty() || get_columns(columns).len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string = insert_statement(table, columns, values.len());
+    info!("INSERT SQL: {}", sql_string);
+    let query = values.iter().fold(
+        diesel::sql_query(&sql_string).into_boxed::<Sqlite>(),
+        |query, value| bind_value(query, value),
+    );
+    match query.execute(conn) {
+        Ok(_) => Ok(String::from(table.trim())),
+        Err(e) => {
+            error!("{:?}", e);
+            Err(VoteErrorKind::Diesel(e))
+        }
+    }
+}
+
+/// Replaces every row of `owner` in a join table, like the candidates of a
+/// voting or the votes of a ballot.
+pub async fn replace_rows(
+    conn: &mut Connection,
+    table: &str,
+    owner_column: &str,
+    owner: &str,
+    columns: &str,
+    rows: &[Vec<DbValue>],
+) -> Result<u64, VoteErrorKind<'static>> {
+    let sql_string = delete_statement(table, owner_column);
+    info!("DELETE SQL: {}", sql_string);
+    diesel::sql_query(&sql_string)
+        .bind::<Text, _>(owner)
+        .execute(&mut *conn)?;
+    let mut inserted = 0;
+    for row in rows {
+        save(&mut *conn, table, columns, row).await?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          RESULTS                        //
+//                                         //
+/////////////////////////////////////////////
+
+impl QueryableByName<Sqlite> for BallotRow {
+    fn build<'a>(row: &impl NamedRow<'a, Sqlite>) -> deserialize::Result<Self> {
+        Ok(Self {
+            voting: NamedRow::get::<Text, String>(row, "voting")?,
+            voter: NamedRow::get::<Text, String>(row, "voter")?,
+            candidate: NamedRow::get::<Text, String>(row, "candidate")?,
+            sum: NamedRow::get::<BigInt, i64>(row, "sum")?,
+            weighted: NamedRow::get::<Double, f64>(row, "weighted")?,
+            mean: NamedRow::get::<Double, f64>(row, "mean")?,
+            notes: NamedRow::get::<Text, String>(row, "notes")?,
+            votes: NamedRow::get::<Nullable<Text>, Option<String>>(row, "votes")?
+                .unwrap_or_else(|| String::from("[]")),
+            voted_on: NamedRow::get::<Text, String>(row, "voted_on")?,
+        })
+    }
+}
+
+/// Ballots of a voting, optionally only the ones of a voter or candidate.
+pub async fn select_ballots(
+    conn: &mut Connection,
+    voting: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Result<Vec<BallotRow>, VoteErrorKind<'static>> {
+    if !is_valid_ballot_query(filter.map(|(c, _)| c), order_by) {
+        return Err(VoteErrorKind::Internal(String::from(
+            "Unknown column for ballots.",
+        )));
+    }
+    let sql_string = select_ballots_statement(Dialect::Sqlite, filter.map(|(c, _)| c), order_by);
+    info!("SELECT BALLOTS SQL: {}", sql_string);
+    let mut query = diesel::sql_query(&sql_string)
+        .into_boxed::<Sqlite>()
+        .bind::<Text, _>(voting.to_lowercase());
+    if let Some((_, value)) = filter {
+        for _ in 0..BALLOT_FILTER_BINDS {
+            query = query.bind::<Text, _>(value.to_string());
+        }
+    }
+    Ok(query.load::<BallotRow>(conn)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diesel::Connection as _;
+
+    fn memory_connection() -> Connection {
+        let mut conn = Connection::establish(":memory:").unwrap();
+        conn.batch_execute("PRAGMA foreign_keys = ON;").unwrap();
+        conn
+    }
+
+    #[rocket::async_test]
+    async fn quotes_are_stored_as_data() {
+        let mut conn = memory_connection();
+        conn.batch_execute(
+            "CREATE TABLE candidates ( voter BOOLEAN, id TEXT PRIMARY KEY, label TEXT )",
+        )
+        .unwrap();
+        let label = String::from("O'Brien'); DROP TABLE candidates; --");
+        let values = vec![
+            DbValue::Bool(true),
+            DbValue::Text(String::from("voter_obrien")),
+            DbValue::Text(label.clone()),
+        ];
+        save(&mut conn, "candidates", "voter, id, label", &values)
+            .await
+            .unwrap();
+        let row = select(
+            &mut conn,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_obrien",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.get("voter"), Some(&DbValue::Bool(true)));
+        assert_eq!(row.text("label"), label.clone());
+
+        let new_label = String::from("Robert'); --");
+        let values = [DbValue::Text(new_label.clone())];
+        assert!(update(
+            &mut conn,
+            "candidates",
+            "label",
+            &values,
+            "id",
+            "voter_obrien"
+        )
+        .await
+        .unwrap());
+        let rows = list_rows(&mut conn, "candidates", "voter, id, label", "id", true)
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![String::from("true_voter_obrien_Robert'); --")]);
+        assert!(!is_unique(&mut conn, "candidates", "id", "voter_obrien")
+            .await
+            .unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn migrates_once_and_computes_results() {
+        let mut conn = memory_connection();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        let recorded = text_column(
+            &mut conn,
+            &format!("SELECT version AS value FROM {}", SCHEMA_VERSION_TABLE),
+        )
+        .unwrap();
+        assert_eq!(
+            recorded.len(),
+            MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+                .unwrap()
+                .len()
+        );
+        conn.batch_execute(crate::db::TEST_SEED).unwrap();
+
+        let ranked = select_ballots(&mut conn, "Voting", None, "sum")
+            .await
+            .unwrap();
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0].sum, 12);
+        assert_eq!(ranked[1].weighted, 21.0);
+        let by_voter = select_ballots(&mut conn, "voting", Some(("voter", "obama")), "id")
+            .await
+            .unwrap();
+        assert_eq!(by_voter.len(), 2);
+    }
 }
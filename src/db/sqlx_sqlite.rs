@@ -1,32 +1,204 @@
 use crate::{
-    common::{cleanup_delimiter, DELIMITER},
+    common::{DbRow, DbValue},
+    db::{migrations, statements::*},
     error::{FromErrorKind, VoteErrorKind},
     persistence::PersistenceMode,
 };
-use futures::future::TryFutureExt;
-use rocket::{debug, error, info, warn};
+use rocket::tokio::sync::Mutex;
+use rocket::{debug, error, fairing::AdHoc, figment::Figment, info, warn};
 use rocket_db_pools::{
     sqlx,
-    sqlx::{sqlite::SqliteRow, Column, Row},
+    sqlx::{
+        pool::PoolConnection,
+        sqlite::{SqliteArguments, SqliteConnectOptions, SqliteConnection, SqliteRow},
+        Column, Executor, Row, Sqlite, TypeInfo, ValueRef,
+    },
+    Database,
 };
-use std::collections::BTreeMap;
+use std::{str::FromStr, sync::OnceLock};
 
-fn get_columns(column_string: &str) -> Vec<&str> {
-    column_string.split(',').collect()
+type SqliteQuery<'q> = sqlx::query::Query<'q, Sqlite, SqliteArguments<'q>>;
+/// The connection the queries of this backend run on.
+pub type Connection = SqliteConnection;
+pub type PooledConnection = PoolConnection<Sqlite>;
+pub type Transaction = sqlx::Transaction<'static, Sqlite>;
+
+/////////////////////////////////////////////
+//                                         //
+//               POOL                      //
+//                                         //
+/////////////////////////////////////////////
+
+#[derive(Database)]
+#[database("voters_verdict")]
+pub struct VotersVerdictDb(sqlx::SqlitePool);
+
+/// The pool of `VotersVerdictDb`, shared with the `Query` trait, which isn't
+/// called from request guards only.
+static POOL: OnceLock<sqlx::SqlitePool> = OnceLock::new();
+
+/// Rocket config with `databases.voters_verdict.url` taken from `DATABASE_URL`.
+pub fn figment() -> Result<Figment, VoteErrorKind<'static>> {
+    Ok(rocket::Config::figment().merge((
+        "databases.voters_verdict.url",
+        PersistenceMode::to_conform_path()?,
+    )))
+}
+
+/// Initialises `VotersVerdictDb`, migrates it and shares its pool.
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("SQLite pool", |rocket| async {
+        rocket
+            .attach(VotersVerdictDb::init())
+            .attach(AdHoc::try_on_ignite("Share SQLite pool", |rocket| async {
+                match VotersVerdictDb::fetch(&rocket) {
+                    Some(db) => match migrate(&db.0).await {
+                        Ok(_) => {
+                            let _ = POOL.set(db.0.clone());
+                            Ok(rocket)
+                        }
+                        Err(e) => {
+                            error!("Migrating the database failed: {:?}", e);
+                            Err(rocket)
+                        }
+                    },
+                    None => Err(rocket),
+                }
+            }))
+    })
+}
+
+/// Returns the shared pool. Outside of a rocket, like in the admin binary or
+/// in tests, the pool is created once on first use.
+pub async fn pool() -> Result<&'static sqlx::SqlitePool, VoteErrorKind<'static>> {
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+    // Only one caller creates and migrates the pool.
+    static INIT: Mutex<()> = Mutex::const_new(());
+    let _guard = INIT.lock().await;
+    if let Some(pool) = POOL.get() {
+        return Ok(pool);
+    }
+    #[cfg(not(test))]
+    let pool = create_pool(&PersistenceMode::database_url(super::Database::SqlxSqlite)?).await?;
+    #[cfg(test)]
+    let pool = {
+        let pool = create_pool(&crate::db::test_database("sqlx").to_string_lossy()).await?;
+        pool.execute(crate::db::TEST_SEED).await?;
+        pool
+    };
+    Ok(POOL.get_or_init(|| pool))
+}
+
+async fn create_pool(database_url: &str) -> Result<sqlx::SqlitePool, VoteErrorKind<'static>> {
+    let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+    let pool = sqlx::SqlitePool::connect_with(options).await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Migrates the database of `database_url` and runs `script` on it, like the
+/// fixtures of `test-data/`.
+pub async fn seed(database_url: &str, script: &str) -> Result<(), VoteErrorKind<'static>> {
+    let pool = create_pool(database_url).await?;
+    pool.execute(script).await?;
+    pool.close().await;
+    Ok(())
+}
+
+async fn migrate(pool: &sqlx::SqlitePool) -> Result<(), VoteErrorKind<'static>> {
+    let mut conn = pool.acquire().await?;
+    let applied = migrations::run(&mut conn).await?;
+    info!(
+        "Schema version {}, applied {:?}",
+        migrations::latest_version(),
+        applied
+    );
+    Ok(())
+}
+
+pub async fn connection() -> Result<PoolConnection<Sqlite>, VoteErrorKind<'static>> {
+    Ok(pool().await?.acquire().await?)
+}
+
+pub async fn begin() -> Result<Transaction, VoteErrorKind<'static>> {
+    Ok(pool().await?.begin().await?)
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          TYPED VALUES                   //
+//                                         //
+/////////////////////////////////////////////
+
+fn bind_value<'q>(query: SqliteQuery<'q>, value: &DbValue) -> SqliteQuery<'q> {
+    match value {
+        DbValue::Text(t) => query.bind(t.clone()),
+        DbValue::Integer(i) => query.bind(*i),
+        DbValue::Real(r) => query.bind(*r),
+        DbValue::Bool(b) => query.bind(*b),
+        DbValue::Null => query.bind(Option::<String>::None),
+    }
+}
+
+/// Maps a column by the declared type of the column, booleans included, and
+/// falls back to the storage class of the value.
+fn get_db_value(row: &SqliteRow, column_name: &str) -> DbValue {
+    let declared_bool = row
+        .try_column(column_name)
+        .map(|c| c.type_info().name() == "BOOLEAN")
+        .unwrap_or(false);
+    let storage_class = match row.try_get_raw(column_name) {
+        Ok(raw) if raw.is_null() => return DbValue::Null,
+        Ok(raw) => raw.type_info().name().to_string(),
+        Err(e) => {
+            error!("{:?}", e);
+            return DbValue::Null;
+        }
+    };
+    let value = match storage_class.as_str() {
+        _ if declared_bool => row.try_get::<bool, &str>(column_name).map(DbValue::Bool),
+        "INTEGER" => row.try_get::<i64, &str>(column_name).map(DbValue::Integer),
+        "REAL" => row.try_get::<f64, &str>(column_name).map(DbValue::Real),
+        _ => row.try_get::<String, &str>(column_name).map(DbValue::Text),
+    };
+    match value {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{:?}", e);
+            DbValue::Null
+        }
+    }
 }
+
+/// Every column of the row, typed by `get_db_value`.
+impl<'r> sqlx::FromRow<'r, SqliteRow> for DbRow {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(DbRow(
+            row.columns()
+                .iter()
+                .map(|c| (c.name().to_string(), get_db_value(row, c.name())))
+                .collect(),
+        ))
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          QUERIES                        //
+//                                         //
+/////////////////////////////////////////////
+
 pub async fn is_unique(
+    conn: &mut SqliteConnection,
     table: &str,
     identity_column: &str,
     name: &str,
 ) -> Result<bool, VoteErrorKind<'static>> {
-    let database_url = PersistenceMode::to_conform_path();
-    let pool = sqlx::SqlitePool::connect(&database_url.to_string()).await?;
-    let sql_string = format!(
-        "SELECT {}  FROM {} WHERE {} = '{}'",
-        identity_column, table, identity_column, name
-    );
+    let sql_string = is_unique_statement(table, identity_column);
     info!("IS_UNIQUE SQL: {:?}", sql_string);
-    match sqlx::query(&sql_string).fetch_all(&pool).await {
+    match sqlx::query(&sql_string).bind(name).fetch_all(conn).await {
         Ok(r) => {
             if r.len() == 1 {
                 Ok(false)
@@ -38,53 +210,34 @@ pub async fn is_unique(
     }
 }
 
-fn get_column_value_by_column_name(table: &str, row: &SqliteRow, column_name: &str) -> String {
-    let value = match column_name {
-        "min" | "max" | "sum" => row.get::<i16, &str>(column_name).to_string(),
-        "weight" | "weighted" | "mean" => row.get::<f32, &str>(column_name).to_string(),
-        "voter" => match table {
-            "candidates" => row.get::<bool, &str>(column_name).to_string(),
-            _ => row.get::<String, &str>(column_name).to_string(),
-        },
-        _ => row.get::<String, &str>(column_name).to_string(),
-    };
-    value.to_owned() + DELIMITER
-}
-
 // UPDATE table_name
-// SET column1 = value1, column2 = value2, ...
-// WHERE condition;
+// SET column1 = ?, column2 = ?, ...
+// WHERE condition = ?;
 pub async fn update(
+    conn: &mut SqliteConnection,
     table: &str,
-    new_pairs: BTreeMap<&str, &String>,
+    columns: &str,
+    values: &[DbValue],
     identity_column: &str,
     name: &str,
 ) -> Result<bool, VoteErrorKind<'static>> {
-    let database_url = PersistenceMode::to_conform_path();
-    let pool = sqlx::SqlitePool::connect(&database_url.to_string()).await?;
-    let cleanup_name = match name.split_once("_") {
-        Some((a, _b)) => match table {
-            "criteria" => a,
-            "candidates" => name,
-            _ => a,
-        },
-        None => name,
-    };
-    let sql_string = format!(
-        "UPDATE {} SET {} WHERE {} = '{}'",
-        table,
-        new_pairs
-            .iter()
-            .map(|(k, v)| format!("{} = '{}',", k, v))
-            .collect::<String>()
-            .strip_suffix(",")
-            .unwrap(),
-        identity_column,
-        cleanup_name
-    );
+    let column_names: Vec<&str> = get_columns(columns).iter().map(|c| c.trim()).collect();
+    if values.is_empty() || column_names.len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string = update_statement(table, &column_names, identity_column);
     info!("UPDATE SQL: {:?}", sql_string);
-    let rows = sqlx::query(&sql_string)
-        .execute(&pool)
+    let query = values
+        .iter()
+        .fold(sqlx::query(&sql_string), |query, value| {
+            bind_value(query, value)
+        });
+    let rows = query
+        .bind(cleanup_name(table.trim(), name))
+        .execute(conn)
         .await?
         .rows_affected();
 
@@ -95,111 +248,385 @@ pub async fn update(
     }
 }
 
+/// Deletes the row of `name`, the rows of its join tables are up to the caller.
+pub async fn delete(
+    conn: &mut SqliteConnection,
+    table: &str,
+    identity_column: &str,
+    name: &str,
+) -> Result<bool, VoteErrorKind<'static>> {
+    let sql_string = delete_row_statement(table, identity_column);
+    info!("DELETE SQL: {:?}", sql_string);
+    let rows = sqlx::query(&sql_string)
+        .bind(cleanup_name(table.trim(), name))
+        .execute(conn)
+        .await?
+        .rows_affected();
+
+    if rows > 0 {
+        Ok(true)
+    } else {
+        Err(FromErrorKind::MultipleRows(false).into())
+    }
+}
+
 pub async fn select(
+    conn: &mut SqliteConnection,
     table: &str,
     object_columns: &str,
     identity_column: &str,
     name: &str,
-) -> Result<Vec<String>, VoteErrorKind<'static>> {
-    let database_url = PersistenceMode::to_conform_path();
-    let pool = sqlx::SqlitePool::connect(&database_url.to_string()).await?;
-    let cleanup_name = match name.split_once("_") {
-        Some((a, _b)) => match table {
-            "criteria" => a,
-            "candidates" => name,
-            _ => a,
-        },
-        None => name,
-    };
-    let sql_string = format!(
-        "SELECT {} FROM {} WHERE {} = '{}'",
-        object_columns, table, identity_column, cleanup_name
-    );
+) -> Result<Option<DbRow>, VoteErrorKind<'static>> {
+    let sql_string = select_statement(table, object_columns, identity_column);
     info!("SELECT SQL: {:?}", sql_string);
-    match sqlx::query(&sql_string)
-        .fetch_one(&pool)
-        .map_ok(|r| {
-            info!("{:?}", r.columns());
-            let mut result: Vec<String> = Vec::new();
-            for (i, c) in r.columns().iter().enumerate() {
-                info!("{}, {}", i, c.name());
-                result.push(get_column_value_by_column_name(table, &r, c.name()));
-            }
-            Ok(result)
-        })
+    match sqlx::query_as::<_, DbRow>(&sql_string)
+        .bind(cleanup_name(table.trim(), name))
+        .fetch_optional(conn)
         .await
     {
-        Ok(r) => {
-            info!("Selecting one worked");
-            r
-        }
+        Ok(r) => Ok(r),
         Err(e) => {
             warn!("Selecting one failed: {}", e);
-            Ok(Vec::new())
+            Err(e.into())
         }
     }
 }
 
 pub async fn list_rows(
+    conn: &mut SqliteConnection,
     table: &str,
     object_columns: &str,
     column_name: &str,
     all: bool,
 ) -> Result<Vec<String>, VoteErrorKind<'static>> {
-    let database_url = PersistenceMode::to_conform_path();
-    let pool = sqlx::SqlitePool::connect(&database_url.to_string()).await?;
-    let sql_string = format!("SELECT {} FROM {} ", object_columns, table);
+    let sql_string = format!("SELECT {} FROM {} ", object_columns, table.trim());
     info!("SELECT_ALL SQL: {}", sql_string);
-    let rows = sqlx::query(&sql_string).fetch_all(&pool).await?;
-    if rows.len() >= 1 {
-        let mut result: Vec<String> = Vec::new();
-        for (_idx, r) in rows.iter().enumerate() {
-            let mut next_row = String::new();
-            if all {
-                get_columns(object_columns).iter().for_each(|c| {
-                    let a =
-                        cleanup_delimiter(&get_column_value_by_column_name(table, &r, c.trim()))
-                            .replace(",", "_");
-                    next_row.push_str(&a)
-                });
-            } else {
-                next_row.push_str(&cleanup_delimiter(&get_column_value_by_column_name(
-                    table,
-                    &r,
-                    column_name,
-                )));
-            }
-            match next_row.strip_suffix('_') {
-                Some(b) => next_row = b.to_string(),
-                None => {}
-            };
-
-            match next_row.strip_suffix(',') {
-                Some(b) => next_row = b.to_string(),
-                None => {}
-            };
-            result.push(next_row);
-        }
-        debug!("Extracted list_rows: {:?}", result);
-        Ok(result)
-    } else {
-        Ok(vec![])
-    }
+    let rows = sqlx::query_as::<_, DbRow>(&sql_string)
+        .fetch_all(conn)
+        .await?;
+    let result: Vec<String> = rows
+        .iter()
+        .map(|r| list_entry(r, object_columns, column_name, all))
+        .collect();
+    debug!("Extracted list_rows: {:?}", result);
+    Ok(result)
 }
-pub async fn save(table: &str, object_row: &str) -> Result<String, VoteErrorKind<'static>> {
-    let database_url = PersistenceMode::to_conform_path();
-    let pool = sqlx::SqlitePool::connect(&database_url.to_string()).await?;
-    let sql_string = format!(
-        "INSERT into {} VALUES ({})",
-        table,
-        cleanup_delimiter(object_row)
-    );
-    info!("INERT SQL: {}", sql_string);
-    match sqlx::query(&sql_string).execute(&pool).await {
-        Ok(_) => Ok(String::from(object_row)),
+
+pub async fn save(
+    conn: &mut SqliteConnection,
+    table: &str,
+    columns: &str,
+    values: &[DbValue],
+) -> Result<String, VoteErrorKind<'static>> {
+    if values.is_empty() || get_columns(columns).len() != values.len() {
+        error!("{} expects {} but got {:?}", table, columns, values);
+        return Err(VoteErrorKind::Internal(String::from(
+            "Row doesn't match the columns.",
+        )));
+    }
+    let sql_string = insert_statement(table, columns, values.len());
+    info!("INSERT SQL: {}", sql_string);
+    let query = values
+        .iter()
+        .fold(sqlx::query(&sql_string), |query, value| {
+            bind_value(query, value)
+        });
+    match query.execute(conn).await {
+        Ok(_) => Ok(String::from(table.trim())),
         Err(e) => {
             error!("{:?}", e);
             Err(VoteErrorKind::DB(e))
         }
     }
 }
+
+/// Replaces every row of `owner` in a join table, like the candidates of a
+/// voting or the votes of a ballot.
+pub async fn replace_rows(
+    conn: &mut SqliteConnection,
+    table: &str,
+    owner_column: &str,
+    owner: &str,
+    columns: &str,
+    rows: &[Vec<DbValue>],
+) -> Result<u64, VoteErrorKind<'static>> {
+    let sql_string = delete_statement(table, owner_column);
+    info!("DELETE SQL: {}", sql_string);
+    sqlx::query(&sql_string)
+        .bind(owner)
+        .execute(&mut *conn)
+        .await?;
+    let mut inserted = 0;
+    for row in rows {
+        save(&mut *conn, table, columns, row).await?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+/// Ballots of a voting, optionally only the ones of a voter or candidate.
+pub async fn select_ballots(
+    conn: &mut SqliteConnection,
+    voting: &str,
+    filter: Option<(&str, &str)>,
+    order_by: &str,
+) -> Result<Vec<BallotRow>, VoteErrorKind<'static>> {
+    if !is_valid_ballot_query(filter.map(|(c, _)| c), order_by) {
+        return Err(VoteErrorKind::Internal(String::from(
+            "Unknown column for ballots.",
+        )));
+    }
+    let sql_string = select_ballots_statement(Dialect::Sqlite, filter.map(|(c, _)| c), order_by);
+    info!("SELECT BALLOTS SQL: {}", sql_string);
+    let mut query = sqlx::query(&sql_string).bind(voting.to_lowercase());
+    if let Some((_, value)) = filter {
+        for _ in 0..BALLOT_FILTER_BINDS {
+            query = query.bind(value.to_string());
+        }
+    }
+    let rows = query.fetch_all(conn).await?;
+    Ok(rows
+        .iter()
+        .map(|r| BallotRow {
+            voting: r.try_get("voting").unwrap_or_default(),
+            voter: r.try_get("voter").unwrap_or_default(),
+            candidate: r.try_get("candidate").unwrap_or_default(),
+            sum: r.try_get("sum").unwrap_or_default(),
+            weighted: r.try_get("weighted").unwrap_or_default(),
+            mean: r.try_get("mean").unwrap_or_default(),
+            notes: r.try_get("notes").unwrap_or_default(),
+            votes: r.try_get("votes").unwrap_or_else(|_| String::from("[]")),
+            voted_on: r.try_get("voted_on").unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket_db_pools::sqlx::{sqlite::SqlitePoolOptions, Connection};
+
+    async fn memory_pool() -> sqlx::SqlitePool {
+        // One connection, otherwise every connection gets its own memory database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE candidates ( voter BOOLEAN, id TEXT PRIMARY KEY, label TEXT )")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    #[rocket::async_test]
+    async fn quotes_are_stored_as_data() {
+        let pool = memory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let label = String::from("O'Brien'); DROP TABLE candidates; --");
+        let values = vec![
+            DbValue::Bool(true),
+            DbValue::Text(String::from("voter_obrien")),
+            DbValue::Text(label.clone()),
+        ];
+        save(&mut conn, "candidates", "voter, id, label", &values)
+            .await
+            .unwrap();
+        let row = select(
+            &mut conn,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_obrien",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.get("voter"), Some(&DbValue::Bool(true)));
+        assert_eq!(row.text("label"), label.clone());
+
+        let new_label = String::from("Robert'); --");
+        let values = [DbValue::Text(new_label.clone())];
+        assert!(update(
+            &mut conn,
+            "candidates",
+            "label",
+            &values,
+            "id",
+            "voter_obrien"
+        )
+        .await
+        .unwrap());
+        let row = select(
+            &mut conn,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_obrien",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(row.text("label"), new_label);
+
+        assert!(delete(&mut conn, "candidates", "id", "Voter_OBrien")
+            .await
+            .unwrap());
+        assert!(delete(&mut conn, "candidates", "id", "voter_obrien")
+            .await
+            .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn rolled_back_transactions_leave_nothing() {
+        let pool = memory_pool().await;
+        let values = vec![
+            DbValue::Bool(true),
+            DbValue::Text(String::from("voter_alice")),
+            DbValue::Text(String::from("Alice")),
+        ];
+        let mut tx = pool.begin().await.unwrap();
+        save(&mut tx, "candidates", "voter, id, label", &values)
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let row = select(
+            &mut conn,
+            "candidates",
+            "voter, id, label",
+            "id",
+            "voter_alice",
+        )
+        .await
+        .unwrap();
+        assert!(row.is_none());
+    }
+
+    #[rocket::async_test]
+    async fn rejects_rows_not_matching_the_columns() {
+        let pool = memory_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let values = vec![DbValue::Bool(true)];
+        assert!(save(&mut conn, "candidates", "voter, id, label", &values)
+            .await
+            .is_err());
+    }
+
+    fn text(t: &str) -> DbValue {
+        DbValue::Text(String::from(t))
+    }
+
+    async fn save_ballot(conn: &mut SqliteConnection, voter: &str, points: [i64; 2]) {
+        let ballot = format!("final-{}-joe", voter);
+        let values = vec![
+            text(&ballot),
+            text("candidate_joe"),
+            text(voter),
+            DbValue::Integer(0),
+            DbValue::Real(0.0),
+            DbValue::Real(0.0),
+            text(""),
+            text("[]"),
+            text("2026-10-19 10:00:00 UTC"),
+            text("final"),
+        ];
+        save(
+            &mut *conn,
+            "ballots",
+            "human_identifier, candidate, voter, sum, weighted, mean, notes, votes, voted_on, voting",
+            &values,
+        )
+        .await
+        .unwrap();
+        let votes = vec![
+            vec![text(&ballot), text("taste"), DbValue::Integer(points[0])],
+            vec![text(&ballot), text("look"), DbValue::Integer(points[1])],
+        ];
+        replace_rows(
+            conn,
+            "votes",
+            "ballot",
+            &ballot,
+            "ballot, criterion, point",
+            &votes,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn computes_results_in_sql() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        migrations::run(&mut conn).await.unwrap();
+        let voting = vec![
+            text("Final"),
+            text("2026-10-19 10:00:00 UTC"),
+            text("2026-10-19 10:00:00 UTC"),
+            text("[]"),
+            text("[]"),
+            text("{}"),
+            text("Invite"),
+            text(""),
+            text("[]"),
+        ];
+        save(
+            &mut conn,
+            "votings",
+            "name, expires_at, created_at, candidates, categories, styles, invite_code, owner, invite_code_rotations",
+            &voting,
+        )
+        .await
+        .unwrap();
+        let criteria = vec![vec![
+            text("Final"),
+            text("taste"),
+            DbValue::Real(20.0),
+            DbValue::Integer(0),
+        ]];
+        replace_rows(
+            &mut conn,
+            "voting_criteria",
+            "voting",
+            "Final",
+            "voting, criterion, weight, position",
+            &criteria,
+        )
+        .await
+        .unwrap();
+        save_ballot(&mut conn, "anna", [4, 2]).await;
+        save_ballot(&mut conn, "bert", [1, 1]).await;
+
+        let ranked = select_ballots(&mut conn, "Final", None, "sum")
+            .await
+            .unwrap();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].voter, "bert");
+        assert_eq!(ranked[1].sum, 6);
+        assert_eq!(ranked[1].weighted, 10.0);
+        assert_eq!(ranked[1].mean, 3.0);
+        assert!(ranked[1].votes.contains("\"taste\""));
+
+        let by_voter = select_ballots(&mut conn, "final", Some(("voter", "ANNA")), "id")
+            .await
+            .unwrap();
+        assert_eq!(by_voter.len(), 1);
+        assert!(
+            select_ballots(&mut conn, "final", Some(("notes", "")), "id")
+                .await
+                .is_err()
+        );
+
+        // Deleting a ballot removes its votes.
+        sqlx::query("DELETE FROM ballots WHERE voter = 'anna'")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let votes = sqlx::query("SELECT * FROM votes")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(votes.len(), 2);
+    }
+}
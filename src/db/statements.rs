@@ -0,0 +1,270 @@
+use crate::common::DbRow;
+
+/////////////////////////////////////////////
+//                                         //
+//          STATEMENTS                     //
+//                                         //
+/////////////////////////////////////////////
+
+pub fn get_columns(column_string: &str) -> Vec<&str> {
+    column_string.split(',').collect()
+}
+
+pub fn cleanup_name<'a>(table: &str, name: &'a str) -> &'a str {
+    match name.split_once("_") {
+        Some((a, _b)) => match table {
+            "criteria" => a,
+            "candidates" => name,
+            _ => a,
+        },
+        None => name,
+    }
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// Where the SQL of SQLite and PostgreSQL differs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+impl Dialect {
+    /// The statements are written with `?`, PostgreSQL numbers its
+    /// placeholders `$1`, `$2`, ... in the order they are bound.
+    pub fn placeholders(self, sql: &str) -> String {
+        match self {
+            Dialect::Sqlite => sql.to_string(),
+            Dialect::Postgres => {
+                let mut count = 0;
+                sql.chars()
+                    .map(|c| match c {
+                        '?' => {
+                            count += 1;
+                            format!("${}", count)
+                        }
+                        c => c.to_string(),
+                    })
+                    .collect()
+            }
+        }
+    }
+    fn ends_with(self, column: &str) -> String {
+        match self {
+            Dialect::Sqlite => format!("substr({}, -length(?))", column),
+            Dialect::Postgres => format!("right({}, length(?))", column),
+        }
+    }
+    /// The votes of a ballot as JSON text, `[]` without votes.
+    fn votes_array(self) -> &'static str {
+        match self {
+            Dialect::Sqlite => "json_group_array(json_object('name', v.criterion, 'point', v.point)) FILTER (WHERE v.criterion IS NOT NULL)",
+            Dialect::Postgres => "COALESCE(CAST(json_agg(json_build_object('name', v.criterion, 'point', v.point)) FILTER (WHERE v.criterion IS NOT NULL) AS TEXT), '[]')",
+        }
+    }
+}
+
+/// Ids are compared case-insensitive, like the file names of the file backend.
+pub fn is_unique_statement(table: &str, identity_column: &str) -> String {
+    format!(
+        "SELECT {} FROM {} WHERE lower({}) = lower(?)",
+        identity_column,
+        table.trim(),
+        identity_column
+    )
+}
+
+pub fn select_statement(table: &str, object_columns: &str, identity_column: &str) -> String {
+    format!(
+        "SELECT {} FROM {} WHERE lower({}) = lower(?)",
+        object_columns,
+        table.trim(),
+        identity_column
+    )
+}
+
+pub fn insert_statement(table: &str, columns: &str, count: usize) -> String {
+    format!(
+        "INSERT INTO {} ( {} ) VALUES ( {} )",
+        table.trim(),
+        columns,
+        placeholders(count)
+    )
+}
+
+pub fn update_statement(table: &str, columns: &[&str], identity_column: &str) -> String {
+    format!(
+        "UPDATE {} SET {} WHERE lower({}) = lower(?)",
+        table.trim(),
+        columns
+            .iter()
+            .map(|k| format!("{} = ?", k))
+            .collect::<Vec<String>>()
+            .join(", "),
+        identity_column
+    )
+}
+
+pub fn delete_statement(table: &str, owner_column: &str) -> String {
+    format!("DELETE FROM {} WHERE {} = ?", table.trim(), owner_column)
+}
+
+pub fn delete_row_statement(table: &str, identity_column: &str) -> String {
+    format!(
+        "DELETE FROM {} WHERE lower({}) = lower(?)",
+        table.trim(),
+        identity_column
+    )
+}
+
+/// One entry of `list_rows`, the identity column or, with `all`, every column
+/// joined by `_`.
+pub fn list_entry(row: &DbRow, object_columns: &str, column_name: &str, all: bool) -> String {
+    if all {
+        get_columns(object_columns)
+            .iter()
+            .map(|c| row.text(c.trim()).replace(',', "_"))
+            .collect::<Vec<String>>()
+            .join("_")
+    } else {
+        row.text(column_name)
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          RESULTS                        //
+//                                         //
+/////////////////////////////////////////////
+
+/// A ballot with its sum, weighted sum and mean computed out of `votes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BallotRow {
+    pub voting: String,
+    pub voter: String,
+    pub candidate: String,
+    pub sum: i64,
+    pub weighted: f64,
+    pub mean: f64,
+    pub notes: String,
+    /// JSON array of `{ "name", "point" }`.
+    pub votes: String,
+    pub voted_on: String,
+}
+
+/// Columns of `ballots`, which the results can be filtered or sorted by.
+pub const BALLOT_FILTER_COLUMNS: [&str; 2] = ["voter", "candidate"];
+pub const BALLOT_ORDER_COLUMNS: [&str; 5] = ["id", "voted_on", "sum", "weighted", "mean"];
+/// How often the filter value of `select_ballots_statement` is bound.
+pub const BALLOT_FILTER_BINDS: usize = 4;
+
+pub fn is_valid_ballot_query(filter_column: Option<&str>, order_by: &str) -> bool {
+    filter_column.map_or(true, |c| BALLOT_FILTER_COLUMNS.contains(&c))
+        && BALLOT_ORDER_COLUMNS.contains(&order_by)
+}
+
+pub fn select_ballots_statement(
+    dialect: Dialect,
+    filter_column: Option<&str>,
+    order_by: &str,
+) -> String {
+    let filter = match filter_column {
+        // Same as `compare_pattern_file_names`, the value is the start or the end.
+        Some(c) => format!(
+            " AND ( substr(lower(b.{c}), 1, length(?)) = lower(?) OR {} = lower(?) )",
+            dialect.ends_with(&format!("lower(b.{c})"))
+        ),
+        None => String::new(),
+    };
+    let order_by = match order_by {
+        "sum" | "weighted" | "mean" => order_by.to_string(),
+        other => format!("b.{}", other),
+    };
+    let statement = format!(
+        "SELECT b.voting, b.voter, b.candidate, \
+        COALESCE(SUM(v.point), 0) AS sum, \
+        COALESCE(SUM(v.point * COALESCE(c.weight * 0.1, 1.0)), 0.0) AS weighted, \
+        CAST(COALESCE(AVG(v.point), 0.0) AS DOUBLE PRECISION) AS mean, \
+        b.notes, b.voted_on, \
+        {} AS votes \
+        FROM ballots b \
+        LEFT JOIN votes v ON v.ballot = b.human_identifier \
+        LEFT JOIN voting_criteria c ON lower(c.voting) = b.voting AND c.criterion = v.criterion \
+        WHERE b.voting = ?{} \
+        GROUP BY b.id \
+        ORDER BY {}, b.id",
+        dialect.votes_array(),
+        filter,
+        order_by
+    );
+    dialect.placeholders(&statement)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::DbValue;
+
+    #[test]
+    fn statements_only_hold_placeholders() {
+        assert_eq!(
+            insert_statement("candidates ", "voter, id, label", 3),
+            "INSERT INTO candidates ( voter, id, label ) VALUES ( ?, ?, ? )"
+        );
+        assert_eq!(
+            update_statement("votings", &["expires_at", "owner"], "name"),
+            "UPDATE votings SET expires_at = ?, owner = ? WHERE lower(name) = lower(?)"
+        );
+        assert_eq!(
+            delete_row_statement("votings", "name"),
+            "DELETE FROM votings WHERE lower(name) = lower(?)"
+        );
+    }
+
+    #[test]
+    fn postgres_numbers_its_placeholders() {
+        let statement = Dialect::Postgres.placeholders(&update_statement(
+            "votings",
+            &["expires_at", "owner"],
+            "name",
+        ));
+        assert_eq!(
+            statement,
+            "UPDATE votings SET expires_at = $1, owner = $2 WHERE lower(name) = lower($3)"
+        );
+        let ballots = select_ballots_statement(Dialect::Postgres, Some("voter"), "sum");
+        assert!(ballots.contains("b.voting = $1"));
+        assert!(ballots.contains("right(lower(b.voter), length($4)) = lower($5)"));
+        assert!(!ballots.contains('?'));
+    }
+
+    #[test]
+    fn ballot_queries_only_know_their_columns() {
+        assert!(is_valid_ballot_query(Some("voter"), "sum"));
+        assert!(is_valid_ballot_query(None, "id"));
+        assert!(!is_valid_ballot_query(Some("notes"), "id"));
+        assert!(!is_valid_ballot_query(None, "sum; DROP TABLE ballots"));
+    }
+
+    #[test]
+    fn lists_all_columns_joined() {
+        let row = DbRow(vec![
+            (
+                String::from("name"),
+                DbValue::Text(String::from("style,dark")),
+            ),
+            (String::from("min"), DbValue::Integer(0)),
+            (String::from("max"), DbValue::Integer(10)),
+        ]);
+        assert_eq!(
+            list_entry(&row, "name, min, max", "name", true),
+            "style_dark_0_10"
+        );
+        assert_eq!(
+            list_entry(&row, "name, min, max", "name", false),
+            "style,dark"
+        );
+    }
+}
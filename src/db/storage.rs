@@ -0,0 +1,253 @@
+use crate::{
+    ballots::{BallotsTable, CompleteBallotsTable, TableRow},
+    common::{Candidate, CastBallots, Criterion, EmojiCategories, Operator, Table, Voting},
+    db::{common::Query, Database},
+    error::VoteErrorKind,
+    persistence::{Kind, PutMode, StorageBackend},
+};
+use rocket::{
+    debug, error,
+    serde::{json, Deserialize, Serialize},
+};
+
+/// Every kind is a table of `database`. Ballots are stored as `BallotsTable`
+/// rows and their results are computed in SQL.
+pub struct DatabaseStorage {
+    database: Database,
+}
+impl DatabaseStorage {
+    pub fn new(database: Database) -> Self {
+        DatabaseStorage { database }
+    }
+}
+
+/// Table, columns and identity column of a kind. Results are computed in SQL
+/// and have no table.
+fn table_of(kind: Kind) -> Option<(String, String, String)> {
+    fn columns<T: Table>() -> Option<(String, String, String)> {
+        Some((
+            T::get_table(false),
+            T::get_db_columns(),
+            T::get_identity_column_name(),
+        ))
+    }
+    match kind {
+        Kind::Voting => columns::<Voting>(),
+        Kind::Ballot => columns::<BallotsTable>(),
+        Kind::Candidate => columns::<Candidate>(),
+        Kind::Criterion => columns::<Criterion>(),
+        Kind::Operator => columns::<Operator>(),
+        Kind::Emojis => columns::<EmojiCategories>(),
+        Kind::Result => None,
+    }
+}
+
+async fn select_row<T: Query>(database: Database, id: &str) -> Result<T, VoteErrorKind<'static>> {
+    let mut conn = database.connection().await?;
+    let row = crate::db::select(
+        &mut conn,
+        &T::get_table(false),
+        &T::get_db_columns(),
+        &T::get_identity_column_name(),
+        id,
+    )
+    .await?
+    .ok_or_else(|| VoteErrorKind::NotFound(id.to_owned() + " not found."))?;
+    Ok(T::from_db_row(&row)?)
+}
+
+async fn get_as<T: Query + Serialize>(
+    database: Database,
+    id: &str,
+) -> Result<String, VoteErrorKind<'static>> {
+    Ok(json::to_string(&select_row::<T>(database, id).await?)?)
+}
+
+async fn put_as<T: Query + for<'de> Deserialize<'de>>(
+    database: Database,
+    entry: &str,
+    mode: PutMode,
+) -> Result<String, VoteErrorKind<'static>> {
+    write(database, &json::from_str::<T>(entry)?, mode).await
+}
+
+async fn write<T: Query>(
+    database: Database,
+    row: &T,
+    mode: PutMode,
+) -> Result<String, VoteErrorKind<'static>> {
+    match mode {
+        PutMode::Create => row.save(database).await,
+        PutMode::Replace => row.update(database).await,
+    }
+}
+
+async fn delete_as<T: Query>(
+    database: Database,
+    id: &str,
+) -> Result<String, VoteErrorKind<'static>> {
+    select_row::<T>(database, id).await?.delete(database).await
+}
+
+async fn list_rows(
+    database: Database,
+    kind: Kind,
+    all: bool,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let (table, columns, identity_column) = match table_of(kind) {
+        Some(table) => table,
+        None => return Ok(vec![]),
+    };
+    let mut conn = database.connection().await?;
+    crate::db::list_rows(&mut conn, &table, &columns, &identity_column, all).await
+}
+
+#[rocket::async_trait]
+impl StorageBackend for DatabaseStorage {
+    fn name(&self) -> &'static str {
+        self.database.name()
+    }
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        match kind {
+            Kind::Voting => get_as::<Voting>(self.database, id).await,
+            Kind::Ballot => Ok(json::to_string(&CastBallots::from(
+                select_row::<BallotsTable>(self.database, id).await?,
+            ))?),
+            Kind::Candidate => get_as::<Candidate>(self.database, id).await,
+            Kind::Criterion => get_as::<Criterion>(self.database, id).await,
+            Kind::Operator => get_as::<Operator>(self.database, id).await,
+            Kind::Emojis => get_as::<EmojiCategories>(self.database, id).await,
+            Kind::Result => Err(VoteErrorKind::NotFound(id.to_owned() + " not found.")),
+        }
+    }
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        debug!("Put {:?} {}", kind, id);
+        match kind {
+            Kind::Voting => put_as::<Voting>(self.database, &entry, mode).await,
+            Kind::Ballot => {
+                let cast_ballot = json::from_str::<CastBallots>(&entry)?;
+                let row =
+                    BallotsTable::from(CompleteBallotsTable::aggregate_all(cast_ballot).await);
+                write(self.database, &row, mode).await
+            }
+            Kind::Candidate => put_as::<Candidate>(self.database, &entry, mode).await,
+            Kind::Criterion => put_as::<Criterion>(self.database, &entry, mode).await,
+            Kind::Operator => put_as::<Operator>(self.database, &entry, mode).await,
+            Kind::Emojis => put_as::<EmojiCategories>(self.database, &entry, mode).await,
+            Kind::Result => Err(VoteErrorKind::Conflict(String::from(
+                "The database computes results itself.",
+            ))),
+        }
+    }
+    /// Every column of a row joined by `_`, like the file names of the file
+    /// backend.
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        list_rows(self.database, kind, true).await
+    }
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        match kind {
+            Kind::Voting => delete_as::<Voting>(self.database, id).await,
+            Kind::Ballot => delete_as::<BallotsTable>(self.database, id).await,
+            Kind::Candidate => delete_as::<Candidate>(self.database, id).await,
+            Kind::Criterion => delete_as::<Criterion>(self.database, id).await,
+            Kind::Operator => delete_as::<Operator>(self.database, id).await,
+            Kind::Emojis => delete_as::<EmojiCategories>(self.database, id).await,
+            Kind::Result => Err(VoteErrorKind::NotFound(id.to_owned() + " not found.")),
+        }
+    }
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        list_rows(self.database, kind, false).await
+    }
+    /// The identity column holds the full id, unlike `list`.
+    async fn find(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let ids = match self.index(kind).await {
+            Ok(ids) => ids,
+            Err(_) => return Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        };
+        match ids.iter().find(|v| match v.split_once('_') {
+            Some((_v_type, found_id)) => found_id.to_lowercase() == id.to_lowercase(),
+            None => true,
+        }) {
+            Some(s) => Ok(s.to_string()),
+            None => Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        }
+    }
+    async fn results(
+        &self,
+        voting: &str,
+        filter: Option<(&str, &str)>,
+        order_by: &str,
+    ) -> Result<Option<Vec<TableRow>>, VoteErrorKind<'static>> {
+        let mut conn = self.database.connection().await?;
+        match crate::db::select_ballots(&mut conn, voting, filter, order_by).await {
+            Ok(rows) => Ok(Some(rows.into_iter().map(TableRow::from).collect())),
+            Err(e) => {
+                error!("{:?}", e);
+                Err(e)
+            }
+        }
+    }
+    fn computes_results(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[rocket::async_test]
+    async fn stores_entries_as_rows() {
+        if crate::db::skips_without(Database::default()) {
+            return;
+        }
+        let storage = DatabaseStorage::new(Database::default());
+        let voting = storage.get(Kind::Voting, "voting").await.unwrap();
+        assert!(voting.contains("\"name\":\"Voting\""));
+        let ballot = storage.get(Kind::Ballot, "voting-obama-joe").await.unwrap();
+        let ballot = json::from_str::<CastBallots>(&ballot).unwrap();
+        assert_eq!(ballot.ballots[0].voter, "Obama");
+        assert_eq!(ballot.ballots[0].ballots[0].votes.len(), 2);
+
+        let candidate = String::from(r#"{"voter":true,"id":"voter_storage","label":"Storage"}"#);
+        let saved = storage
+            .put(
+                Kind::Candidate,
+                "voter_storage",
+                candidate.clone(),
+                PutMode::Create,
+            )
+            .await;
+        assert_eq!(saved.unwrap(), "Saved and index updated.");
+        let conflict = storage
+            .put(Kind::Candidate, "voter_storage", candidate, PutMode::Create)
+            .await;
+        assert!(matches!(conflict, Err(VoteErrorKind::Conflict(_))));
+        assert_eq!(
+            storage.find(Kind::Candidate, "storage").await.unwrap(),
+            "voter_storage"
+        );
+        storage
+            .delete(Kind::Candidate, "voter_storage")
+            .await
+            .unwrap();
+        assert!(storage.get(Kind::Candidate, "voter_storage").await.is_err());
+    }
+
+    #[cfg(all(feature = "sqlx_sqlite", feature = "diesel_sqlite"))]
+    #[rocket::async_test]
+    async fn runs_on_either_sqlite_backend() {
+        for database in [Database::SqlxSqlite, Database::DieselSqlite] {
+            let storage = DatabaseStorage::new(database);
+            assert_eq!(storage.name(), database.name());
+            let voting = storage.get(Kind::Voting, "voting").await.unwrap();
+            assert!(voting.contains("\"name\":\"Voting\""));
+        }
+    }
+}
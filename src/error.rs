@@ -1,4 +1,4 @@
-use rocket::{response::status::Unauthorized, Responder};
+use rocket::{http::Status, response::status::Unauthorized, Responder};
 /////////////////////////////////////////////
 //                                         //
 //        VOTE-ERROR-KIND                  //
@@ -13,8 +13,10 @@ pub enum VoteErrorKind<'r> {
     Conflict(String),
     NotFound(String),
     Unauthorized(Unauthorized<String>),
-    #[cfg(feature = "sqlx_sqlite")]
+    #[cfg(any(feature = "sqlx_sqlite", feature = "postgres"))]
     DB(rocket_db_pools::sqlx::Error),
+    #[cfg(feature = "diesel_sqlite")]
+    Diesel(diesel::result::Error),
     Internal(String),
 }
 
@@ -46,8 +48,10 @@ impl<'r> ToString for VoteErrorKind<'r> {
             VoteErrorKind::Conflict(e) => e.to_string(),
             VoteErrorKind::NotFound(e) => e.to_string(),
             VoteErrorKind::Unauthorized(e) => e.0.clone(),
-            #[cfg(feature = "sqlx_sqlite")]
+            #[cfg(any(feature = "sqlx_sqlite", feature = "postgres"))]
             VoteErrorKind::DB(e) => e.to_string(),
+            #[cfg(feature = "diesel_sqlite")]
+            VoteErrorKind::Diesel(e) => e.to_string(),
             VoteErrorKind::Internal(e) => e.to_string(),
         }
     }
@@ -64,12 +68,37 @@ impl<'r> From<rocket::serde::json::serde_json::Error> for VoteErrorKind<'r> {
         VoteErrorKind::Serialize(error)
     }
 }
-#[cfg(feature = "sqlx_sqlite")]
+#[cfg(any(feature = "sqlx_sqlite", feature = "postgres"))]
 impl<'r> From<rocket_db_pools::sqlx::Error> for VoteErrorKind<'r> {
     fn from(error: rocket_db_pools::sqlx::Error) -> Self {
         VoteErrorKind::DB(error)
     }
 }
+#[cfg(feature = "diesel_sqlite")]
+impl<'r> From<diesel::result::Error> for VoteErrorKind<'r> {
+    fn from(error: diesel::result::Error) -> Self {
+        VoteErrorKind::Diesel(error)
+    }
+}
+#[cfg(feature = "diesel_sqlite")]
+impl<'r> From<diesel::r2d2::PoolError> for VoteErrorKind<'r> {
+    fn from(error: diesel::r2d2::PoolError) -> Self {
+        VoteErrorKind::Internal(error.to_string())
+    }
+}
+/// The status a route answers with, if it can't do better than passing the
+/// error on.
+impl<'r> From<&VoteErrorKind<'r>> for Status {
+    fn from(error: &VoteErrorKind<'r>) -> Self {
+        match error {
+            VoteErrorKind::MissingField(_) => Status::BadRequest,
+            VoteErrorKind::Conflict(_) => Status::Conflict,
+            VoteErrorKind::NotFound(_) => Status::NotFound,
+            VoteErrorKind::Unauthorized(_) => Status::Unauthorized,
+            _ => Status::InternalServerError,
+        }
+    }
+}
 #[derive(Responder, Debug)]
 #[response(content_type = "application/json")]
 pub struct MissingField<'r> {
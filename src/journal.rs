@@ -0,0 +1,382 @@
+use crate::error::VoteErrorKind;
+use rocket::{
+    serde::{
+        de::IgnoredAny,
+        json::{self, Value},
+        Deserialize, Serialize,
+    },
+    tokio::{
+        fs::{File, OpenOptions},
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
+        sync::Mutex,
+    },
+    warn,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::SeekFrom,
+};
+
+/// A line of a journal, a ballot without `entry` is deleted.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Line {
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<Value>,
+}
+
+/// A line, which is only read for its id.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct LineId {
+    id: String,
+    #[serde(default)]
+    entry: Option<IgnoredAny>,
+}
+
+/// The ids of a journal, which aren't deleted.
+#[derive(Default)]
+struct Live {
+    /// In the order they were written first.
+    ids: Vec<String>,
+    /// The offset of the line holding the current entry of an id.
+    offsets: HashMap<String, u64>,
+    /// The end of the last complete line, which was read.
+    read_up_to: u64,
+    /// The number of lines read, for the warnings.
+    lines: usize,
+}
+
+/// The ballots of a voting appended to `<voting>.jsonl`, one JSON line per
+/// written or deleted ballot. A later line of an id replaces the earlier ones.
+///
+/// Writes aren't locked here, `FileStorage` holds the lock of the ballots.
+/// A line cut off by a crash is skipped and the next one starts on a new line.
+///
+/// The offsets of the current lines are kept in memory. A journal is read once
+/// and afterwards only the lines appended since, by this or another process.
+pub struct BallotJournal {
+    dir: String,
+    live: Mutex<HashMap<String, Live>>,
+}
+
+impl BallotJournal {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: dir.to_string(),
+            live: Mutex::new(HashMap::new()),
+        }
+    }
+    fn path(&self, voting: &str) -> String {
+        self.dir.clone() + "/" + voting + ".jsonl"
+    }
+    /// The voting a ballot is written to, out of the ballot itself, as the
+    /// voting can't be told from ids like `voting-a-joe-doe`.
+    pub fn voting_of(entry: &Value) -> Result<String, VoteErrorKind<'static>> {
+        match entry.get("voting").and_then(|v| v.as_str()) {
+            Some(voting) => Ok(voting.to_lowercase()),
+            None => Err(VoteErrorKind::Conflict(String::from(
+                "The ballot names no voting.",
+            ))),
+        }
+    }
+    /// Every voting with a journal.
+    pub async fn votings(&self) -> Vec<String> {
+        let mut votings = vec![];
+        if let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if let Ok(file_name) = entry.file_name().into_string() {
+                    if let Some(voting) = file_name.strip_suffix(".jsonl") {
+                        votings.push(voting.to_owned());
+                    }
+                }
+            }
+        }
+        votings.sort();
+        votings
+    }
+    async fn open(&self, voting: &str) -> Result<Option<File>, VoteErrorKind<'static>> {
+        match File::open(self.path(voting)).await {
+            Ok(f) => Ok(Some(f)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(VoteErrorKind::IO(e)),
+        }
+    }
+    /// Reads the lines appended since the last call. A journal, which got
+    /// shorter, was replaced and is read anew.
+    async fn refresh<'a>(
+        &self,
+        live: &'a mut HashMap<String, Live>,
+        voting: &str,
+    ) -> Result<&'a Live, VoteErrorKind<'static>> {
+        let known = live.entry(voting.to_string()).or_default();
+        let f = match self.open(voting).await? {
+            Some(f) => f,
+            None => {
+                *known = Live::default();
+                return Ok(known);
+            }
+        };
+        let len = f.metadata().await?.len();
+        if len < known.read_up_to {
+            *known = Live::default();
+        }
+        if len == known.read_up_to {
+            return Ok(known);
+        }
+        let mut reader = BufReader::new(f);
+        reader.seek(SeekFrom::Start(known.read_up_to)).await?;
+        let mut line = vec![];
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).await?;
+            // A line, which is still written or was cut off.
+            if read == 0 || line.last() != Some(&b'\n') {
+                break;
+            }
+            match json::from_slice::<LineId>(&line) {
+                Ok(LineId { id, entry: Some(_) }) => {
+                    if !known.offsets.contains_key(&id) {
+                        known.ids.push(id.clone());
+                    }
+                    known.offsets.insert(id, known.read_up_to);
+                }
+                Ok(LineId { id, entry: None }) => {
+                    if known.offsets.remove(&id).is_some() {
+                        known.ids.retain(|i| *i != id);
+                    }
+                }
+                Err(e) => warn!("Skipping line {} of {}: {}", known.lines, voting, e),
+            }
+            known.read_up_to += read as u64;
+            known.lines += 1;
+        }
+        Ok(known)
+    }
+    /// The ids of every journal, in the order they were written per voting.
+    pub async fn ids(&self) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let mut live = self.live.lock().await;
+        let mut ids = vec![];
+        for voting in self.votings().await {
+            ids.extend_from_slice(&self.refresh(&mut live, &voting).await?.ids);
+        }
+        Ok(ids)
+    }
+    /// The voting of the journal holding `id` and the offset of its line.
+    async fn find(&self, id: &str) -> Result<Option<(String, u64)>, VoteErrorKind<'static>> {
+        let mut votings: Vec<String> = self
+            .votings()
+            .await
+            .into_iter()
+            .filter(|v| id.starts_with(&(v.clone() + "-")))
+            .collect();
+        votings.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        let mut live = self.live.lock().await;
+        for voting in votings {
+            if let Some(offset) = self.refresh(&mut live, &voting).await?.offsets.get(id) {
+                return Ok(Some((voting, *offset)));
+            }
+        }
+        Ok(None)
+    }
+    pub async fn contains(&self, id: &str) -> Result<bool, VoteErrorKind<'static>> {
+        Ok(self.find(id).await?.is_some())
+    }
+    /// Reads the line of `id` alone.
+    pub async fn get(&self, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let not_found = || VoteErrorKind::NotFound(String::from("Not found"));
+        let (voting, offset) = self.find(id).await?.ok_or_else(not_found)?;
+        let mut f = self.open(&voting).await?.ok_or_else(not_found)?;
+        f.seek(SeekFrom::Start(offset)).await?;
+        let mut line = vec![];
+        BufReader::new(f).read_until(b'\n', &mut line).await?;
+        match json::from_slice::<Line>(&line)? {
+            Line {
+                id: found,
+                entry: Some(entry),
+            } if found == id => Ok(entry.to_string()),
+            _ => Err(not_found()),
+        }
+    }
+    /// Passes every ballot of the voting to `each`, line by line. Only the
+    /// offsets of the current lines are kept in memory.
+    pub async fn for_each(
+        &self,
+        voting: &str,
+        each: &mut (dyn FnMut(&str, &str) + Send),
+    ) -> Result<(), VoteErrorKind<'static>> {
+        let (current, read_up_to) = {
+            let mut live = self.live.lock().await;
+            let known = self.refresh(&mut live, voting).await?;
+            let current: HashSet<u64> = known.offsets.values().copied().collect();
+            (current, known.read_up_to)
+        };
+        let f = match self.open(voting).await? {
+            Some(f) => f,
+            None => return Ok(()),
+        };
+        let mut reader = BufReader::new(f);
+        let mut offset = 0;
+        let mut line = vec![];
+        while offset < read_up_to {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).await?;
+            if read == 0 {
+                break;
+            }
+            if current.contains(&offset) {
+                if let Ok(Line {
+                    id,
+                    entry: Some(entry),
+                }) = json::from_slice::<Line>(&line)
+                {
+                    each(&id, &entry.to_string());
+                }
+            }
+            offset += read as u64;
+        }
+        Ok(())
+    }
+    /// Appends the ballot `id`, without `entry` it is deleted.
+    pub async fn append(
+        &self,
+        id: &str,
+        entry: Option<Value>,
+    ) -> Result<(), VoteErrorKind<'static>> {
+        let voting = match &entry {
+            Some(entry) => Self::voting_of(entry)?,
+            None => match self.find(id).await? {
+                Some((voting, _)) => voting,
+                None => return Err(VoteErrorKind::NotFound(String::from("Not found"))),
+            },
+        };
+        let line = Line {
+            id: id.to_string(),
+            entry,
+        };
+        let mut stringified = json::to_string(&line)? + "\n";
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut f = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(self.path(&voting))
+            .await?;
+        if f.metadata().await?.len() > 0 {
+            f.seek(SeekFrom::End(-1)).await?;
+            if f.read_u8().await? != b'\n' {
+                stringified.insert(0, '\n');
+            }
+        }
+        f.write_all(stringified.as_bytes()).await?;
+        f.sync_all().await?;
+        let mut live = self.live.lock().await;
+        self.refresh(&mut live, &voting).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ballot(voting: &str, voter: &str) -> Value {
+        json::json!({ "voting": voting, "ballots": [{ "voter": voter, "ballots": [] }] })
+    }
+
+    #[rocket::async_test]
+    async fn appends_and_streams_ballots() {
+        let dir =
+            std::env::temp_dir().join(format!("voters-verdict-journal-{}", std::process::id()));
+        let journal = BallotJournal::new(&dir.to_string_lossy());
+
+        for (id, voting, voter) in [
+            ("voting-obama-joe", "Voting", "Obama"),
+            ("voting-a-obama-joe", "Voting-A", "Obama"),
+            ("voting-michelle-joe", "Voting", "Michelle"),
+            ("voting-obama-joe", "Voting", "Barack"),
+        ] {
+            journal
+                .append(id, Some(ballot(voting, voter)))
+                .await
+                .unwrap();
+        }
+        journal.append("voting-michelle-joe", None).await.unwrap();
+        assert_eq!(journal.votings().await, vec!["voting", "voting-a"]);
+        assert_eq!(
+            journal.ids().await.unwrap(),
+            vec!["voting-obama-joe", "voting-a-obama-joe"]
+        );
+        assert!(journal
+            .get("voting-a-obama-joe")
+            .await
+            .unwrap()
+            .contains("Voting-A"));
+        assert!(journal.get("voting-michelle-joe").await.is_err());
+
+        let mut streamed = vec![];
+        journal
+            .for_each("voting", &mut |id, entry| {
+                streamed.push((id.to_string(), entry.contains("Barack")))
+            })
+            .await
+            .unwrap();
+        assert_eq!(streamed, vec![(String::from("voting-obama-joe"), true)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn reads_lines_of_other_writers() {
+        let dir =
+            std::env::temp_dir().join(format!("voters-verdict-writers-{}", std::process::id()));
+        let journal = BallotJournal::new(&dir.to_string_lossy());
+        journal
+            .append("voting-obama-joe", Some(ballot("Voting", "Obama")))
+            .await
+            .unwrap();
+        let other = BallotJournal::new(&dir.to_string_lossy());
+        other
+            .append("voting-michelle-joe", Some(ballot("Voting", "Michelle")))
+            .await
+            .unwrap();
+        assert!(journal
+            .get("voting-michelle-joe")
+            .await
+            .unwrap()
+            .contains("Michelle"));
+        assert!(journal
+            .get("voting-obama-joe")
+            .await
+            .unwrap()
+            .contains("Obama"));
+
+        let without_voting = json::json!({ "ballots": [] });
+        assert!(matches!(
+            journal.append("nobody-joe", Some(without_voting)).await,
+            Err(VoteErrorKind::Conflict(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn skips_cut_off_lines() {
+        let dir = std::env::temp_dir().join(format!("voters-verdict-cut-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("voting.jsonl"),
+            "{\"id\":\"voting-obama-joe\",\"entry\":{\"voting\":\"Voting\"}}\n{\"id\":\"voting-mich",
+        )
+        .unwrap();
+        let journal = BallotJournal::new(&dir.to_string_lossy());
+        journal
+            .append("voting-michelle-joe", Some(ballot("Voting", "Michelle")))
+            .await
+            .unwrap();
+        assert_eq!(
+            journal.ids().await.unwrap(),
+            vec!["voting-obama-joe", "voting-michelle-joe"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,18 +1,33 @@
+pub mod aggregate;
 pub mod authentication;
+pub mod backup;
 pub mod ballots;
+pub mod cache;
+#[cfg(any(feature = "remote", feature = "s3"))]
+pub mod client;
 pub mod common;
 pub mod config;
+pub mod consistency;
 pub mod criteria;
+pub mod csrf;
 #[cfg(feature = "db")]
 pub mod db;
 pub mod error;
 #[cfg(feature = "local")]
+pub mod journal;
+#[cfg(feature = "local")]
 pub mod local;
+pub mod memory;
+pub mod migration;
+pub mod operators;
 pub mod persistence;
 pub mod plumping;
+pub mod rate_limit;
 #[cfg(feature = "remote")]
 pub mod remote;
 pub mod routes;
+#[cfg(feature = "s3")]
+pub mod s3;
 pub mod serialize;
 #[cfg(feature = "templates")]
 pub mod templates;
@@ -1,167 +1,244 @@
 use crate::{
-    common::{
-        Candidate, CastBallots, Criteria, Criterion, EmojiCategories, IdGenerator, Selfaware,
-        Voting, Votings,
-    },
-    config::{ASSET_DIR, FILE_DIR},
     error::VoteErrorKind,
-    persistence::Path,
+    journal::BallotJournal,
+    persistence::{Kind, PutMode, StorageBackend},
 };
+use fs2::FileExt;
 use rocket::{
     debug, error,
-    serde::Deserialize,
     tokio::{
         fs::File,
-        io::{AsyncReadExt, AsyncWriteExt},
+        io::AsyncWriteExt,
+        sync::{Mutex, MutexGuard},
     },
 };
-use std::io::Read;
-pub trait FileDir {
-    fn get_dir() -> &'static str;
-    fn get_full_path(&self, _possible_remote: bool) -> String {
-        let root_path = match std::env::var(FILE_DIR) {
-            Ok(file_dir) => file_dir,
-            Err(_) => String::from("/tmp/"),
-        };
-        root_path + &Self::get_dir()
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Makes the names of temporary files unique within the process.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Every entry is a JSON file in the directory of its kind below `root`, the
+/// emojis are below `assets`. Each directory has an `index.json` with the ids
+/// in the order they were created.
+///
+/// Files are written to a temporary file and renamed, so a crash never leaves
+/// half written JSON. Creating and deleting entries holds the lock of their
+/// directory, concurrent voters can't lose an entry of the index. The lock is
+/// an advisory lock of `index.json.lock` as well, so the admin tool can
+/// rebuild an index or migrate the ballots next to a running server.
+///
+/// With a journal, new ballots are appended to `ballots/<voting>.jsonl`
+/// instead. Ballots of the old layout are read until they are migrated.
+pub struct FileStorage {
+    root: String,
+    assets: String,
+    locks: HashMap<Kind, Mutex<()>>,
+    journal: Option<BallotJournal>,
+}
+
+/// The lock of a directory within the process and against other processes,
+/// both are released on drop.
+struct DirLock<'a> {
+    _guard: MutexGuard<'a, ()>,
+    file: std::fs::File,
+}
+impl Drop for DirLock<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = FileExt::unlock(&self.file) {
+            error!("{:?}", e);
+        }
     }
 }
 
-/////////////////////////////////////////////
-//                                         //
-//    TRAIT - IMPLEMENTED                  //
-//                                         //
-/////////////////////////////////////////////
-#[rocket::async_trait]
-pub trait DirectoryIndex: FileDir + FromFile + for<'de> Deserialize<'de> {
-    async fn list_dir(&self) -> Result<Vec<String>, String> {
-        let full_path = FileDir::get_full_path(self, true);
-        debug!("{:?}", full_path);
-        let read_dir = tokio::fs::read_dir(full_path);
-        match read_dir.await {
-            Ok(mut entries) => {
-                let mut files = vec![];
-                while let entry = entries.next_entry() {
-                    match entry.await {
-                        Ok(e) => match e {
-                            Some(unpacked_e) => match unpacked_e.file_name().into_string() {
-                                Ok(file_name) => match file_name.strip_suffix(".json") {
-                                    Some(cleaned) => {
-                                        files.push(cleaned.to_owned());
-                                    }
-                                    None => {}
-                                },
-                                Err(_) => {}
-                            },
-                            None => break,
-                        },
-                        Err(_) => break,
+/// The outcome of `FileStorage::migrate_ballots`.
+#[derive(Debug, Default, PartialEq)]
+pub struct BallotMigration {
+    pub moved: Vec<String>,
+    /// Files, which aren't JSON and are left where they are.
+    pub unparsable: Vec<String>,
+}
+
+impl FileStorage {
+    pub fn new(root: &str, assets: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            assets: assets.to_string(),
+            locks: Kind::ALL.into_iter().map(|k| (k, Mutex::new(()))).collect(),
+            journal: None,
+        }
+    }
+    /// Keeps new ballots in a JSON Lines file per voting.
+    pub fn with_journal(mut self) -> Self {
+        self.journal = Some(BallotJournal::new(&self.get_full_path(Kind::Ballot)));
+        self
+    }
+    /// The journal, if `kind` is kept in it.
+    fn journal(&self, kind: Kind) -> Option<&BallotJournal> {
+        match kind {
+            Kind::Ballot => self.journal.as_ref(),
+            _ => None,
+        }
+    }
+    async fn exists(&self, path: &str) -> bool {
+        File::open(path).await.is_ok()
+    }
+    /// Appends every ballot of the old layout to the journal of its voting
+    /// and removes its file. Ballots already in the journal are kept as they
+    /// are, so an interrupted migration can be run again.
+    pub async fn migrate_ballots(&self) -> Result<BallotMigration, VoteErrorKind<'static>> {
+        let journal = match self.journal(Kind::Ballot) {
+            Some(journal) => journal,
+            None => {
+                return Err(VoteErrorKind::Conflict(String::from(
+                    "The ballots aren't kept in a journal.",
+                )))
+            }
+        };
+        let _lock = self.lock(Kind::Ballot).await?;
+        let files = self.list_dir(Kind::Ballot).await;
+        let mut ids: Vec<String> = self
+            .read_index(Kind::Ballot)
+            .await
+            .into_iter()
+            .filter(|id| files.contains(id))
+            .collect();
+        ids.extend(
+            files
+                .into_iter()
+                .filter(|f| !ids.contains(f))
+                .collect::<Vec<_>>(),
+        );
+        let mut migration = BallotMigration::default();
+        for id in ids {
+            let path = self.get_file_path(Kind::Ballot, &id);
+            let raw = self.read_file(&path).await?;
+            match rocket::serde::json::from_str(&raw) {
+                Ok(entry) if BallotJournal::voting_of(&entry).is_ok() => {
+                    if !journal.contains(&id).await? {
+                        journal.append(&id, Some(entry)).await?;
                     }
+                    self.remove(path).await?;
+                    migration.moved.push(id);
                 }
-                Ok(files)
+                _ => migration.unparsable.push(id),
             }
-            Err(_) => Ok(vec![]),
         }
+        self.update_index(Kind::Ballot, migration.unparsable.clone())
+            .await?;
+        Ok(migration)
     }
-    async fn read_index(&self, uri: &str) -> Result<Vec<String>, String> {
-        debug!("Reading file from: {:?}", uri);
-        let raw_file = self.from_file(uri).await;
-        match rocket::serde::json::from_str::<Vec<String>>(&raw_file) {
-            Ok(json) => Ok(json),
-            Err(_votes) => Ok(vec![]),
+    fn get_full_path(&self, kind: Kind) -> String {
+        match kind {
+            Kind::Emojis => self.assets.clone() + kind.dir(),
+            _ => self.root.clone() + kind.dir(),
         }
     }
-}
-#[rocket::async_trait]
-pub trait IsUniqueFile: DirectoryIndex {
-    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind>
-    where
-        Self: Sync,
-    {
-        match self.list_dir().await {
-            Ok(files_exist) => match files_exist.iter().find(|v| match v.split_once('_') {
-                Some((_v_type, found_id)) => found_id.to_lowercase() == id.to_lowercase(),
-                None => true,
-            }) {
-                Some(s) => Ok(s.to_string()),
-                None => Err(VoteErrorKind::NotFound(String::from("Not found"))),
-            },
-            Err(_) => Err(VoteErrorKind::NotFound(String::from("Not found"))),
-        }
+    fn get_file_path(&self, kind: Kind, id: &str) -> String {
+        self.get_full_path(kind) + "/" + id + ".json"
+    }
+    fn get_index_path(&self, kind: Kind) -> String {
+        self.get_full_path(kind) + "/index.json"
     }
-}
 
-#[rocket::async_trait]
-pub trait FromFile {
-    async fn from_file(&self, path: &str) -> String
-    where
-        Self: Sized + for<'de> Deserialize<'de>,
-    {
-        match File::open(path).await {
-            Ok(r) => self.read_to_string(r).await,
-            Err(e) => {
-                error!("Error: {:?} on {:?}", e, path);
-                String::new()
+    async fn list_dir(&self, kind: Kind) -> Vec<String> {
+        let full_path = self.get_full_path(kind);
+        debug!("{:?}", full_path);
+        let mut files = vec![];
+        if let Ok(mut entries) = tokio::fs::read_dir(full_path).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if let Ok(file_name) = entry.file_name().into_string() {
+                    match file_name.strip_suffix(".json") {
+                        Some("index") | None => {}
+                        Some(cleaned) => files.push(cleaned.to_owned()),
+                    }
+                }
             }
         }
+        files
     }
-    async fn read_to_string(&self, f: File) -> String {
-        let mut contents = String::new();
-        f.into_std().await.read_to_string(&mut contents).unwrap();
-        contents
+    async fn read_index(&self, kind: Kind) -> Vec<String> {
+        let uri = self.get_index_path(kind);
+        debug!("Reading file from: {:?}", uri);
+        match self.read_file(&uri).await {
+            Ok(raw_file) => {
+                rocket::serde::json::from_str::<Vec<String>>(&raw_file).unwrap_or_default()
+            }
+            Err(_) => vec![],
+        }
     }
-}
-
-#[rocket::async_trait]
-pub trait ToFile: FileDir + IdGenerator + Selfaware {
-    #[cfg(test)]
-    async fn init_index(&self) -> Result<String, VoteErrorKind> {
-        Ok(String::from("Saved and index updated."))
-    }
-    #[cfg(not(test))]
-    async fn init_index(&self, index_file_path: &str) -> Result<String, VoteErrorKind> {
-        match self
-            .create(
-                String::from(index_file_path),
-                "[\"".to_owned() + &self.get_id() + "\"]",
-            )
-            .await
-        {
-            Ok(_) => Ok(String::from("Saved and index updated.")),
-            Err(e) => Err(e),
+    async fn read_file(&self, path: &str) -> Result<String, VoteErrorKind<'static>> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(contents) => Ok(contents),
+            Err(e) => {
+                error!("Error: {:?} on {:?}", e, path);
+                Err(VoteErrorKind::NotFound(String::from("Not found")))
+            }
         }
     }
-    async fn save_to_file(
+
+    /// Replaces `path` atomically with `stringified`.
+    async fn create(
         &self,
         path: String,
         stringified: String,
-    ) -> Result<String, VoteErrorKind> {
-        let created_artifact = match File::open(&path).await {
-            Ok(_f) => Err(VoteErrorKind::Conflict(self.get_type() + " already exist.")),
-            Err(_) => self.create(path, stringified).await,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let temp_path = format!(
+            "{}.{}.{}.tmp",
+            path,
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        );
+        if let Some(dir) = std::path::Path::new(&path).parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let written = match File::create(&temp_path).await {
+            Ok(f) => self.write_all(f, stringified).await,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(VoteErrorKind::IO(e));
+            }
+        };
+        let renamed = match written {
+            Ok(_) => tokio::fs::rename(&temp_path, &path).await,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return Err(e);
+            }
         };
-        match created_artifact {
-            Ok(_) => Ok(self.update_index().await?),
-            Err(e) => Err(e),
+        match renamed {
+            Ok(_) => self.sync_dir(&path).await,
+            Err(e) => {
+                error!("{:?}", e);
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                Err(VoteErrorKind::IO(e))
+            }
         }
     }
-
-    #[cfg(test)]
-    async fn create(&self, _path: String, _stringified: String) -> Result<String, VoteErrorKind> {
-        Ok(String::from("Done"))
-    }
-
-    #[cfg(not(test))]
-    async fn create(&self, path: String, stringified: String) -> Result<String, VoteErrorKind> {
-        match File::create(&path).await {
-            Ok(f) => self.write_all(f, stringified).await,
+    /// Persists the renaming of a file in its directory.
+    async fn sync_dir(&self, path: &str) -> Result<String, VoteErrorKind<'static>> {
+        let dir = match std::path::Path::new(path).parent() {
+            Some(dir) => dir,
+            None => return Ok(String::from("Done")),
+        };
+        match File::open(dir).await {
+            Ok(d) => {
+                d.sync_all().await?;
+                Ok(String::from("Done"))
+            }
             Err(e) => {
                 error!("{:?}", e);
                 Err(VoteErrorKind::IO(e))
             }
         }
     }
-    async fn write_all(&self, mut f: File, stringified: String) -> Result<String, VoteErrorKind> {
+    async fn write_all(
+        &self,
+        mut f: File,
+        stringified: String,
+    ) -> Result<String, VoteErrorKind<'static>> {
         match f.write_all(stringified.as_bytes()).await {
             Ok(_) => {
                 f.sync_all().await?;
@@ -173,132 +250,398 @@ pub trait ToFile: FileDir + IdGenerator + Selfaware {
             }
         }
     }
-    #[cfg(test)]
-    async fn update_index(&self) -> Result<String, VoteErrorKind> {
-        Ok(String::from("Saved and index updated."))
+    async fn remove(&self, path: String) -> Result<String, VoteErrorKind<'static>> {
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => self.sync_dir(&path).await,
+            Err(e) => {
+                error!("{:?}", e);
+                Err(VoteErrorKind::IO(e))
+            }
+        }
     }
+    fn get_lock_path(&self, kind: Kind) -> String {
+        self.get_index_path(kind) + ".lock"
+    }
+    /// Waits for the other tasks of the process first, so only one of them
+    /// blocks a thread on the file lock.
+    async fn lock(&self, kind: Kind) -> Result<DirLock<'_>, VoteErrorKind<'static>> {
+        let guard = self.locks[&kind].lock().await;
+        tokio::fs::create_dir_all(self.get_full_path(kind)).await?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.get_lock_path(kind))?;
+        let file = rocket::tokio::task::spawn_blocking(move || file.lock_exclusive().map(|_| file))
+            .await
+            .map_err(|e| {
+                error!("{:?}", e);
+                VoteErrorKind::Internal(String::from("Directory couldn't be locked."))
+            })??;
+        Ok(DirLock {
+            _guard: guard,
+            file,
+        })
+    }
+    async fn update_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        match rocket::serde::json::to_string(&ids) {
+            Ok(stringified) => self.create(self.get_index_path(kind), stringified).await,
+            Err(e) => Err(VoteErrorKind::Serialize(e)),
+        }
+    }
+}
 
-    #[cfg(not(test))]
-    async fn update_index(&self) -> Result<String, VoteErrorKind> {
-        let index_file_path = FileDir::get_full_path(self, true) + "/index.json";
-        match File::open(&index_file_path).await {
-            Ok(f) => {
-                let mut contents = String::new();
-                f.into_std().await.read_to_string(&mut contents).unwrap();
-                match rocket::serde::json::from_str::<Vec<String>>(&contents) {
-                    Ok(mut vec) => {
-                        vec.push(self.get_id());
-                        match self
-                            .create(
-                                index_file_path.clone(),
-                                rocket::serde::json::to_string(&vec).unwrap(),
-                            )
-                            .await
-                        {
-                            Ok(_) => Ok(String::from("Saved and index updated.")),
-                            Err(_) => self.init_index(&index_file_path).await,
-                        }
+#[rocket::async_trait]
+impl StorageBackend for FileStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let path = self.get_file_path(kind, id);
+        match self.journal(kind) {
+            Some(journal) if !self.exists(&path).await => journal.get(id).await,
+            _ => self.read_file(&path).await,
+        }
+    }
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let path = self.get_file_path(kind, id);
+        if let Some(journal) = self.journal(kind) {
+            let _lock = self.lock(kind).await?;
+            let exists = self.exists(&path).await;
+            return match mode {
+                PutMode::Replace if exists => self.create(path, entry).await,
+                PutMode::Create if exists || journal.contains(id).await? => Err(
+                    VoteErrorKind::Conflict(String::from("Entry already exist.")),
+                ),
+                _ => {
+                    let entry = rocket::serde::json::from_str(&entry)?;
+                    journal.append(id, Some(entry)).await?;
+                    Ok(String::from(match mode {
+                        PutMode::Create => "Saved and index updated.",
+                        PutMode::Replace => "Done",
+                    }))
+                }
+            };
+        }
+        match mode {
+            PutMode::Replace => self.create(path, entry).await,
+            PutMode::Create => {
+                let _lock = self.lock(kind).await?;
+                if File::open(&path).await.is_ok() {
+                    return Err(VoteErrorKind::Conflict(String::from(
+                        "Entry already exist.",
+                    )));
+                }
+                self.create(path.clone(), entry).await?;
+                let mut ids = self.read_index(kind).await;
+                ids.push(id.to_string());
+                // An entry missing in the index is taken back.
+                if let Err(e) = self.update_index(kind, ids).await {
+                    if let Err(removing) = self.remove(path).await {
+                        error!("{:?}", removing);
                     }
-                    Err(_e) => self.init_index(&index_file_path).await,
+                    return Err(e);
                 }
+                Ok(String::from("Saved and index updated."))
             }
-            Err(_) => self.init_index(&index_file_path).await,
         }
     }
-}
-
-impl DirectoryIndex for Votings {}
-impl FileDir for Votings {
-    fn get_dir() -> &'static str {
-        "votings"
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let mut ids = self.list_dir(kind).await;
+        if let Some(journal) = self.journal(kind) {
+            ids.append(&mut journal.ids().await?);
+        }
+        Ok(ids)
+    }
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let path = self.get_file_path(kind, id);
+        let _lock = self.lock(kind).await?;
+        if !self.exists(&path).await {
+            return match self.journal(kind) {
+                Some(journal) => {
+                    journal.append(id, None).await?;
+                    Ok(String::from("Deleted."))
+                }
+                None => Err(VoteErrorKind::NotFound(String::from("Not found"))),
+            };
+        }
+        self.remove(path).await?;
+        let ids = self
+            .read_index(kind)
+            .await
+            .into_iter()
+            .filter(|i| i != id)
+            .collect();
+        self.update_index(kind, ids).await?;
+        Ok(String::from("Deleted."))
+    }
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let mut ids = self.read_index(kind).await;
+        if let Some(journal) = self.journal(kind) {
+            ids.append(&mut journal.ids().await?);
+        }
+        Ok(ids)
+    }
+    fn keeps_index(&self) -> bool {
+        true
+    }
+    async fn rebuild_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let _lock = self.lock(kind).await?;
+        let ids = match self.journal(kind) {
+            Some(_) => {
+                let files = self.list_dir(kind).await;
+                ids.into_iter().filter(|id| files.contains(id)).collect()
+            }
+            None => ids,
+        };
+        self.update_index(kind, ids).await
+    }
+    async fn for_each_ballot(
+        &self,
+        voting: &str,
+        each: &mut (dyn for<'i, 'e> FnMut(&'i str, &'e str) + Send),
+    ) -> Result<bool, VoteErrorKind<'static>> {
+        match self.journal(Kind::Ballot) {
+            Some(journal) => {
+                journal.for_each(voting, each).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
     }
 }
 
-impl FromFile for Votings {}
+#[cfg(test)]
+mod test {
+    use super::*;
 
-impl FileDir for Voting {
-    fn get_dir() -> &'static str {
-        "votings"
+    fn test_storage() -> FileStorage {
+        let manifest_dir = std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default();
+        FileStorage::new(
+            &(manifest_dir.clone() + "/test-data/"),
+            &(manifest_dir + "/test-data/"),
+        )
     }
-}
-impl ToFile for Votings {}
-
-impl IsUniqueFile for Votings {}
-impl ToFile for Voting {}
-impl FromFile for Voting {}
 
-impl DirectoryIndex for Voting {}
-impl IsUniqueFile for Voting {}
+    #[rocket::async_test]
+    async fn reads_entries_and_index() {
+        let storage = test_storage();
+        let voting = storage.get(Kind::Voting, "voting").await.unwrap();
+        assert!(voting.contains("\"name\":\"Voting\""));
+        assert!(storage
+            .index(Kind::Voting)
+            .await
+            .unwrap()
+            .contains(&String::from("voting")));
+        assert!(storage.get(Kind::Voting, "missing").await.is_err());
+        assert_eq!(
+            storage.find(Kind::Candidate, "Joe").await.unwrap(),
+            "candidate_joe"
+        );
+    }
 
-impl FromFile for CastBallots {}
+    #[rocket::async_test]
+    async fn writes_entries_and_index() {
+        let root =
+            std::env::temp_dir().join(format!("voters-verdict-local-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("votings")).unwrap();
+        let root = root.to_string_lossy().to_string() + "/";
+        let storage = FileStorage::new(&root, &root);
 
-impl ToFile for CastBallots {}
-impl IsUniqueFile for CastBallots {}
-impl DirectoryIndex for CastBallots {}
+        let created = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert_eq!(created.unwrap(), "Saved and index updated.");
+        let conflict = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert!(matches!(conflict, Err(VoteErrorKind::Conflict(_))));
+        let replaced = storage
+            .put(Kind::Voting, "voting", String::from("[]"), PutMode::Replace)
+            .await;
+        assert_eq!(replaced.unwrap(), "Done");
+        assert_eq!(storage.get(Kind::Voting, "voting").await.unwrap(), "[]");
+        assert_eq!(storage.index(Kind::Voting).await.unwrap(), vec!["voting"]);
+        assert_eq!(storage.list(Kind::Voting).await.unwrap(), vec!["voting"]);
 
-impl FileDir for Candidate {
-    fn get_dir() -> &'static str {
-        "candidates"
+        storage.delete(Kind::Voting, "voting").await.unwrap();
+        assert!(storage.get(Kind::Voting, "voting").await.is_err());
+        assert!(storage.index(Kind::Voting).await.unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
     }
-}
-
-impl FromFile for Candidate {}
 
-impl ToFile for Candidate {}
+    #[rocket::async_test]
+    async fn takes_back_entries_missing_in_the_index() {
+        let root = std::env::temp_dir().join(format!(
+            "voters-verdict-local-unindexed-{}",
+            std::process::id()
+        ));
+        // An index, which can't be written.
+        std::fs::create_dir_all(root.join("votings/index.json")).unwrap();
+        let root = root.to_string_lossy().to_string() + "/";
+        let storage = FileStorage::new(&root, &root);
 
-impl DirectoryIndex for Candidate {}
-impl IsUniqueFile for Candidate {}
-
-impl FileDir for CastBallots {
-    fn get_dir() -> &'static str {
-        "ballots"
-    }
-    fn get_full_path(&self, _possible_remote: bool) -> String {
-        let root_path = match std::env::var(FILE_DIR) {
-            Ok(file_dir) => file_dir,
-            Err(_) => String::from("/tmp/"),
-        };
-        root_path.to_owned() + &<CastBallots as FileDir>::get_dir()
+        let created = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert!(created.is_err());
+        assert!(storage.get(Kind::Voting, "voting").await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
     }
-}
 
-impl ToFile for Criteria {}
-impl DirectoryIndex for Criteria {}
-impl FromFile for Criteria {}
+    #[rocket::async_test]
+    async fn keeps_index_of_concurrent_writes() {
+        let root =
+            std::env::temp_dir().join(format!("voters-verdict-concurrent-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("ballots")).unwrap();
+        let root = root.to_string_lossy().to_string() + "/";
+        let storage = FileStorage::new(&root, &root);
 
-impl IsUniqueFile for Criteria {}
-impl FileDir for Criteria {
-    fn get_dir() -> &'static str {
-        "criteria"
+        let ids: Vec<String> = (0..20).map(|i| format!("voting-voter{}-joe", i)).collect();
+        let writes = ids
+            .iter()
+            .map(|id| storage.put(Kind::Ballot, id, String::from("{}"), PutMode::Create));
+        for written in futures::future::join_all(writes).await {
+            written.unwrap();
+        }
+        let mut index = storage.index(Kind::Ballot).await.unwrap();
+        index.sort();
+        let mut listed = storage.list(Kind::Ballot).await.unwrap();
+        listed.sort();
+        assert_eq!(index.len(), 20);
+        assert_eq!(index, listed);
+        let temp_files = std::fs::read_dir(root.clone() + "ballots")
+            .unwrap()
+            .flatten()
+            .filter(|f| f.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(temp_files, 0);
+        std::fs::remove_dir_all(root).unwrap();
     }
-}
 
-impl FromFile for Criterion {}
+    #[rocket::async_test]
+    async fn migrates_ballots_into_journal() {
+        let root =
+            std::env::temp_dir().join(format!("voters-verdict-migrate-{}", std::process::id()));
+        let root = root.to_string_lossy().to_string() + "/";
+        let ballot = |voting: &str| format!("{{\"voting\":\"{}\",\"ballots\":[]}}", voting);
+        let files = FileStorage::new(&root, &root);
+        for (id, voting) in [
+            ("voting-obama-joe", "Voting"),
+            ("voting-a-obama-joe", "Voting-A"),
+        ] {
+            files
+                .put(Kind::Ballot, id, ballot(voting), PutMode::Create)
+                .await
+                .unwrap();
+        }
+
+        let storage = FileStorage::new(&root, &root).with_journal();
+        storage
+            .put(
+                Kind::Ballot,
+                "voting-michelle-joe",
+                ballot("Voting"),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+        let conflict = storage
+            .put(
+                Kind::Ballot,
+                "voting-obama-joe",
+                ballot("Voting"),
+                PutMode::Create,
+            )
+            .await;
+        assert!(matches!(conflict, Err(VoteErrorKind::Conflict(_))));
+        let mut listed = storage.list(Kind::Ballot).await.unwrap();
+        listed.sort();
+        assert_eq!(
+            listed,
+            vec![
+                "voting-a-obama-joe",
+                "voting-michelle-joe",
+                "voting-obama-joe"
+            ]
+        );
 
-impl ToFile for Criterion {}
+        let migration = storage.migrate_ballots().await.unwrap();
+        assert_eq!(
+            migration.moved,
+            vec!["voting-obama-joe", "voting-a-obama-joe"]
+        );
+        assert!(files.list(Kind::Ballot).await.unwrap().is_empty());
+        assert_eq!(
+            storage.index(Kind::Ballot).await.unwrap(),
+            vec![
+                "voting-michelle-joe",
+                "voting-obama-joe",
+                "voting-a-obama-joe"
+            ]
+        );
+        assert!(storage
+            .get(Kind::Ballot, "voting-a-obama-joe")
+            .await
+            .unwrap()
+            .contains("Voting-A"));
 
-impl IsUniqueFile for Criterion {}
-impl DirectoryIndex for Criterion {}
-impl FileDir for Criterion {
-    fn get_dir() -> &'static str {
-        "criteria"
+        storage
+            .delete(Kind::Ballot, "voting-obama-joe")
+            .await
+            .unwrap();
+        assert!(storage.get(Kind::Ballot, "voting-obama-joe").await.is_err());
+        let mut streamed = vec![];
+        let streams = storage
+            .for_each_ballot("voting", &mut |id, _| streamed.push(id.to_string()))
+            .await
+            .unwrap();
+        assert!(streams);
+        assert_eq!(streamed, vec!["voting-michelle-joe"]);
+        std::fs::remove_dir_all(root).unwrap();
     }
-}
 
-impl FileDir for EmojiCategories {
-    fn get_dir() -> &'static str {
-        "static"
-    }
-    fn get_full_path(&self, _possible_remote: bool) -> String {
-        let asset_dir = match std::env::var(ASSET_DIR) {
-            Ok(file_dir) => file_dir,
-            Err(_) => String::from("/tmp/"),
-        };
-        asset_dir + <EmojiCategories as FileDir>::get_dir()
+    #[rocket::async_test]
+    async fn waits_for_the_lock_of_other_processes() {
+        let root =
+            std::env::temp_dir().join(format!("voters-verdict-flock-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("votings")).unwrap();
+        let root = root.to_string_lossy().to_string() + "/";
+        let storage = FileStorage::new(&root, &root);
+
+        // Another process, like the admin tool rebuilding the index.
+        let other = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(storage.get_lock_path(Kind::Voting))
+            .unwrap();
+        other.lock_exclusive().unwrap();
+        let write = storage.put(Kind::Voting, "voting", String::from("{}"), PutMode::Create);
+        rocket::tokio::pin!(write);
+        let waited =
+            rocket::tokio::time::timeout(std::time::Duration::from_millis(100), &mut write).await;
+        assert!(waited.is_err());
+        assert!(storage.index(Kind::Voting).await.unwrap().is_empty());
+
+        FileExt::unlock(&other).unwrap();
+        assert_eq!(write.await.unwrap(), "Saved and index updated.");
+        assert_eq!(storage.index(Kind::Voting).await.unwrap(), vec!["voting"]);
+        assert_eq!(storage.list(Kind::Voting).await.unwrap(), vec!["voting"]);
+        std::fs::remove_dir_all(root).unwrap();
     }
 }
-impl FromFile for EmojiCategories {}
-impl ToFile for EmojiCategories {}
-
-impl IsUniqueFile for EmojiCategories {}
-impl DirectoryIndex for EmojiCategories {}
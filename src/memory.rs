@@ -0,0 +1,205 @@
+use crate::{
+    error::VoteErrorKind,
+    persistence::{Kind, PutMode, StorageBackend},
+};
+use rocket::{debug, warn};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+/// The entries of one kind and their index, like a directory of `FileStorage`.
+#[derive(Default)]
+struct Entries {
+    entries: BTreeMap<String, String>,
+    index: Vec<String>,
+}
+
+/// Keeps every entry in memory, nothing is written. Used by the tests and for
+/// demos, optionally seeded with the layout of `FileStorage`.
+#[derive(Default)]
+pub struct MemoryStorage {
+    kinds: RwLock<HashMap<Kind, Entries>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Copies the JSON files below `root` and the emojis below `assets`. Kinds
+    /// without a directory stay empty.
+    pub fn seeded(root: &str, assets: &str) -> Self {
+        let storage = Self::new();
+        if let Ok(mut kinds) = storage.kinds.write() {
+            for kind in Kind::ALL {
+                let dir = match kind {
+                    Kind::Emojis => assets.to_string() + kind.dir(),
+                    _ => root.to_string() + kind.dir(),
+                };
+                kinds.insert(kind, Self::read_dir(&dir));
+            }
+        }
+        storage
+    }
+    fn read_dir(dir: &str) -> Entries {
+        debug!("Seeding from {:?}", dir);
+        let mut seeded = Entries::default();
+        let files = match std::fs::read_dir(dir) {
+            Ok(files) => files,
+            Err(_) => return seeded,
+        };
+        for file in files.flatten() {
+            let file_name = match file.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+            let contents = match std::fs::read_to_string(file.path()) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    warn!("{:?} couldn't be seeded: {:?}", file_name, e);
+                    continue;
+                }
+            };
+            match file_name.strip_suffix(".json") {
+                Some("index") => {
+                    seeded.index = rocket::serde::json::from_str(&contents).unwrap_or_default()
+                }
+                Some(id) => {
+                    seeded.entries.insert(id.to_owned(), contents);
+                }
+                None => {}
+            }
+        }
+        seeded
+    }
+    fn poisoned() -> VoteErrorKind<'static> {
+        VoteErrorKind::Internal(String::from("Memory storage is poisoned."))
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let kinds = self.kinds.read().map_err(|_| Self::poisoned())?;
+        match kinds.get(&kind).and_then(|e| e.entries.get(id)) {
+            Some(entry) => Ok(entry.clone()),
+            None => Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        }
+    }
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let mut kinds = self.kinds.write().map_err(|_| Self::poisoned())?;
+        let entries = kinds.entry(kind).or_default();
+        match mode {
+            PutMode::Replace => {
+                entries.entries.insert(id.to_string(), entry);
+                Ok(String::from("Done"))
+            }
+            PutMode::Create => {
+                if entries.entries.contains_key(id) {
+                    return Err(VoteErrorKind::Conflict(String::from(
+                        "Entry already exist.",
+                    )));
+                }
+                entries.entries.insert(id.to_string(), entry);
+                entries.index.push(id.to_string());
+                Ok(String::from("Saved and index updated."))
+            }
+        }
+    }
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let kinds = self.kinds.read().map_err(|_| Self::poisoned())?;
+        Ok(kinds
+            .get(&kind)
+            .map(|e| e.entries.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let mut kinds = self.kinds.write().map_err(|_| Self::poisoned())?;
+        let entries = kinds.entry(kind).or_default();
+        if entries.entries.remove(id).is_none() {
+            return Err(VoteErrorKind::NotFound(String::from("Not found")));
+        }
+        entries.index.retain(|i| i != id);
+        Ok(String::from("Deleted."))
+    }
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let kinds = self.kinds.read().map_err(|_| Self::poisoned())?;
+        Ok(kinds
+            .get(&kind)
+            .map(|e| e.index.clone())
+            .unwrap_or_default())
+    }
+    fn keeps_index(&self) -> bool {
+        true
+    }
+    async fn rebuild_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let mut kinds = self.kinds.write().map_err(|_| Self::poisoned())?;
+        kinds.entry(kind).or_default().index = ids;
+        Ok(String::from("Done"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[rocket::async_test]
+    async fn round_trips_entries() {
+        let storage = MemoryStorage::new();
+        let created = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert_eq!(created.unwrap(), "Saved and index updated.");
+        let conflict = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert!(matches!(conflict, Err(VoteErrorKind::Conflict(_))));
+        storage
+            .put(Kind::Voting, "voting", String::from("[]"), PutMode::Replace)
+            .await
+            .unwrap();
+        assert_eq!(storage.get(Kind::Voting, "voting").await.unwrap(), "[]");
+        assert_eq!(storage.index(Kind::Voting).await.unwrap(), vec!["voting"]);
+
+        assert_eq!(
+            storage.delete(Kind::Voting, "voting").await.unwrap(),
+            "Deleted."
+        );
+        assert!(storage.get(Kind::Voting, "voting").await.is_err());
+        assert!(storage.index(Kind::Voting).await.unwrap().is_empty());
+        assert!(storage.delete(Kind::Voting, "voting").await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn seeds_from_test_data() {
+        let test_data =
+            std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/";
+        let storage = MemoryStorage::seeded(&test_data, &test_data);
+        let voting = storage.get(Kind::Voting, "voting").await.unwrap();
+        assert!(voting.contains("\"name\":\"Voting\""));
+        assert_eq!(storage.list(Kind::Ballot).await.unwrap().len(), 3);
+        assert!(!storage
+            .list(Kind::Candidate)
+            .await
+            .unwrap()
+            .contains(&String::from("index")));
+        assert_eq!(
+            storage.find(Kind::Candidate, "Joe").await.unwrap(),
+            "candidate_joe"
+        );
+        assert!(storage.list(Kind::Operator).await.unwrap().is_empty());
+    }
+}
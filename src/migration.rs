@@ -0,0 +1,314 @@
+use crate::{
+    common::{Candidate, CastBallots, Criterion, EmojiCategories, IdGenerator, Operator, Voting},
+    error::VoteErrorKind,
+    persistence::{Kind, PutMode, StorageBackend},
+};
+use rocket::{
+    error,
+    serde::{de::Error, json, Deserialize, Serialize},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+/// The kinds in the order they are copied, ballots after their votings.
+/// Results are counted anew by the target.
+pub const MIGRATED_KINDS: [Kind; 6] = [
+    Kind::Criterion,
+    Kind::Candidate,
+    Kind::Voting,
+    Kind::Operator,
+    Kind::Emojis,
+    Kind::Ballot,
+];
+
+/// What a migration did with the entries of a kind.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct MigrationReport {
+    pub kind: String,
+    /// Entries of the source.
+    pub read: usize,
+    /// Entries written to the target, or which would be written on a dry run.
+    pub written: usize,
+    /// Entries the target has already, with the same content.
+    pub existing: usize,
+    /// Entries the target has already, with another content. They are kept.
+    pub differing: Vec<String>,
+    /// Entries of the source, which can't be read as their type.
+    pub unparsable: Vec<String>,
+    /// Ids of the source index without an entry, they are skipped.
+    pub missing: Vec<String>,
+    /// Entries the target refused, e.g. a voting with a candidate twice
+    /// can't be stored in a database.
+    pub failed: Vec<String>,
+    /// Entries of the source found in the target afterwards, on a dry run
+    /// the ones, which are or would be.
+    pub found: usize,
+    pub source_checksum: String,
+    /// Equal to `source_checksum`, if the target holds nothing else.
+    pub target_checksum: String,
+}
+impl MigrationReport {
+    /// Whether the target holds every entry of the source as it is.
+    pub fn is_verified(&self) -> bool {
+        self.read == self.found
+            && self.differing.is_empty()
+            && self.unparsable.is_empty()
+            && self.failed.is_empty()
+    }
+}
+
+/// Where a migration is, passed after every entry.
+#[derive(Debug, PartialEq)]
+pub struct Progress<'a> {
+    pub kind: Kind,
+    pub id: &'a str,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// The id the type of `raw` gives it and `raw` as its type prints it. The
+/// backends use different ids and store the same entry in different ways,
+/// e.g. a database keeps a criterion by its name and lowercases the voting of
+/// a ballot, so entries are compared after parsing.
+pub(crate) fn canonical(
+    kind: Kind,
+    raw: &str,
+) -> Result<(String, String), json::serde_json::Error> {
+    fn typed<T: for<'de> Deserialize<'de> + Serialize + IdGenerator>(
+        raw: &str,
+    ) -> Result<(String, String), json::serde_json::Error> {
+        let entry = json::from_str::<T>(raw)?;
+        Ok((entry.get_id(), json::to_string(&entry)?))
+    }
+    match kind {
+        Kind::Voting => typed::<Voting>(raw),
+        Kind::Ballot => {
+            let mut cast_ballots = json::from_str::<CastBallots>(raw)?;
+            cast_ballots.voting = cast_ballots.voting.map(|v| v.to_lowercase());
+            Ok((cast_ballots.get_id(), json::to_string(&cast_ballots)?))
+        }
+        Kind::Candidate => typed::<Candidate>(raw),
+        Kind::Criterion => typed::<Criterion>(raw),
+        Kind::Operator => typed::<Operator>(raw),
+        Kind::Emojis => typed::<EmojiCategories>(raw),
+        Kind::Result => Err(Error::custom("Results are counted by the target.")),
+    }
+}
+
+/// A checksum of entries, independent of their order.
+#[derive(Default)]
+struct Checksum(u64);
+impl Checksum {
+    fn add(&mut self, id: &str, canonical: &str) {
+        let mut hasher = DefaultHasher::new();
+        (id, canonical).hash(&mut hasher);
+        self.0 ^= hasher.finish();
+    }
+    fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// The ids of `kind` in the order they were created, entries missing in the
+/// index after them. Without an index of its own, `list` of a backend may
+/// not be ids, see `DatabaseStorage`.
+pub(crate) async fn ids_of(
+    storage: &dyn StorageBackend,
+    kind: Kind,
+) -> Result<Vec<String>, VoteErrorKind<'static>> {
+    let mut ids = storage.index(kind).await?;
+    if !storage.keeps_index() {
+        return Ok(ids);
+    }
+    for id in storage.list(kind).await? {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// Every readable entry of `kind` by the id of its type.
+pub(crate) async fn entries_of(
+    storage: &dyn StorageBackend,
+    kind: Kind,
+) -> Result<HashMap<String, String>, VoteErrorKind<'static>> {
+    let mut entries = HashMap::new();
+    for id in ids_of(storage, kind).await? {
+        let raw = match storage.get(kind, &id).await {
+            Ok(raw) => raw,
+            Err(VoteErrorKind::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        if let Ok((id, entry)) = canonical(kind, &raw) {
+            entries.insert(id, entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Copies the entries of `kind` from `source` to `target` and reads the
+/// target again to verify it.
+pub async fn migrate_kind(
+    source: &dyn StorageBackend,
+    target: &dyn StorageBackend,
+    kind: Kind,
+    dry_run: bool,
+    progress: &mut (dyn FnMut(Progress) + Send),
+) -> Result<MigrationReport, VoteErrorKind<'static>> {
+    let mut report = MigrationReport {
+        kind: kind.dir().to_string(),
+        ..Default::default()
+    };
+    let mut source_sum = Checksum::default();
+    let ids = ids_of(source, kind).await?;
+    let in_target = entries_of(target, kind).await?;
+    let mut copied = vec![];
+    for (done, id) in ids.iter().enumerate() {
+        progress(Progress {
+            kind,
+            id,
+            done: done + 1,
+            total: ids.len(),
+        });
+        let raw = match source.get(kind, id).await {
+            Ok(raw) => raw,
+            Err(VoteErrorKind::NotFound(_)) => {
+                report.missing.push(id.clone());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        report.read += 1;
+        let (id, entry) = match canonical(kind, &raw) {
+            Ok(canonical) => canonical,
+            Err(_) => {
+                report.unparsable.push(id.clone());
+                continue;
+            }
+        };
+        source_sum.add(&id, &entry);
+        match in_target.get(&id) {
+            Some(existing) if *existing == entry => report.existing += 1,
+            Some(_) => report.differing.push(id.clone()),
+            None if dry_run => report.written += 1,
+            None => match target.put(kind, &id, raw, PutMode::Create).await {
+                Ok(_) => report.written += 1,
+                Err(e) => {
+                    error!("{} {}: {:?}", kind.dir(), id, e);
+                    report.failed.push(id.clone());
+                }
+            },
+        }
+        copied.push((id, entry));
+    }
+    let in_target = match dry_run {
+        true => in_target,
+        false => entries_of(target, kind).await?,
+    };
+    report.found = copied
+        .iter()
+        .filter(|(id, entry)| dry_run || in_target.get(id) == Some(entry))
+        .count();
+    let mut target_sum = Checksum::default();
+    for (id, entry) in &in_target {
+        target_sum.add(id, entry);
+    }
+    report.source_checksum = source_sum.hex();
+    report.target_checksum = target_sum.hex();
+    Ok(report)
+}
+
+/// Copies every voting, candidate, criterion, operator, emoji category and
+/// ballot from `source` to `target`. Entries the target has already are kept,
+/// so an interrupted migration can be run again. A dry run only reads.
+pub async fn migrate(
+    source: &dyn StorageBackend,
+    target: &dyn StorageBackend,
+    dry_run: bool,
+    progress: &mut (dyn FnMut(Progress) + Send),
+) -> Result<Vec<MigrationReport>, VoteErrorKind<'static>> {
+    let mut reports = vec![];
+    for kind in MIGRATED_KINDS {
+        reports.push(migrate_kind(source, target, kind, dry_run, progress).await?);
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MemoryStorage;
+
+    fn test_data() -> MemoryStorage {
+        let test_data =
+            std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/";
+        MemoryStorage::seeded(&test_data, &test_data)
+    }
+
+    #[rocket::async_test]
+    async fn copies_every_kind() {
+        let source = test_data();
+        let target = MemoryStorage::new();
+        let mut steps = 0;
+
+        let dry_run = migrate(&source, &target, true, &mut |_| steps += 1)
+            .await
+            .unwrap();
+        assert!(dry_run.iter().all(|r| r.is_verified()));
+        assert!(target.list(Kind::Voting).await.unwrap().is_empty());
+
+        let reports = migrate(&source, &target, false, &mut |_| {}).await.unwrap();
+        assert_eq!(reports.len(), MIGRATED_KINDS.len());
+        for report in &reports {
+            assert!(report.is_verified(), "{:?}", report);
+            assert_eq!(report.written, report.read);
+            assert_eq!(report.source_checksum, report.target_checksum);
+        }
+        let ballots = reports.iter().find(|r| r.kind == "ballots").unwrap();
+        assert_eq!(ballots.read, 3);
+        assert_eq!(
+            steps,
+            reports
+                .iter()
+                .map(|r| r.read + r.missing.len())
+                .sum::<usize>()
+        );
+        assert_eq!(
+            target.index(Kind::Ballot).await.unwrap(),
+            source.index(Kind::Ballot).await.unwrap()
+        );
+
+        let again = migrate(&source, &target, false, &mut |_| {}).await.unwrap();
+        assert!(again.iter().all(|r| r.written == 0 && r.existing == r.read));
+    }
+
+    #[rocket::async_test]
+    async fn keeps_differing_entries() {
+        let source = test_data();
+        let target = MemoryStorage::new();
+        let voting = source.get(Kind::Voting, "voting").await.unwrap();
+        target
+            .put(
+                Kind::Voting,
+                "voting",
+                voting.replace("W1234", "X9876"),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+        let report = migrate_kind(&source, &target, Kind::Voting, false, &mut |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.differing, vec!["voting"]);
+        assert!(!report.is_verified());
+        assert!(target
+            .get(Kind::Voting, "voting")
+            .await
+            .unwrap()
+            .contains("X9876"));
+    }
+}
@@ -0,0 +1,174 @@
+use rocket::{
+    get,
+    http::Status,
+    post, put,
+    response::status::Created,
+    serde::{json::Json, Deserialize, Serialize},
+};
+
+use crate::{
+    authentication::{can, close_sessions_of, Authorized, UserRole},
+    common::{Empty, Fill, Operator},
+    csrf::CsrfVerified,
+    persistence::ToPersistence,
+    routes::API_OPERATORS,
+    serialize::ToStorage,
+};
+
+#[derive(Debug, Serialize, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CreateOperator {
+    pub name: String,
+    pub password: String,
+    pub role: UserRole,
+}
+
+#[derive(Debug, Serialize, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OperatorModification {
+    pub password: Option<String>,
+    pub role: Option<UserRole>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct OperatorAccount {
+    pub name: String,
+    pub role: UserRole,
+}
+impl From<Operator> for OperatorAccount {
+    fn from(operator: Operator) -> Self {
+        Self {
+            name: operator.name,
+            role: operator.role,
+        }
+    }
+}
+
+pub async fn list_operators() -> Vec<OperatorAccount> {
+    let operator_index = Operator::empty().index().await.unwrap_or_default();
+    let operators: Vec<_> = operator_index
+        .iter()
+        .map(|o| Operator::fill(o, true, "operator"))
+        .collect();
+    futures::future::join_all(operators)
+        .await
+        .into_iter()
+        .filter(|o| !o.name.is_empty())
+        .map(OperatorAccount::from)
+        .collect()
+}
+
+/// Creates the first admin account. Refuses to run once any admin exists,
+/// so it can't be used to take over an already configured instance.
+pub async fn bootstrap_admin(name: &str, password: &str) -> Result<String, String> {
+    if list_operators()
+        .await
+        .iter()
+        .any(|o| o.role == UserRole::Admin)
+    {
+        return Err(String::from("An admin already exists."));
+    }
+    save_operator(name, password, UserRole::Admin).await
+}
+
+async fn save_operator(name: &str, password: &str, role: UserRole) -> Result<String, String> {
+    if !Operator::is_valid_name(name) {
+        return Err(String::from(
+            "Operator name needs to match [a-z0-9-] with 3 to 32 characters.",
+        ));
+    }
+    let operator = Operator::new(name, password, role).map_err(|e| e.to_string())?;
+    match operator.save().await {
+        Ok(done) => Ok(done),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[get("/")]
+pub async fn get_operators(
+    _authorized: Authorized<can::ManageOperators>,
+) -> Json<Vec<OperatorAccount>> {
+    Json(list_operators().await)
+}
+
+#[post("/", format = "application/json", data = "<operator>")]
+pub async fn post_operator(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageOperators>,
+    operator: Json<CreateOperator>,
+) -> Result<Created<&'static str>, Status> {
+    let payload = operator.into_inner();
+    match save_operator(&payload.name, &payload.password, payload.role).await {
+        Ok(_done) => {
+            authorized.user.audit("created operator", &payload.name);
+            Ok(Created::new(API_OPERATORS.to_owned() + "/" + &payload.name))
+        }
+        Err(e) => {
+            rocket::info!("{:?}", e);
+            Err(Status::Conflict)
+        }
+    }
+}
+
+#[put("/<name>", format = "application/json", data = "<modifications>")]
+pub async fn modify_operator(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::ManageOperators>,
+    name: &str,
+    modifications: Json<OperatorModification>,
+) -> Result<String, Status> {
+    let mut operator = Operator::fill(name, true, "operator").await;
+    if operator.name.is_empty() {
+        return Err(Status::NotFound);
+    }
+    let unpacked_payload = modifications.into_inner();
+    if let Some(password) = unpacked_payload.password {
+        if operator.set_password(&password).is_err() {
+            return Err(Status::UnprocessableEntity);
+        }
+    }
+    if let Some(role) = unpacked_payload.role {
+        operator.role = role;
+    }
+    authorized.user.audit("modified operator", &operator.name);
+    match operator.update().await {
+        Ok(done) => {
+            close_sessions_of(&operator.name);
+            Ok(done)
+        }
+        Err(_e) => Err(Status::Conflict),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::authentication::{ElevatedUser, Permission};
+
+    #[test]
+    fn operator_password_round_trip() {
+        let operator = Operator::new("Alice", "correct horse", UserRole::Admin).unwrap();
+        assert_eq!(operator.name, "alice");
+        assert!(operator.verify("correct horse"));
+        assert!(!operator.verify("wrong horse"));
+    }
+
+    #[test]
+    fn operator_password_too_short() {
+        assert!(Operator::new("alice", "short", UserRole::Admin).is_err());
+    }
+
+    #[test]
+    fn operator_name_validation() {
+        assert!(Operator::is_valid_name("alice-1"));
+        assert!(!Operator::is_valid_name("al"));
+        assert!(!Operator::is_valid_name("alice_1"));
+    }
+
+    #[test]
+    fn maintainer_can_not_manage_operators() {
+        assert!(!ElevatedUser::new_maintainer().has_permission(Permission::ManageOperators));
+        assert!(ElevatedUser::new_admin().has_permission(Permission::ManageOperators));
+    }
+}
@@ -1,21 +1,253 @@
 use crate::{
-    common::{IdGenerator, Selfaware},
-    config::STORAGE_MODE,
+    ballots::TableRow,
+    cache::CachedStorage,
+    config::{ASSET_DIR, FILE_DIR, STORAGE_MODE},
     error::VoteErrorKind,
+    memory::MemoryStorage,
 };
-#[cfg(feature = "local")]
-use rocket::error;
-use rocket::{debug, serde::Deserialize};
+use rocket::{debug, error, fairing::AdHoc, figment::Figment, info, warn};
+use std::sync::{Arc, OnceLock};
 
+#[cfg(feature = "db")]
+use crate::db::{storage::DatabaseStorage, Database};
 #[cfg(feature = "local")]
+use crate::{config::BALLOT_LAYOUT, local::FileStorage};
+#[cfg(feature = "remote")]
+use crate::{config::REMOTE_STORAGE, remote::RemoteStorage};
+#[cfg(feature = "s3")]
 use crate::{
-    config::FILE_DIR,
-    local::{DirectoryIndex, FileDir, IsUniqueFile, ToFile},
+    config::{S3_ACCESS_KEY, S3_BUCKET, S3_ENDPOINT, S3_PREFIX, S3_REGION, S3_SECRET_KEY},
+    s3::{Credentials, S3Storage},
 };
-#[cfg(feature = "remote")]
-use crate::{config::REMOTE_STORAGE, remote::RemoteIndex};
-#[cfg(feature = "db")]
-use crate::{config::SQLITE_CONNECTION, db::common::Query};
+
+/////////////////////////////////////////////
+//                                         //
+//          STORAGE BACKEND                //
+//                                         //
+/////////////////////////////////////////////
+
+/// The kinds of entries a backend stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Voting,
+    Ballot,
+    Candidate,
+    Criterion,
+    Operator,
+    Emojis,
+    /// The running totals of a voting, see `aggregate::ResultsAggregate`.
+    Result,
+}
+impl Kind {
+    pub const ALL: [Kind; 7] = [
+        Kind::Voting,
+        Kind::Ballot,
+        Kind::Candidate,
+        Kind::Criterion,
+        Kind::Operator,
+        Kind::Emojis,
+        Kind::Result,
+    ];
+    /// The directory of the file based backends.
+    pub fn dir(self) -> &'static str {
+        match self {
+            Kind::Voting => "votings",
+            Kind::Ballot => "ballots",
+            Kind::Candidate => "candidates",
+            Kind::Criterion => "criteria",
+            Kind::Operator => "operators",
+            Kind::Emojis => "static",
+            Kind::Result => "results",
+        }
+    }
+}
+
+/// How `put` treats an existing entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutMode {
+    /// Fails with `VoteErrorKind::Conflict` if the entry exists and adds a new
+    /// one to the index.
+    Create,
+    /// Overwrites the entry, the index stays as it is.
+    Replace,
+}
+
+/// Where votings, ballots, users and operators are kept. Entries are passed as
+/// JSON, the typed `FromStorage` and `ToStorage` traits build on top of it.
+#[rocket::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// The name shown in the logs.
+    fn name(&self) -> &'static str;
+    /// The entry `id` of `kind`, `VoteErrorKind::NotFound` if there is none.
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>>;
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>>;
+    /// Every stored entry of `kind`.
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>>;
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>>;
+    /// The ids of `kind` in the order they were created.
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>>;
+
+    /// Whether the index is kept apart from the entries and can drift from
+    /// `list`, like the `index.json` of `FileStorage`.
+    fn keeps_index(&self) -> bool {
+        false
+    }
+    /// Replaces the index of `kind` with `ids`.
+    async fn rebuild_index(
+        &self,
+        _kind: Kind,
+        _ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        Err(VoteErrorKind::Conflict(format!(
+            "The {} storage has no index to rebuild.",
+            self.name()
+        )))
+    }
+    /// Checks on startup, what can't be checked by building the backend, e.g.
+    /// the certificate of a remote storage.
+    async fn verify(&self) -> Result<(), VoteErrorKind<'static>> {
+        Ok(())
+    }
+
+    /// The full id of `id`, which is stored with a prefix like `candidate_`.
+    async fn find(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let ids = match self.list(kind).await {
+            Ok(ids) => ids,
+            Err(_) => return Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        };
+        match ids.iter().find(|v| match v.split_once('_') {
+            Some((_v_type, found_id)) => found_id.to_lowercase() == id.to_lowercase(),
+            None => true,
+        }) {
+            Some(s) => Ok(s.to_string()),
+            None => Err(VoteErrorKind::NotFound(String::from("Not found"))),
+        }
+    }
+    /// The ballots of a voting with their sum, weighted sum and mean, if the
+    /// backend computes them itself. `None` computes them out of every ballot.
+    async fn results(
+        &self,
+        _voting: &str,
+        _filter: Option<(&str, &str)>,
+        _order_by: &str,
+    ) -> Result<Option<Vec<TableRow>>, VoteErrorKind<'static>> {
+        Ok(None)
+    }
+    /// Whether `results` always computes the rows, so no aggregate is kept.
+    fn computes_results(&self) -> bool {
+        false
+    }
+    /// Passes the id and entry of every ballot of `voting` to `each`, while
+    /// they are read. `false` if the backend can't tell the ballots of a
+    /// voting apart, they are loaded by their ids then.
+    async fn for_each_ballot(
+        &self,
+        _voting: &str,
+        _each: &mut (dyn for<'i, 'e> FnMut(&'i str, &'e str) + Send),
+    ) -> Result<bool, VoteErrorKind<'static>> {
+        Ok(false)
+    }
+}
+
+/// The backend as it is managed by rocket.
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// The backend chosen at startup, shared with the typed traits, which aren't
+/// called from request handlers only.
+static STORAGE: OnceLock<Storage> = OnceLock::new();
+
+/// Returns the shared backend. Outside of a rocket, like in the admin binary or
+/// in tests, it is chosen on first use.
+pub fn storage() -> Result<&'static Storage, VoteErrorKind<'static>> {
+    if let Some(storage) = STORAGE.get() {
+        return Ok(storage);
+    }
+    let storage: Storage = Arc::new(CachedStorage::new(
+        PersistenceMode::detect_config_mode()?.backend()?,
+    ));
+    Ok(STORAGE.get_or_init(|| storage))
+}
+
+/// Whether tests on the chosen backend skip, as its database isn't at hand.
+#[cfg(test)]
+pub fn skips_without_storage() -> bool {
+    #[cfg(feature = "db")]
+    if let Ok(Some(database)) = PersistenceMode::detect_config_mode().map(|m| m.database()) {
+        return crate::db::skips_without(database);
+    }
+    false
+}
+
+/// Rocket config of the chosen backend, like the url of a database.
+/// An unknown mode is refused by `stage`.
+pub fn figment() -> Figment {
+    let mode = match PersistenceMode::detect_config_mode() {
+        Ok(mode) => mode,
+        Err(_) => return rocket::Config::figment(),
+    };
+    #[cfg(feature = "db")]
+    if let Some(database) = mode.database() {
+        if mode.missing_config().is_none() {
+            match database.figment() {
+                Ok(figment) => return figment,
+                Err(e) => error!("{:?}", e),
+            }
+        }
+    }
+    debug!("{} needs no extra config.", mode.name());
+    rocket::Config::figment()
+}
+
+/// Chooses the backend, prepares it and manages it as `Storage`.
+pub fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Storage", |rocket| async {
+        let mode = match PersistenceMode::detect_config_mode() {
+            Ok(mode) => mode,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(rocket);
+            }
+        };
+        if let Some(variable) = mode.missing_config() {
+            error!("The {} storage needs {} to be set.", mode.name(), variable);
+            return Err(rocket);
+        }
+        #[cfg(feature = "db")]
+        let rocket = match mode.database() {
+            Some(database) => rocket.attach(database.stage()),
+            None => rocket,
+        };
+        let verified = match storage() {
+            Ok(storage) => storage.verify().await.map(|_| storage),
+            Err(e) => Err(e),
+        };
+        match verified {
+            Ok(storage) => {
+                info!("Storage: {}", storage.name());
+                if mode.name() == "memory" {
+                    warn!("Nothing is written, every change is lost on shutdown.");
+                }
+                Ok(rocket.manage(storage.clone()))
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          PERSISTENCE MODE               //
+//                                         //
+/////////////////////////////////////////////
 
 pub enum PersistenceMode {
     #[cfg(feature = "db")]
@@ -24,257 +256,367 @@ pub enum PersistenceMode {
     File(FileConfig),
     #[cfg(feature = "remote")]
     Remote(RemoteConfig),
+    #[cfg(feature = "s3")]
+    S3(S3Config),
+    Memory(MemoryConfig),
 }
 #[cfg(feature = "remote")]
 pub struct RemoteConfig {
     url: Option<String>,
 }
+#[cfg(feature = "s3")]
+pub struct S3Config {
+    endpoint: Option<String>,
+    bucket: Option<String>,
+    region: String,
+    prefix: String,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+}
 #[cfg(feature = "local")]
 pub struct FileConfig {
     dir: String,
+    assets: String,
+    /// Whether ballots are appended to a JSON Lines file per voting.
+    journal: bool,
+}
+#[cfg(feature = "local")]
+impl FileConfig {
+    pub fn from_env() -> Self {
+        FileConfig {
+            dir: match std::env::var(FILE_DIR) {
+                Ok(file_dir) => file_dir,
+                Err(_) => String::from("/tmp/"),
+            },
+            assets: match std::env::var(ASSET_DIR) {
+                Ok(asset_dir) => asset_dir,
+                Err(_) => String::from("/tmp/"),
+            },
+            journal: match std::env::var(BALLOT_LAYOUT).as_deref() {
+                Ok("jsonl") => true,
+                Ok("files") | Err(_) => false,
+                Ok(layout) => {
+                    warn!("Unknown {} {}, using files", BALLOT_LAYOUT, layout);
+                    false
+                }
+            },
+        }
+    }
+    pub fn journal(&self) -> bool {
+        self.journal
+    }
+    pub fn storage(self) -> FileStorage {
+        let storage = FileStorage::new(&self.dir, &self.assets);
+        match self.journal {
+            true => storage.with_journal(),
+            false => storage,
+        }
+    }
+}
+
+/// The directories a `MemoryStorage` is seeded from, it starts empty without.
+pub struct MemoryConfig {
+    dir: Option<String>,
+    assets: Option<String>,
 }
 
+/// One of the databases built in and its url.
 #[cfg(feature = "db")]
 pub struct DatabaseConfig {
+    database: Database,
     url: Option<String>,
 }
 
 impl PersistenceMode {
-    pub fn detect_config_mode() -> Self {
+    /// The mode of `STORAGE_MODE`, the default if it isn't set. A mode, which
+    /// isn't built in, is an error.
+    pub fn detect_config_mode() -> Result<Self, VoteErrorKind<'static>> {
         match std::env::var(STORAGE_MODE) {
-            Ok(s) => PersistenceMode::from(s.as_str()),
-            Err(_) => PersistenceMode::default_mode(),
+            Ok(s) => PersistenceMode::named(&s),
+            Err(_) => Ok(PersistenceMode::default_mode()),
         }
     }
-    #[cfg(feature = "local")]
+    /// A database, if one is built in, the files otherwise. The tests of the
+    /// file based backends run on a copy of `test-data` in memory.
+    #[cfg(feature = "db")]
     fn default_mode() -> Self {
-        PersistenceMode::File(FileConfig {
-            dir: match std::env::var(FILE_DIR) {
-                Ok(file_dir) => file_dir,
-                Err(_) => String::from("/tmp/"),
-            },
+        PersistenceMode::of_database(Database::default())
+    }
+    #[cfg(all(feature = "local", not(feature = "db"), not(test)))]
+    fn default_mode() -> Self {
+        PersistenceMode::file()
+    }
+    #[cfg(all(
+        feature = "remote",
+        not(feature = "local"),
+        not(feature = "db"),
+        not(test)
+    ))]
+    fn default_mode() -> Self {
+        PersistenceMode::remote()
+    }
+    #[cfg(all(
+        not(any(feature = "local", feature = "remote")),
+        not(feature = "db"),
+        not(test)
+    ))]
+    fn default_mode() -> Self {
+        PersistenceMode::memory()
+    }
+    #[cfg(all(not(feature = "db"), test))]
+    fn default_mode() -> Self {
+        let test_data =
+            std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/";
+        PersistenceMode::Memory(MemoryConfig {
+            dir: Some(test_data.clone()),
+            assets: Some(test_data),
         })
     }
     #[cfg(feature = "db")]
-    fn default_mode() -> Self {
+    fn of_database(database: Database) -> Self {
         PersistenceMode::DB(DatabaseConfig {
-            url: match std::env::var(SQLITE_CONNECTION) {
-                Ok(connection) => Some(connection),
-                Err(_) => panic!("No sqlite connnection backup defined"),
-            },
+            database,
+            url: std::env::var(database.variable()).ok(),
+        })
+    }
+    #[cfg(feature = "local")]
+    fn file() -> Self {
+        PersistenceMode::File(FileConfig::from_env())
+    }
+    fn memory() -> Self {
+        PersistenceMode::Memory(MemoryConfig {
+            dir: std::env::var(FILE_DIR).ok(),
+            assets: std::env::var(ASSET_DIR).ok(),
+        })
+    }
+    #[cfg(feature = "remote")]
+    fn remote() -> Self {
+        PersistenceMode::Remote(RemoteConfig {
+            url: std::env::var(REMOTE_STORAGE).ok(),
+        })
+    }
+    #[cfg(feature = "s3")]
+    fn s3() -> Self {
+        PersistenceMode::S3(S3Config {
+            endpoint: std::env::var(S3_ENDPOINT).ok(),
+            bucket: std::env::var(S3_BUCKET).ok(),
+            region: std::env::var(S3_REGION).unwrap_or_else(|_| String::from("us-east-1")),
+            prefix: std::env::var(S3_PREFIX).unwrap_or_default(),
+            access_key: std::env::var(S3_ACCESS_KEY).ok(),
+            secret_key: std::env::var(S3_SECRET_KEY).ok(),
         })
     }
-    pub fn to_conform_path() -> String {
-        match PersistenceMode::detect_config_mode() {
+    pub fn name(&self) -> &'static str {
+        match self {
             #[cfg(feature = "db")]
-            PersistenceMode::DB(d) => match d.url {
-                Some(s) => s.to_string(),
-                None => panic!("No DB"),
-            },
+            PersistenceMode::DB(d) => d.database.name(),
             #[cfg(feature = "local")]
-            PersistenceMode::File(d) => d.dir,
+            PersistenceMode::File(_) => "local",
             #[cfg(feature = "remote")]
-            PersistenceMode::Remote(d) => {
-                debug!("Remote URL: {:?}", d.url);
-                match d.url {
-                    Some(s) => s.to_string(),
-                    None => panic!("No remote"),
-                }
-            }
+            PersistenceMode::Remote(_) => "remote",
+            #[cfg(feature = "s3")]
+            PersistenceMode::S3(_) => "s3",
+            PersistenceMode::Memory(_) => "memory",
         }
     }
-}
-impl From<&str> for PersistenceMode {
-    fn from(mode: &str) -> Self {
-        match mode {
+    /// The database of the mode, `None` if it isn't one.
+    #[cfg(feature = "db")]
+    fn database(&self) -> Option<Database> {
+        match self {
+            PersistenceMode::DB(d) => Some(d.database),
+            _ => None,
+        }
+    }
+    /// The environment variable the mode can't work without, if it isn't set.
+    fn missing_config(&self) -> Option<&'static str> {
+        match self {
             #[cfg(feature = "db")]
-            "1" | "db" => PersistenceMode::DB(DatabaseConfig {
-                url: match std::env::var(SQLITE_CONNECTION) {
-                    Ok(connection) => Some(connection),
-                    Err(_) => panic!("No sqlite connnection backup defined"),
-                },
-            }),
+            PersistenceMode::DB(d) => d.url.is_none().then_some(d.database.variable()),
             #[cfg(feature = "local")]
-            "2" | "local" => PersistenceMode::File(FileConfig {
-                dir: match std::env::var(FILE_DIR) {
-                    Ok(file_dir) => file_dir,
-                    Err(_) => String::from("/tmp/"),
-                },
-            }),
+            PersistenceMode::File(_) => None,
             #[cfg(feature = "remote")]
-            "3" | "remote" => PersistenceMode::Remote(RemoteConfig {
-                url: match std::env::var(REMOTE_STORAGE) {
-                    Ok(connection) => Some(connection),
-                    Err(_) => panic!("No remote storage defined"),
-                },
-            }),
-            _ => PersistenceMode::default_mode(),
-        }
-    }
-}
-
-#[rocket::async_trait]
-#[cfg(all(feature = "local", not(feature = "remote")))]
-pub trait Path: FileDir + IsUniqueFile {
-    fn get_dir() -> &'static str {
-        match PersistenceMode::detect_config_mode() {
-            PersistenceMode::File(_d) => <Self as FileDir>::get_dir(),
-            _ => panic!("n/a"),
+            PersistenceMode::Remote(d) => d.url.is_none().then_some(REMOTE_STORAGE),
+            #[cfg(feature = "s3")]
+            PersistenceMode::S3(d) => [
+                (d.endpoint.is_none(), S3_ENDPOINT),
+                (d.bucket.is_none(), S3_BUCKET),
+                (d.access_key.is_none(), S3_ACCESS_KEY),
+                (d.secret_key.is_none(), S3_SECRET_KEY),
+            ]
+            .into_iter()
+            .find_map(|(missing, name)| missing.then_some(name)),
+            PersistenceMode::Memory(_) => None,
         }
     }
-    fn get_full_path(&self, possible_remote: bool) -> String {
-        let root_path = match possible_remote {
-            true => PersistenceMode::to_conform_path(),
-            false => match std::env::var(FILE_DIR) {
-                Ok(file_dir) => file_dir,
-                Err(_) => String::from("/tmp/"),
+    fn backend(self) -> Result<Storage, VoteErrorKind<'static>> {
+        match self {
+            #[cfg(feature = "db")]
+            PersistenceMode::DB(d) => Ok(Arc::new(DatabaseStorage::new(d.database))),
+            #[cfg(feature = "local")]
+            PersistenceMode::File(d) => Ok(Arc::new(d.storage())),
+            #[cfg(feature = "remote")]
+            PersistenceMode::Remote(d) => match d.url {
+                Some(url) => Ok(Arc::new(RemoteStorage::new(&url)?)),
+                None => Err(VoteErrorKind::Internal(String::from(
+                    "No remote storage defined.",
+                ))),
+            },
+            #[cfg(feature = "s3")]
+            PersistenceMode::S3(d) => match (d.endpoint, d.bucket, d.access_key, d.secret_key) {
+                (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) => {
+                    let credentials = Credentials {
+                        access_key,
+                        secret_key,
+                        region: d.region,
+                    };
+                    Ok(Arc::new(S3Storage::new(
+                        &endpoint,
+                        &bucket,
+                        &d.prefix,
+                        credentials,
+                    )?))
+                }
+                _ => Err(VoteErrorKind::Internal(String::from(
+                    "No S3 storage defined.",
+                ))),
+            },
+            PersistenceMode::Memory(d) => match d.dir {
+                Some(dir) => {
+                    let assets = d.assets.unwrap_or_else(|| dir.clone());
+                    Ok(Arc::new(MemoryStorage::seeded(&dir, &assets)))
+                }
+                None => Ok(Arc::new(MemoryStorage::new())),
             },
-        };
-        root_path + &<Self as FileDir>::get_dir()
-    }
-
-    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind> {
-        match PersistenceMode::detect_config_mode() {
-            PersistenceMode::File(_) => <Self as IsUniqueFile>::is_unique(self, id).await,
         }
     }
-}
-
-#[cfg(feature = "file")]
-#[rocket::async_trait]
-pub trait Path: FileDir + IsUniqueFile {
-    fn get_dir() -> &'static str {
-        match PersistenceMode::detect_config_mode() {
-            #[cfg(feature = "local")]
-            PersistenceMode::File(_d) => <Self as FileDir>::get_dir(),
-            _ => panic!("n/a"),
-        }
+    /// The url of `database`, even if another mode is chosen.
+    #[cfg(feature = "db")]
+    pub fn database_url(database: Database) -> Result<String, VoteErrorKind<'static>> {
+        std::env::var(database.variable()).map_err(|_| {
+            VoteErrorKind::Internal(format!(
+                "The {} database needs {} to be set.",
+                database.name(),
+                database.variable()
+            ))
+        })
     }
-    fn get_full_path(&self, possible_remote: bool) -> String {
-        let root_path = match possible_remote {
-            true => PersistenceMode::to_conform_path(),
-            false => match std::env::var(FILE_DIR) {
-                Ok(file_dir) => file_dir,
-                Err(_) => String::from("/tmp/"),
+    pub fn to_conform_path() -> Result<String, VoteErrorKind<'static>> {
+        match PersistenceMode::detect_config_mode()? {
+            #[cfg(feature = "db")]
+            PersistenceMode::DB(d) => match d.url {
+                Some(s) => Ok(s.to_string()),
+                None => Err(VoteErrorKind::Internal(String::from("No DB"))),
             },
-        };
-        root_path + &<Self as FileDir>::get_dir()
-    }
-
-    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind> {
-        match PersistenceMode::detect_config_mode() {
             #[cfg(feature = "local")]
-            PersistenceMode::File(_) => <Self as IsUniqueFile>::is_unique(self, id).await,
-            PersistenceMode::Remote(_) => Ok(String::from("name")),
+            PersistenceMode::File(d) => Ok(d.dir),
+            #[cfg(feature = "remote")]
+            PersistenceMode::Remote(d) => {
+                debug!("Remote URL: {:?}", d.url);
+                match d.url {
+                    Some(s) => Ok(s.to_string()),
+                    None => Err(VoteErrorKind::Internal(String::from("No remote"))),
+                }
+            }
+            #[cfg(feature = "s3")]
+            PersistenceMode::S3(d) => match (d.endpoint, d.bucket) {
+                (Some(endpoint), Some(bucket)) => {
+                    Ok(format!("{}/{}/{}", endpoint, bucket, d.prefix))
+                }
+                _ => Err(VoteErrorKind::Internal(String::from("No S3 storage"))),
+            },
+            PersistenceMode::Memory(d) => Ok(d.dir.unwrap_or_default()),
         }
     }
 }
-
-#[cfg(feature = "db")]
-#[rocket::async_trait]
-pub trait Path: Query + Selfaware {
-    async fn get_dir(&self) -> String {
-        self.get_type().to_string()
-    }
-    fn get_full_path(&self, _possible_remote: bool) -> String {
-        <Self as Query>::get_dir()
-    }
-    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind> {
-        debug!("is_unique");
-        match id.split_once("_") {
-            Some((a, _b)) => <Self as Query>::is_unique(self, a).await,
-            None => <Self as Query>::is_unique(self, id).await,
+impl PersistenceMode {
+    /// The mode named `mode`, if it is built in.
+    fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            #[cfg(feature = "sqlx_sqlite")]
+            "1" | "db" => Some(PersistenceMode::of_database(Database::SqlxSqlite)),
+            #[cfg(all(feature = "diesel_sqlite", not(feature = "sqlx_sqlite")))]
+            "1" | "db" => Some(PersistenceMode::of_database(Database::DieselSqlite)),
+            #[cfg(feature = "local")]
+            "2" | "local" => Some(PersistenceMode::file()),
+            #[cfg(feature = "remote")]
+            "3" | "remote" => Some(PersistenceMode::remote()),
+            #[cfg(feature = "postgres")]
+            "4" | "postgres" => Some(PersistenceMode::of_database(Database::Postgres)),
+            "5" | "memory" => Some(PersistenceMode::memory()),
+            #[cfg(feature = "s3")]
+            "6" | "s3" => Some(PersistenceMode::s3()),
+            #[cfg(feature = "diesel_sqlite")]
+            "7" | "diesel" => Some(PersistenceMode::of_database(Database::DieselSqlite)),
+            _ => None,
         }
     }
 }
-
-#[cfg(all(not(feature = "file"), feature = "local"))]
-#[rocket::async_trait]
-pub trait ToPersistence: DirectoryIndex + Path + ToFile {
-    async fn index(&self) -> Result<Vec<String>, String> {
-        let uri = Path::get_full_path(self, true) + "/index.json";
-        self.read_index(&uri).await
-    }
-
-    async fn list(&self) -> Result<Vec<String>, String> {
-        self.list_dir().await
+impl PersistenceMode {
+    /// The mode named `mode`, an error if it isn't built in.
+    fn named(mode: &str) -> Result<Self, VoteErrorKind<'static>> {
+        PersistenceMode::parse(mode).ok_or_else(|| {
+            VoteErrorKind::Internal(format!("Storage mode {} isn't built in.", mode))
+        })
     }
 }
 
-#[cfg(all(not(feature = "file"), feature = "remove"))]
-#[rocket::async_trait]
-pub trait ToPersistence: RemoteIndex + Path + ToFile {
-    async fn index(&self) -> Result<Vec<String>, String> {
-        let uri = Path::get_full_path(self, true) + "/index.json";
-        self.prepare(&uri).await
+/// The backend of `mode` with the config of the environment, apart from the
+/// one the server uses. E.g. the admin binary copies from one to another.
+pub async fn backend_of(mode: &str) -> Result<Storage, VoteErrorKind<'static>> {
+    let mode = PersistenceMode::named(mode)?;
+    if let Some(variable) = mode.missing_config() {
+        return Err(VoteErrorKind::Internal(format!(
+            "The {} storage needs {} to be set.",
+            mode.name(),
+            variable
+        )));
     }
+    let backend = mode.backend()?;
+    backend.verify().await?;
+    Ok(backend)
 }
 
-#[cfg(feature = "file")]
+/////////////////////////////////////////////
+//                                         //
+//          TYPED ACCESS                   //
+//                                         //
+/////////////////////////////////////////////
+
+/// Where the entries of a type are kept in the storage.
 #[rocket::async_trait]
-pub trait ToPersistence: DirectoryIndex + RemoteIndex + Path + ToFile {
-    async fn index(&self) -> Result<Vec<String>, String> {
-        let uri = FileDir::get_full_path(self, true) + "/index.json";
-        match PersistenceMode::detect_config_mode() {
-            PersistenceMode::File(_d) => self.read_index(&uri).await,
-            PersistenceMode::Remote(_d) => self.prepare(&uri).await,
-        }
-    }
+pub trait Path: Sync {
+    const KIND: Kind;
 
-    async fn list(&self) -> Result<Vec<String>, String> {
-        self.list_dir().await
+    /// Same as `StorageBackend::find`, returns the full id of `id` without
+    /// its prefix.
+    async fn is_unique(&self, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        storage()?.find(Self::KIND, id).await
     }
 }
 
-#[cfg(feature = "db")]
 #[rocket::async_trait]
-pub trait ToPersistence: Query + Selfaware + IdGenerator {
+pub trait ToPersistence: Path {
     async fn index(&self) -> Result<Vec<String>, String> {
-        <Self as Query>::index(self).await
+        let storage = storage().map_err(|e| e.to_string())?;
+        storage.index(Self::KIND).await.map_err(|e| e.to_string())
     }
 
     async fn list(&self) -> Result<Vec<String>, String> {
-        <Self as Query>::list_rows(self, true).await
+        let storage = storage().map_err(|e| e.to_string())?;
+        storage.list(Self::KIND).await.map_err(|e| e.to_string())
     }
 }
-#[cfg(feature = "file")]
-#[rocket::async_trait]
-pub trait FromPersistence: Path + for<'de> Deserialize<'de> {
-    async fn from_persistence(&self, path: &str, internal: bool) -> String {
-        match PersistenceMode::detect_config_mode() {
-            #[cfg(feature = "local")]
-            PersistenceMode::File(_d) => self.from_file(path).await,
-            #[cfg(feature = "remote")]
-            PersistenceMode::Remote(_d) => {
-                if internal {
-                    self.from_file(&path).await
-                } else {
-                    match reqwest::get(path).await {
-                        Ok(r) => match r.text().await {
-                            Ok(as_text) => as_text,
-                            Err(e) => {
-                                error!("{:?}", e);
 
-                                String::from("{}")
-                            }
-                        },
-                        Err(e) => {
-                            error!("{:?}", e);
-                            String::from("{}")
-                        }
-                    }
-                }
-            }
-        }
-    }
-}
+#[cfg(test)]
+mod test {
+    use super::*;
 
-#[cfg(feature = "db")]
-#[rocket::async_trait]
-pub trait FromPersistence: Path + for<'de> Deserialize<'de> {
-    async fn from_persistence(&self, _path: &str, _internal: bool) -> String {
-        match PersistenceMode::detect_config_mode() {
-            PersistenceMode::DB(d) => d.url.unwrap().to_string(),
-        }
+    #[test]
+    fn refuses_modes_not_built_in() {
+        assert!(PersistenceMode::named("memory").is_ok());
+        assert!(matches!(
+            PersistenceMode::named("unknown"),
+            Err(VoteErrorKind::Internal(_))
+        ));
     }
 }
@@ -1,10 +1,13 @@
-use crate::config::{DATE_DEPLOY, ENVIRONMENT};
+use crate::{
+    config::{CORS_HEADERS, CORS_METHODS, CORS_ORIGINS, CORS_ROUTES, DATE_DEPLOY, ENVIRONMENT},
+    routes::API_BALLOTS,
+};
 use rocket::{
     catch,
-    fairing::{Fairing, Info, Kind},
+    fairing::{self, Fairing, Info, Kind},
     get,
-    http::{Cookie, CookieJar, Header, Method},
-    Request, Response,
+    http::{Cookie, CookieJar, Header, Method, Status},
+    info, options, Build, Request, Response, Rocket,
 };
 
 /////////////////////////////////////////////
@@ -13,35 +16,129 @@ use rocket::{
 //                                         //
 /////////////////////////////////////////////
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsPolicy {
+    /// Exact origins like `https://example.org`. A single `*` allows every
+    /// origin, but then credentials are never allowed.
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    /// Paths, which are exposed to other origins at all, with every path
+    /// below them.
+    pub routes: Vec<String>,
+}
+impl CorsPolicy {
+    pub fn from_env() -> Self {
+        fn read(var_key: &str, default: &str) -> Vec<String> {
+            std::env::var(var_key)
+                .unwrap_or_else(|_| default.to_string())
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        }
+        Self {
+            origins: read(CORS_ORIGINS, ""),
+            methods: read(CORS_METHODS, "GET, POST, PUT, OPTIONS"),
+            headers: read(
+                CORS_HEADERS,
+                "Content-Type, X-Concafe-Invite-Code, X-Concafe-User",
+            ),
+            routes: read(CORS_ROUTES, &(API_BALLOTS.to_owned() + ", /info")),
+        }
+    }
+    pub fn allows_any_origin(&self) -> bool {
+        self.origins.iter().any(|o| o == "*")
+    }
+    /// Compares whole segments, `/info` exposes `/info/x` but not `/infox`.
+    fn exposes(&self, path: &str) -> bool {
+        self.routes.iter().any(|route| {
+            let route = route.trim_end_matches('/');
+            match path.strip_prefix(route) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            }
+        })
+    }
+    /// Returns the value for `Access-Control-Allow-Origin`, if the origin may
+    /// read the response of `path`.
+    pub fn allowed_origin(&self, origin: &str, path: &str) -> Option<String> {
+        if !self.exposes(path) {
+            return None;
+        }
+        if self.allows_any_origin() {
+            return Some(String::from("*"));
+        }
+        self.origins
+            .iter()
+            .find(|o| o.eq_ignore_ascii_case(origin))
+            .cloned()
+    }
+}
+
 pub struct CORS;
 #[rocket::async_trait]
 impl Fairing for CORS {
     fn info(&self) -> Info {
         Info {
             name: "Add CORS headers to responses",
-            kind: Kind::Response,
+            kind: Kind::Ignite | Kind::Response,
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let policy = CorsPolicy::from_env();
+        info!("CORS policy: {:?}", policy);
+        Ok(rocket.manage(policy))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
         let env = match std::env::var(ENVIRONMENT) {
             Ok(e) => e,
             Err(_) => String::from("TEST"),
         };
-        response.set_header(Header::new(
+        // Adjoined, the cookies of the handler are already set.
+        response.adjoin_header(Header::new(
             "set-cookie",
             Cookie::new("cv-".to_owned() + &env, env).to_string(),
         ));
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
-        response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "POST, GET, OPTIONS",
-        ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        let policy = match request.rocket().state::<CorsPolicy>() {
+            Some(p) => p,
+            None => return,
+        };
+        let origin = match request.headers().get_one("Origin") {
+            Some(o) => o,
+            None => return,
+        };
+        response.set_header(Header::new("Vary", "Origin"));
+        let allowed_origin = match policy.allowed_origin(origin, request.uri().path().as_str()) {
+            Some(o) => o,
+            None => return,
+        };
+        if allowed_origin != "*" {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+        response.set_header(Header::new("Access-Control-Allow-Origin", allowed_origin));
+        if request.method() == Method::Options {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                policy.methods.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                policy.headers.join(", "),
+            ));
+            response.set_header(Header::new("Access-Control-Max-Age", "600"));
+        }
     }
 }
 
+/// Answers every preflight request, the `CORS` fairing decides on the headers.
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
+
 /////////////////////////////////////////////
 //                                         //
 //        VERSION HANDLER                  //
@@ -78,6 +175,31 @@ pub fn unauthorized(req: &Request<'_>) -> String {
     "UNAUTHORIZED. You are not allowed to vote. Wrong invite_code or token?".to_string()
 }
 
+#[catch(401)]
+pub fn unauthenticated(req: &Request<'_>) -> String {
+    if req.method() == Method::Options {
+        return "".to_string();
+    }
+    "UNAUTHORIZED. Log in at /admin/login or send your credentials in the Authorization header."
+        .to_string()
+}
+
+#[catch(403)]
+pub fn forbidden(req: &Request<'_>) -> String {
+    if req.method() == Method::Options {
+        return "".to_string();
+    }
+    "FORBIDDEN. Your operator is missing the permission or doesn't own this voting.".to_string()
+}
+
+#[catch(429)]
+pub fn too_many_requests(req: &Request<'_>) -> String {
+    if req.method() == Method::Options {
+        return "".to_string();
+    }
+    "TOO MANY REQUESTS. Too many wrong invite codes or tokens, try again later.".to_string()
+}
+
 #[catch(422)]
 pub fn unprocessable_content(req: &Request<'_>) -> String {
     if req.method() == Method::Options {
@@ -96,3 +218,78 @@ pub fn not_found(req: &Request<'_>) -> String {
         req.uri()
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy(origins: &[&str]) -> CorsPolicy {
+        CorsPolicy {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            methods: vec![String::from("GET")],
+            headers: vec![String::from("Content-Type")],
+            routes: vec![String::from("/api/v1/ballots")],
+        }
+    }
+
+    #[test]
+    fn allows_only_configured_origins() {
+        let policy = policy(&["https://example.org"]);
+        assert_eq!(
+            policy.allowed_origin("https://example.org", "/api/v1/ballots/voting"),
+            Some(String::from("https://example.org"))
+        );
+        assert_eq!(
+            policy.allowed_origin("https://evil.org", "/api/v1/ballots/voting"),
+            None
+        );
+    }
+
+    #[test]
+    fn hides_routes_not_exposed() {
+        let policy = policy(&["*"]);
+        assert_eq!(
+            policy.allowed_origin("https://example.org", "/api/v1/ballots/voting"),
+            Some(String::from("*"))
+        );
+        assert_eq!(
+            policy.allowed_origin("https://example.org", "/api/v1/operators"),
+            None
+        );
+        assert_eq!(
+            policy.allowed_origin("https://example.org", "/api/v1/ballots"),
+            Some(String::from("*"))
+        );
+        assert_eq!(
+            policy.allowed_origin("https://example.org", "/api/v1/ballotsx"),
+            None
+        );
+    }
+
+    #[test]
+    fn nothing_is_exposed_by_default() {
+        assert_eq!(
+            policy(&[]).allowed_origin("https://example.org", "/api/v1/ballots/voting"),
+            None
+        );
+    }
+
+    #[get("/cookie")]
+    fn set_cookie(jar: &CookieJar<'_>) -> &'static str {
+        jar.add(Cookie::new("handler", "set"));
+        "set"
+    }
+
+    #[test]
+    fn keeps_the_cookies_of_handlers() {
+        let rocket = rocket::build()
+            .attach(CORS)
+            .mount("/", rocket::routes![set_cookie]);
+        let client = rocket::local::blocking::Client::untracked(rocket).unwrap();
+        let response = client.get("/cookie").dispatch();
+        let cookies: Vec<&str> = response.headers().get("set-cookie").collect();
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies.iter().any(|c| c.starts_with("handler=set")));
+        assert!(cookies.iter().any(|c| c.starts_with("cv-")));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::config::{
+    RATE_LIMIT_ATTEMPTS, RATE_LIMIT_BACKOFF, RATE_LIMIT_MAX_BACKOFF, RATE_LIMIT_MAX_TRACKED,
+};
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{Header, Status},
+    info, warn, Build, Request, Response, Rocket,
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/////////////////////////////////////////////
+//                                         //
+//              CONFIG                     //
+//                                         //
+/////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// Failed checks, which are allowed before the back-off starts.
+    pub max_attempts: u32,
+    /// First back-off, it doubles with every further failure.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Clients and scopes, which are tracked at once. The ones, which failed
+    /// longest ago, are dropped first.
+    pub max_tracked: usize,
+}
+impl RateLimitConfig {
+    pub fn from_env() -> Self {
+        fn read(var_key: &str, default: u64) -> u64 {
+            match std::env::var(var_key) {
+                Ok(v) => v.parse::<u64>().unwrap_or_else(|_| {
+                    warn!("{} is not a number, using {}", var_key, default);
+                    default
+                }),
+                Err(_) => default,
+            }
+        }
+        Self {
+            max_attempts: read(RATE_LIMIT_ATTEMPTS, 5) as u32,
+            backoff: Duration::from_secs(read(RATE_LIMIT_BACKOFF, 2)),
+            max_backoff: Duration::from_secs(read(RATE_LIMIT_MAX_BACKOFF, 900)),
+            max_tracked: read(RATE_LIMIT_MAX_TRACKED, 10_000) as usize,
+        }
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//              LIMITER                    //
+//                                         //
+/////////////////////////////////////////////
+
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Option<Instant>,
+}
+
+type AttemptKey = (Option<IpAddr>, String);
+
+/// How often attempts, which are forgotten, are removed.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Tracked {
+    attempts: HashMap<AttemptKey, Attempts>,
+    pruned_at: Instant,
+}
+impl Tracked {
+    /// Removes the attempts, which failed more than `forget_after` ago.
+    fn prune(&mut self, now: Instant, forget_after: Duration) {
+        self.attempts
+            .retain(|_, a| now.duration_since(a.last_failure) < forget_after);
+        self.pruned_at = now;
+    }
+    /// Removes the older half of the attempts, so a full map is pruned once
+    /// per `max_tracked / 2` new clients, not on every one.
+    fn evict_oldest(&mut self) {
+        let mut failures: Vec<Instant> = self.attempts.values().map(|a| a.last_failure).collect();
+        if failures.is_empty() {
+            return;
+        }
+        let middle = (failures.len() / 2).saturating_sub(1);
+        let (_, newest_dropped, _) = failures.select_nth_unstable(middle);
+        let newest_dropped = *newest_dropped;
+        self.attempts.retain(|_, a| a.last_failure > newest_dropped);
+    }
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    tracked: Mutex<Tracked>,
+}
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            tracked: Mutex::new(Tracked {
+                attempts: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        }
+    }
+    pub fn retry_after(&self, ip: Option<IpAddr>, scope: &str) -> Option<Duration> {
+        self.retry_after_at(ip, scope, Instant::now())
+    }
+    pub fn record_failure(&self, ip: Option<IpAddr>, scope: &str) {
+        self.record_failure_at(ip, scope, Instant::now())
+    }
+    pub fn reset(&self, ip: Option<IpAddr>, scope: &str) {
+        self.tracked
+            .lock()
+            .unwrap()
+            .attempts
+            .remove(&(ip, scope.to_lowercase()));
+    }
+    fn retry_after_at(&self, ip: Option<IpAddr>, scope: &str, now: Instant) -> Option<Duration> {
+        let tracked = self.tracked.lock().unwrap();
+        match tracked.attempts.get(&(ip, scope.to_lowercase())) {
+            Some(Attempts {
+                blocked_until: Some(until),
+                ..
+            }) if *until > now => Some(*until - now),
+            _ => None,
+        }
+    }
+    fn record_failure_at(&self, ip: Option<IpAddr>, scope: &str, now: Instant) {
+        let mut tracked = self.tracked.lock().unwrap();
+        if now.duration_since(tracked.pruned_at) >= PRUNE_INTERVAL {
+            tracked.prune(now, self.config.max_backoff);
+        }
+        let key = (ip, scope.to_lowercase());
+        if tracked.attempts.len() >= self.config.max_tracked && !tracked.attempts.contains_key(&key)
+        {
+            tracked.evict_oldest();
+        }
+        let entry = tracked.attempts.entry(key).or_insert(Attempts {
+            failures: 0,
+            last_failure: now,
+            blocked_until: None,
+        });
+        entry.failures += 1;
+        entry.last_failure = now;
+        if entry.failures >= self.config.max_attempts {
+            let exponent = (entry.failures - self.config.max_attempts).min(31);
+            let backoff = self
+                .config
+                .backoff
+                .saturating_mul(2u32.saturating_pow(exponent))
+                .min(self.config.max_backoff);
+            info!(
+                "Throttling {:?} on {} for {:?} after {} failures.",
+                ip, scope, backoff, entry.failures
+            );
+            entry.blocked_until = Some(now + backoff);
+        }
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//          GUARD HELPERS                  //
+//                                         //
+/////////////////////////////////////////////
+
+/// What failed checks are counted for. Votings and operator logins are kept
+/// apart, a voting named `admin` doesn't throttle logins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope<'a> {
+    Admin,
+    Voting(&'a str),
+}
+impl Scope<'_> {
+    fn key(&self) -> String {
+        match self {
+            Scope::Admin => String::from("admin:"),
+            Scope::Voting(voting) => format!("voting:{}", voting),
+        }
+    }
+}
+
+/// Cached on the request, so the fairing can add the `Retry-After` header.
+struct RetryAfter(Option<u64>);
+
+/// Checked by the guards before the invite code or token is compared.
+pub fn is_throttled(req: &Request<'_>, scope: Scope<'_>) -> bool {
+    let retry_after = match req.rocket().state::<RateLimiter>() {
+        Some(limiter) => limiter.retry_after(req.client_ip(), &scope.key()),
+        None => None,
+    };
+    match retry_after {
+        Some(d) => {
+            let secs = d.as_secs() + u64::from(d.subsec_nanos() > 0);
+            req.local_cache(|| RetryAfter(Some(secs)));
+            true
+        }
+        None => false,
+    }
+}
+pub fn record_failure(req: &Request<'_>, scope: Scope<'_>) {
+    if let Some(limiter) = req.rocket().state::<RateLimiter>() {
+        limiter.record_failure(req.client_ip(), &scope.key());
+    }
+}
+pub fn record_success(req: &Request<'_>, scope: Scope<'_>) {
+    if let Some(limiter) = req.rocket().state::<RateLimiter>() {
+        limiter.reset(req.client_ip(), &scope.key());
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//               FAIRING                   //
+//                                         //
+/////////////////////////////////////////////
+
+pub struct RateLimit;
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Throttle failed invite code and token checks",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = RateLimitConfig::from_env();
+        info!("Rate limit: {:?}", config);
+        Ok(rocket.manage(RateLimiter::new(config)))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.status() == Status::TooManyRequests {
+            if let RetryAfter(Some(secs)) = request.local_cache(|| RetryAfter(None)) {
+                response.set_header(Header::new("Retry-After", secs.to_string()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            max_attempts: 3,
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(10),
+            max_tracked: 4,
+        })
+    }
+
+    #[test]
+    fn allows_attempts_below_limit() {
+        let limiter = limiter();
+        let now = Instant::now();
+        limiter.record_failure_at(None, "voting", now);
+        limiter.record_failure_at(None, "voting", now);
+        assert_eq!(limiter.retry_after_at(None, "voting", now), None);
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.record_failure_at(None, "voting", now);
+        }
+        assert_eq!(
+            limiter.retry_after_at(None, "voting", now),
+            Some(Duration::from_secs(2))
+        );
+        limiter.record_failure_at(None, "voting", now);
+        assert_eq!(
+            limiter.retry_after_at(None, "voting", now),
+            Some(Duration::from_secs(4))
+        );
+        limiter.record_failure_at(None, "voting", now);
+        limiter.record_failure_at(None, "voting", now);
+        assert_eq!(
+            limiter.retry_after_at(None, "voting", now),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn scopes_are_separated_and_reset() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.record_failure_at(None, "Voting", now);
+        }
+        assert!(limiter.retry_after_at(None, "voting", now).is_some());
+        assert_eq!(limiter.retry_after_at(None, "other", now), None);
+        limiter.reset(None, "voting");
+        assert_eq!(limiter.retry_after_at(None, "voting", now), None);
+    }
+
+    #[test]
+    fn forgets_the_oldest_clients_beyond_the_cap() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for i in 0..4u64 {
+            let at = now + Duration::from_secs(i);
+            for _ in 0..3 {
+                limiter.record_failure_at(None, &format!("voting-{}", i), at);
+            }
+        }
+        let later = now + Duration::from_secs(4);
+        limiter.record_failure_at(None, "voting-4", later);
+        assert_eq!(limiter.tracked.lock().unwrap().attempts.len(), 3);
+        assert_eq!(limiter.retry_after_at(None, "voting-0", later), None);
+        assert!(limiter.retry_after_at(None, "voting-3", later).is_some());
+    }
+
+    #[test]
+    fn prunes_forgotten_attempts_on_a_timer() {
+        let limiter = limiter();
+        let now = Instant::now();
+        limiter.record_failure_at(None, "voting", now);
+        limiter.record_failure_at(None, "other", now + PRUNE_INTERVAL - Duration::from_secs(5));
+        assert_eq!(limiter.tracked.lock().unwrap().attempts.len(), 2);
+        limiter.record_failure_at(None, "other", now + PRUNE_INTERVAL);
+        assert_eq!(limiter.tracked.lock().unwrap().attempts.len(), 1);
+    }
+
+    #[test]
+    fn votings_dont_throttle_logins() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..3 {
+            limiter.record_failure_at(None, &Scope::Voting("admin").key(), now);
+        }
+        assert!(limiter
+            .retry_after_at(None, &Scope::Voting("admin").key(), now)
+            .is_some());
+        assert_eq!(limiter.retry_after_at(None, &Scope::Admin.key(), now), None);
+    }
+}
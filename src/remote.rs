@@ -1,124 +1,719 @@
-use crate::common::{
-    Candidate, CastBallots, Criteria, Criterion, EmojiCategories, Voting, Votings,
+use crate::{
+    client::ClientConfig,
+    config::{REMOTE_AUTH, REMOTE_CREDENTIALS},
+    error::VoteErrorKind,
+    persistence::{Kind, PutMode, StorageBackend},
 };
-use rocket::{
-    debug, error,
-    tokio::{fs::File, io::AsyncReadExt},
-};
-#[rocket::async_trait]
-pub trait RemoteIndex {
-    async fn prepare(&self, uri: &str) -> Result<Vec<String>, String> {
-        debug!("Remote URL: {:?}", uri);
-        match std::env::var("VOTERS_VERDICT_SELF_CERT") {
-            Ok(cert) => {
-                let mut buf = Vec::new();
-                match File::open(cert).await {
-                    Ok(mut f) => {
-                        let _ = match f.read_to_end(&mut buf).await {
-                            Ok(_) => {}
-                            Err(e) => error!("{:?}", e),
-                        };
-
-                        match reqwest::Certificate::from_der(&buf) {
-                            Ok(c) => {
-                                let client_builder =
-                                    reqwest::Client::builder().add_root_certificate(c);
-                                self.build_client(&uri, Some(client_builder)).await
-                            }
-                            Err(_e) => {
-                                error!("Self signed cert couldn't be loaded.");
-                                self.build_client(&uri, None).await
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        error!("Self signed cert couldn't be loaded.");
-                        self.build_client(&uri, None).await
-                    }
+use reqwest::{header, Method, RequestBuilder, StatusCode};
+use rocket::{debug, error, tokio::sync::Mutex};
+use std::collections::HashMap;
+
+/////////////////////////////////////////////
+//                                         //
+//              CONFIG                     //
+//                                         //
+/////////////////////////////////////////////
+
+/// How requests to the remote storage are authenticated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteAuth {
+    None,
+    Bearer(String),
+    Basic(String, Option<String>),
+}
+impl RemoteAuth {
+    pub fn from_env() -> Result<Self, VoteErrorKind<'static>> {
+        RemoteAuth::from_values(
+            std::env::var(REMOTE_AUTH).ok().as_deref(),
+            std::env::var(REMOTE_CREDENTIALS).ok().as_deref(),
+        )
+    }
+    /// Reads the kind of auth, `bearer` or `basic`, and its credentials, a
+    /// token or `user:password`.
+    pub fn from_values(
+        auth: Option<&str>,
+        credentials: Option<&str>,
+    ) -> Result<Self, VoteErrorKind<'static>> {
+        match (auth, credentials) {
+            (None, _) => Ok(RemoteAuth::None),
+            (Some(_), None) => Err(VoteErrorKind::Internal(format!(
+                "{} is set, but {} is missing.",
+                REMOTE_AUTH, REMOTE_CREDENTIALS
+            ))),
+            (Some("bearer"), Some(token)) => Ok(RemoteAuth::Bearer(token.to_string())),
+            (Some("basic"), Some(credentials)) => Ok(match credentials.split_once(':') {
+                Some((user, password)) => {
+                    RemoteAuth::Basic(user.to_string(), Some(password.to_string()))
                 }
+                None => RemoteAuth::Basic(credentials.to_string(), None),
+            }),
+            (Some(auth), Some(_)) => Err(VoteErrorKind::Internal(format!(
+                "Remote auth {} isn't supported, use bearer or basic.",
+                auth
+            ))),
+        }
+    }
+}
+
+/////////////////////////////////////////////
+//                                         //
+//              STORAGE                    //
+//                                         //
+/////////////////////////////////////////////
+
+/// Keeps the layout of `FileStorage` on a web server below `url`. Entries are
+/// read with GET, written with PUT and removed with DELETE. `PUT` with
+/// `If-None-Match: *` only creates an entry and has to be answered with
+/// `412 Precondition Failed` if it exists. `GET` on a directory may list its
+/// ids, otherwise `index.json` is used. The index is kept up to date by the
+/// client like the one of `FileStorage`.
+pub struct RemoteStorage {
+    url: String,
+    config: ClientConfig,
+    auth: RemoteAuth,
+    client: reqwest::Client,
+    locks: HashMap<Kind, Mutex<()>>,
+}
+
+impl RemoteStorage {
+    pub fn new(url: &str) -> Result<Self, VoteErrorKind<'static>> {
+        RemoteStorage::with_config(url, ClientConfig::from_env()?, RemoteAuth::from_env()?)
+    }
+    pub fn with_config(
+        url: &str,
+        config: ClientConfig,
+        auth: RemoteAuth,
+    ) -> Result<Self, VoteErrorKind<'static>> {
+        let url = match url.ends_with('/') {
+            true => url.to_string(),
+            false => url.to_string() + "/",
+        };
+        debug!("Remote URL: {:?}", url);
+        if !config.pinned_certs.is_empty() && !url.starts_with("https://") {
+            return Err(VoteErrorKind::Internal(String::from(
+                "Pinned certificates need a https remote storage.",
+            )));
+        }
+        Ok(Self {
+            url,
+            client: config.build_client()?,
+            config,
+            auth,
+            locks: Kind::ALL.into_iter().map(|k| (k, Mutex::new(()))).collect(),
+        })
+    }
+    fn dir_url(&self, kind: Kind) -> String {
+        self.url.clone() + kind.dir()
+    }
+    fn entry_url(&self, kind: Kind, id: &str) -> String {
+        self.dir_url(kind) + "/" + id + ".json"
+    }
+    fn index_url(&self, kind: Kind) -> String {
+        self.dir_url(kind) + "/index.json"
+    }
+    fn request(&self, method: Method, uri: &str) -> RequestBuilder {
+        let request = self.client.request(method, uri).header(
+            header::ACCEPT,
+            header::HeaderValue::from_static("application/json"),
+        );
+        match &self.auth {
+            RemoteAuth::None => request,
+            RemoteAuth::Bearer(token) => request.bearer_auth(token),
+            RemoteAuth::Basic(user, password) => request.basic_auth(user, password.as_ref()),
+        }
+    }
+    /// Sends the request and maps the status of the answer.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<reqwest::Response, VoteErrorKind<'static>> {
+        let response = self.config.execute(request, "Remote").await?;
+        debug!("Raw request response: {:?}", response);
+        match response.status() {
+            s if s.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(VoteErrorKind::NotFound(String::from("Not found"))),
+            StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => Err(VoteErrorKind::Conflict(
+                String::from("Entry already exist."),
+            )),
+            s => {
+                error!("Remote storage answered {}", s);
+                Err(VoteErrorKind::Internal(format!(
+                    "Remote storage answered {}",
+                    s
+                )))
             }
-            Err(_) => self.build_client(&uri, None).await,
         }
     }
-    async fn build_client(
+    async fn get_text(&self, uri: &str) -> Result<String, VoteErrorKind<'static>> {
+        let response = self.send(self.request(Method::GET, uri)).await?;
+        response.text().await.map_err(|e| {
+            error!("{:?}", e);
+            VoteErrorKind::Internal(String::from("Remote storage answer broke off."))
+        })
+    }
+    async fn put_text(
         &self,
         uri: &str,
-        client_builder: Option<reqwest::ClientBuilder>,
-    ) -> Result<Vec<String>, String> {
-        let custom_client = match client_builder {
-            Some(builder) => builder,
-            None => reqwest::Client::builder(),
+        body: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let request = self
+            .request(Method::PUT, uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body);
+        let request = match mode {
+            PutMode::Create => request.header(header::IF_NONE_MATCH, "*"),
+            PutMode::Replace => request,
         };
-        let client = match custom_client.build() {
-            Ok(c) => c,
-            Err(_) => reqwest::Client::new(),
-        };
-        self.http_get(uri, client).await
-    }
-    async fn http_get(&self, uri: &str, client: reqwest::Client) -> Result<Vec<String>, String> {
-        let get_client = client.get(uri);
-        match std::env::var("VOTERS_VERDICT_REMOTE_CREDENTIALS") {
-            Ok(t) => match std::env::var("VOTERS_VERDICT_REMOTE_AUTH") {
-                Ok(auths) => {
-                    debug!("Detected remote auth: {}", auths);
-                    match auths.as_str() {
-                        "bearer" => {
-                            debug!("Detected bearer auth");
-                            self.get(get_client.bearer_auth("Bearer ".to_owned() + &t))
-                                .await
-                        }
-                        "basic" => {
-                            debug!("Detected basic auth");
-                            let credentails = auths.split_once(':');
-                            match credentails {
-                                Some((user, pw)) => {
-                                    self.get(get_client.basic_auth(user, Some(pw))).await
-                                }
-                                None => {
-                                    error!("Credentials for basic auth not provided");
-                                    self.get(get_client).await
-                                }
-                            }
-                        }
-                        _ => self.get(get_client).await,
+        self.send(request).await?;
+        Ok(String::from("Done"))
+    }
+    /// The index, empty if none was written yet.
+    async fn read_index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        match self.get_text(&self.index_url(kind)).await {
+            Ok(raw) => Ok(rocket::serde::json::from_str::<Vec<String>>(&raw)?),
+            Err(VoteErrorKind::NotFound(_)) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+    async fn update_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let stringified = rocket::serde::json::to_string(&ids)?;
+        self.put_text(&self.index_url(kind), stringified, PutMode::Replace)
+            .await
+    }
+    fn lock(&self, kind: Kind) -> &Mutex<()> {
+        &self.locks[&kind]
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for RemoteStorage {
+    fn name(&self) -> &'static str {
+        "remote"
+    }
+    async fn get(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        self.get_text(&self.entry_url(kind, id)).await
+    }
+    async fn put(
+        &self,
+        kind: Kind,
+        id: &str,
+        entry: String,
+        mode: PutMode,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let uri = self.entry_url(kind, id);
+        match mode {
+            PutMode::Replace => self.put_text(&uri, entry, mode).await,
+            PutMode::Create => {
+                let _guard = self.lock(kind).lock().await;
+                self.put_text(&uri, entry, mode).await?;
+                let indexed = match self.read_index(kind).await {
+                    Ok(mut ids) => {
+                        ids.push(id.to_string());
+                        self.update_index(kind, ids).await
                     }
+                    Err(e) => Err(e),
+                };
+                // An entry missing in the index is taken back.
+                if let Err(e) = indexed {
+                    if let Err(deleting) = self.send(self.request(Method::DELETE, &uri)).await {
+                        error!("{:?}", deleting);
+                    }
+                    return Err(e);
                 }
-                Err(_) => self.get(get_client).await,
+                Ok(String::from("Saved and index updated."))
+            }
+        }
+    }
+    /// The listing of the directory, the index if the server can't list it.
+    /// An unreachable server is an error either way.
+    async fn list(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        let response = self
+            .config
+            .execute(self.request(Method::GET, &self.dir_url(kind)), "Remote")
+            .await?;
+        let listed = match response.status().is_success() {
+            true => match response.text().await {
+                Ok(raw) => rocket::serde::json::from_str::<Vec<String>>(&raw).ok(),
+                Err(_) => None,
             },
-            Err(_) => self.get(get_client).await,
+            false => None,
+        };
+        match listed {
+            Some(ids) => Ok(ids),
+            None => self.index(kind).await,
         }
     }
-    async fn get(&self, request_builder: reqwest::RequestBuilder) -> Result<Vec<String>, String> {
-        match request_builder
-            .header(
-                reqwest::header::ACCEPT,
-                reqwest::header::HeaderValue::from_static("application/json"),
-            )
-            .send()
+    async fn delete(&self, kind: Kind, id: &str) -> Result<String, VoteErrorKind<'static>> {
+        let _guard = self.lock(kind).lock().await;
+        self.send(self.request(Method::DELETE, &self.entry_url(kind, id)))
+            .await?;
+        let ids = self
+            .read_index(kind)
+            .await?
+            .into_iter()
+            .filter(|i| i != id)
+            .collect();
+        self.update_index(kind, ids).await?;
+        Ok(String::from("Deleted."))
+    }
+    async fn index(&self, kind: Kind) -> Result<Vec<String>, VoteErrorKind<'static>> {
+        self.read_index(kind).await
+    }
+    fn keeps_index(&self) -> bool {
+        true
+    }
+    /// Connects once, so a certificate, which isn't pinned, stops the server
+    /// before anything is sent.
+    async fn verify(&self) -> Result<(), VoteErrorKind<'static>> {
+        if !self.config.pinned_certs.is_empty() {
+            self.config
+                .execute(self.request(Method::HEAD, &self.url), "Remote")
+                .await?;
+        }
+        Ok(())
+    }
+    async fn rebuild_index(
+        &self,
+        kind: Kind,
+        ids: Vec<String>,
+    ) -> Result<String, VoteErrorKind<'static>> {
+        let _guard = self.lock(kind).lock().await;
+        self.update_index(kind, ids).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::{
+        delete, get,
+        http::Status,
+        put,
+        request::{FromRequest, Outcome},
+        routes,
+        serde::json::Json,
+        Request, State,
+    };
+    use sha2::{Digest, Sha256};
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex as SyncMutex,
+        },
+        time::Duration,
+    };
+
+    /// The files of the stand-in server by their path.
+    type Files = SyncMutex<BTreeMap<String, String>>;
+    /// How many reads of a file are answered with `503` first.
+    struct Unavailable(SyncMutex<usize>);
+    /// Writes that answer 503 nonetheless, like a proxy timing out.
+    struct LostAnswers(SyncMutex<usize>);
+
+    struct CreateOnly(bool);
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for CreateOnly {
+        type Error = ();
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            Outcome::Success(CreateOnly(
+                req.headers().get_one("If-None-Match") == Some("*"),
+            ))
+        }
+    }
+
+    #[get("/<dir>")]
+    fn list_files(dir: &str, files: &State<Files>) -> Json<Vec<String>> {
+        let prefix = dir.to_string() + "/";
+        Json(
+            files
+                .lock()
+                .unwrap()
+                .keys()
+                .filter_map(|p| p.strip_prefix(&prefix)?.strip_suffix(".json"))
+                .filter(|id| *id != "index")
+                .map(String::from)
+                .collect(),
+        )
+    }
+    #[get("/<dir>/<file>")]
+    fn get_file(
+        dir: &str,
+        file: &str,
+        files: &State<Files>,
+        unavailable: &State<Unavailable>,
+    ) -> Result<Option<String>, Status> {
+        let mut unavailable = unavailable.0.lock().unwrap();
+        if *unavailable > 0 {
+            *unavailable -= 1;
+            return Err(Status::ServiceUnavailable);
+        }
+        Ok(files
+            .lock()
+            .unwrap()
+            .get(&(dir.to_string() + "/" + file))
+            .cloned())
+    }
+    #[put("/<dir>/<file>", data = "<body>")]
+    fn put_file(
+        dir: &str,
+        file: &str,
+        body: String,
+        create_only: CreateOnly,
+        files: &State<Files>,
+        lost_answers: &State<LostAnswers>,
+    ) -> Status {
+        let mut files = files.lock().unwrap();
+        let path = dir.to_string() + "/" + file;
+        if create_only.0 && files.contains_key(&path) {
+            return Status::PreconditionFailed;
+        }
+        files.insert(path, body);
+        let mut lost_answers = lost_answers.0.lock().unwrap();
+        if *lost_answers > 0 {
+            *lost_answers -= 1;
+            return Status::ServiceUnavailable;
+        }
+        Status::NoContent
+    }
+    #[delete("/<dir>/<file>")]
+    fn delete_file(dir: &str, file: &str, files: &State<Files>) -> Status {
+        match files
+            .lock()
+            .unwrap()
+            .remove(&(dir.to_string() + "/" + file))
+        {
+            Some(_) => Status::NoContent,
+            None => Status::NotFound,
+        }
+    }
+
+    /// Starts the stand-in server and returns its url. The first `unavailable`
+    /// reads of a file fail.
+    async fn stand_in(unavailable: usize) -> String {
+        stand_in_losing(unavailable, 0).await
+    }
+
+    /// Like `stand_in`, the answers of the first `lost_answers` writes fail
+    /// after the file was written.
+    async fn stand_in_losing(unavailable: usize, lost_answers: usize) -> String {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = rocket::Config {
+            port,
+            address: std::net::Ipv4Addr::LOCALHOST.into(),
+            log_level: rocket::config::LogLevel::Off,
+            ..rocket::Config::debug_default()
+        };
+        let server = rocket::custom(config)
+            .manage(Files::default())
+            .manage(Unavailable(SyncMutex::new(unavailable)))
+            .manage(LostAnswers(SyncMutex::new(lost_answers)))
+            .mount(
+                "/bucket",
+                routes![list_files, get_file, put_file, delete_file],
+            );
+        rocket::tokio::spawn(server.launch());
+        while rocket::tokio::net::TcpStream::connect(("127.0.0.1", port))
             .await
+            .is_err()
         {
-            Ok(r) => {
-                debug!("Raw request response: {:?}", r);
-                match r.json::<Vec<String>>().await {
-                    Ok(d) => Ok(d),
-                    Err(e) => {
-                        error!("{:?}", e);
-                        Ok(vec![])
+            rocket::tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        format!("http://127.0.0.1:{}/bucket", port)
+    }
+
+    #[rocket::async_test]
+    async fn round_trips_entries_over_http() {
+        let storage = RemoteStorage::new(&stand_in(0).await).unwrap();
+        assert!(matches!(
+            storage.get(Kind::Voting, "voting").await,
+            Err(VoteErrorKind::NotFound(_))
+        ));
+
+        let created = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert_eq!(created.unwrap(), "Saved and index updated.");
+        let conflict = storage
+            .put(Kind::Voting, "voting", String::from("{}"), PutMode::Create)
+            .await;
+        assert!(matches!(conflict, Err(VoteErrorKind::Conflict(_))));
+        storage
+            .put(Kind::Voting, "voting", String::from("[]"), PutMode::Replace)
+            .await
+            .unwrap();
+        assert_eq!(storage.get(Kind::Voting, "voting").await.unwrap(), "[]");
+        storage
+            .put(
+                Kind::Candidate,
+                "voter_obama",
+                String::from("{}"),
+                PutMode::Create,
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.index(Kind::Voting).await.unwrap(), vec!["voting"]);
+        assert_eq!(storage.list(Kind::Voting).await.unwrap(), vec!["voting"]);
+        assert_eq!(
+            storage.find(Kind::Candidate, "Obama").await.unwrap(),
+            "voter_obama"
+        );
+
+        assert_eq!(
+            storage.delete(Kind::Voting, "voting").await.unwrap(),
+            "Deleted."
+        );
+        assert!(storage.get(Kind::Voting, "voting").await.is_err());
+        assert!(storage.index(Kind::Voting).await.unwrap().is_empty());
+        assert!(matches!(
+            storage.delete(Kind::Voting, "voting").await,
+            Err(VoteErrorKind::NotFound(_))
+        ));
+    }
+
+    fn config(retries: u32) -> ClientConfig {
+        ClientConfig {
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(1),
+            retries,
+            backoff: Duration::from_millis(1),
+            self_cert: None,
+            identity: None,
+            pinned_certs: vec![],
+        }
+    }
+
+    #[rocket::async_test]
+    async fn retries_unavailable_storage() {
+        let url = stand_in(2).await;
+        let storage = RemoteStorage::with_config(&url, config(2), RemoteAuth::None).unwrap();
+        assert!(matches!(
+            storage.get(Kind::Voting, "voting").await,
+            Err(VoteErrorKind::NotFound(_))
+        ));
+
+        let url = stand_in(1).await;
+        let storage = RemoteStorage::with_config(&url, config(0), RemoteAuth::None).unwrap();
+        assert!(matches!(
+            storage.index(Kind::Voting).await,
+            Err(VoteErrorKind::Internal(_))
+        ));
+
+        let storage =
+            RemoteStorage::with_config("http://127.0.0.1:1/", config(1), RemoteAuth::None).unwrap();
+        assert!(matches!(
+            storage.list(Kind::Voting).await,
+            Err(VoteErrorKind::Internal(_))
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn retries_writes_only_if_idempotent() {
+        let url = stand_in_losing(0, 1).await;
+        let storage = RemoteStorage::with_config(&url, config(2), RemoteAuth::None).unwrap();
+        assert!(matches!(
+            storage
+                .put(Kind::Voting, "voting", String::from("[]"), PutMode::Create)
+                .await,
+            Err(VoteErrorKind::Internal(_))
+        ));
+        assert_eq!(storage.get(Kind::Voting, "voting").await.unwrap(), "[]");
+
+        let url = stand_in_losing(0, 1).await;
+        let storage = RemoteStorage::with_config(&url, config(2), RemoteAuth::None).unwrap();
+        storage
+            .put(Kind::Voting, "voting", String::from("[]"), PutMode::Replace)
+            .await
+            .unwrap();
+        assert_eq!(storage.get(Kind::Voting, "voting").await.unwrap(), "[]");
+    }
+
+    #[test]
+    fn sends_credentials() {
+        let authorization = |auth: RemoteAuth| {
+            let storage = RemoteStorage::with_config("http://127.0.0.1/", config(0), auth).unwrap();
+            let request = storage
+                .request(Method::GET, "http://127.0.0.1/votings")
+                .build()
+                .unwrap();
+            request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .map(|h| h.to_str().unwrap().to_string())
+        };
+        assert_eq!(authorization(RemoteAuth::None), None);
+        assert_eq!(
+            authorization(RemoteAuth::from_values(Some("bearer"), Some("token")).unwrap()),
+            Some(String::from("Bearer token"))
+        );
+        assert_eq!(
+            authorization(RemoteAuth::from_values(Some("basic"), Some("user:pw")).unwrap()),
+            Some(String::from("Basic dXNlcjpwdw=="))
+        );
+        assert!(RemoteAuth::from_values(Some("basic"), None).is_err());
+        assert!(RemoteAuth::from_values(Some("digest"), Some("user:pw")).is_err());
+    }
+
+    fn tls_file(name: &str) -> String {
+        std::env::var(crate::config::MANIFEST_DIR).unwrap_or_default() + "/test-data/tls/" + name
+    }
+
+    /// Answers every request over TLS with an empty list and returns the url
+    /// and the number of connections and of requests it read.
+    fn tls_stand_in() -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        use std::io::{Read, Write};
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (connected, read) = (connections.clone(), requests.clone());
+        let identity = native_tls::Identity::from_pkcs8(
+            &std::fs::read(tls_file("server.pem")).unwrap(),
+            &std::fs::read(tls_file("server.key")).unwrap(),
+        )
+        .unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                connected.fetch_add(1, Ordering::SeqCst);
+                let mut stream = match acceptor.accept(stream) {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
                     }
                 }
+                read.fetch_add(1, Ordering::SeqCst);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]",
+                );
             }
-            Err(e) => {
-                error!("{:?}", e);
-                Ok(vec![])
-            }
+        });
+        (
+            format!("https://127.0.0.1:{}/bucket", port),
+            connections,
+            requests,
+        )
+    }
+
+    fn tls_config(pinned_certs: Vec<String>) -> ClientConfig {
+        ClientConfig {
+            self_cert: Some(tls_file("server.der")),
+            pinned_certs,
+            ..config(0)
         }
     }
-}
 
-impl RemoteIndex for EmojiCategories {}
-impl RemoteIndex for Criterion {}
-impl RemoteIndex for Criteria {}
-impl RemoteIndex for Candidate {}
-impl RemoteIndex for CastBallots {}
-impl RemoteIndex for Voting {}
-impl RemoteIndex for Votings {}
+    #[rocket::async_test]
+    async fn pins_storage_certificate() {
+        let (url, connections, requests) = tls_stand_in();
+        let fingerprint = hex::encode(Sha256::digest(
+            std::fs::read(tls_file("server.der")).unwrap(),
+        ));
+        let storage = RemoteStorage::with_config(
+            &url,
+            tls_config(vec![fingerprint.clone()]),
+            RemoteAuth::None,
+        )
+        .unwrap();
+        storage.verify().await.unwrap();
+        assert!(storage.list(Kind::Voting).await.unwrap().is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let other = "0".repeat(64);
+        let storage = RemoteStorage::with_config(
+            &url,
+            ClientConfig {
+                retries: 2,
+                ..tls_config(vec![other.clone()])
+            },
+            RemoteAuth::None,
+        )
+        .unwrap();
+        assert!(matches!(
+            storage.verify().await,
+            Err(VoteErrorKind::Internal(e)) if e.contains("isn't pinned")
+        ));
+        assert!(storage
+            .put(Kind::Voting, "voting", String::from("[]"), PutMode::Create)
+            .await
+            .is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        // Neither attempt is retried.
+        assert_eq!(connections.load(Ordering::SeqCst), 4);
+        assert!(RemoteStorage::with_config(
+            "http://127.0.0.1/",
+            tls_config(vec![other]),
+            RemoteAuth::None
+        )
+        .is_err());
+
+        let storage = RemoteStorage::with_config(
+            &url,
+            ClientConfig {
+                self_cert: None,
+                identity: Some((tls_file("client.pem"), tls_file("client.key"))),
+                ..tls_config(vec![fingerprint])
+            },
+            RemoteAuth::None,
+        )
+        .unwrap();
+        assert!(storage.list(Kind::Voting).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn loads_client_identity() {
+        let with_identity = |cert: &str, key: &str| ClientConfig {
+            identity: Some((tls_file(cert), tls_file(key))),
+            ..tls_config(vec![])
+        };
+        let pinned = |cert: &str, key: &str| ClientConfig {
+            pinned_certs: vec!["0".repeat(64)],
+            ..with_identity(cert, key)
+        };
+        assert!(RemoteStorage::with_config(
+            "https://127.0.0.1/",
+            pinned("client.pem", "client.key"),
+            RemoteAuth::None
+        )
+        .is_ok());
+        assert!(RemoteStorage::with_config(
+            "https://127.0.0.1/",
+            pinned("client.pem", "client.pem"),
+            RemoteAuth::None
+        )
+        .is_err());
+        assert!(RemoteStorage::with_config(
+            "https://127.0.0.1/",
+            with_identity("client.pem", "client.key"),
+            RemoteAuth::None
+        )
+        .is_ok());
+        assert!(RemoteStorage::with_config(
+            "https://127.0.0.1/",
+            with_identity("client.pem", "client.pem"),
+            RemoteAuth::None
+        )
+        .is_err());
+        assert!(RemoteStorage::with_config(
+            "https://127.0.0.1/",
+            with_identity("client.pem", "missing.key"),
+            RemoteAuth::None
+        )
+        .is_err());
+    }
+}
@@ -2,5 +2,8 @@ pub const API_BALLOTS: &str = "/api/v1/ballots";
 pub const API_VOTINGS: &str = "/api/v1/votings";
 pub const API_USERS: &str = "/api/v1/users";
 pub const API_CRITERIA: &str = "/api/v1/criteria";
+pub const API_OPERATORS: &str = "/api/v1/operators";
+pub const API_STORAGE: &str = "/api/v1/storage";
+pub const API_SESSION: &str = "/api/v1/session";
 #[cfg(feature = "templates")]
 pub const API_ADMIN: &str = "/admin";
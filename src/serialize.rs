@@ -1,24 +1,19 @@
-#[cfg(feature = "file")]
-use crate::{common::IdGenerator, local::ToFile};
 use crate::{
+    cache::cache,
     common::{
-        Candidate, CastBallots, Criteria, Criterion, EmojiCategories, Empty, QueryableExt, Voting,
-        Votings,
+        Candidate, CastBallots, Criteria, Criterion, EmojiCategories, Empty, IdGenerator, Operator,
+        Selfaware, Voting, Votings,
     },
     error::VoteErrorKind,
-    persistence::{FromPersistence, Path, ToPersistence},
+    persistence::{storage, Kind, Path, PutMode, ToPersistence},
 };
 use rocket::{
-    error, info,
+    error,
     serde::{json::Json, Deserialize, Serialize},
 };
-use std::collections::BTreeMap;
 
-#[cfg(feature = "sqlx_sqlite")]
-use crate::db::common::Query;
-#[cfg(feature = "file")]
 #[rocket::async_trait]
-pub trait FromStorage: FromPersistence + Empty {
+pub trait FromStorage: Path + Empty {
     async fn load(&self, id: &str, internal: bool) -> Json<Self>
     where
         Self: Sized + for<'de> Deserialize<'de>,
@@ -26,50 +21,40 @@ pub trait FromStorage: FromPersistence + Empty {
         Json(self.load_into(id, internal).await)
     }
 
-    async fn load_into(&self, id: &str, internal: bool) -> Self
+    /// Like `load_into`, but an unreachable storage or a broken entry is
+    /// returned instead of an empty entry.
+    async fn try_load(&self, id: &str) -> Result<Self, VoteErrorKind<'static>>
     where
         Self: Sized + for<'de> Deserialize<'de>,
     {
-        let path = Path::get_full_path(self, true) + "/" + id + ".json";
-        let raw_file = self.from_persistence(&path, internal).await;
-        match rocket::serde::json::from_str::<Self>(&raw_file) {
-            Ok(json) => json,
-            Err(e) => {
-                error!("{:?}", e);
-                Self::empty()
-            }
+        if let Some(cached) = Self::from_cache(id) {
+            return Ok(cached);
         }
+        let generation = Self::cache_generation(id);
+        let raw = storage()?.get(Self::KIND, id).await?;
+        let loaded = rocket::serde::json::from_str::<Self>(&raw)?;
+        loaded.to_cache(id, generation);
+        Ok(loaded)
     }
-}
-#[cfg(feature = "sqlx_sqlite")]
-#[rocket::async_trait]
-pub trait FromStorage:
-    crate::common::Table + FromPersistence + Empty + Query + std::str::FromStr + std::fmt::Debug
-{
-    async fn load(&self, id: &str, internal: bool) -> Json<Self>
+    /// The parsed entry of the `EntityCache`, only cached types override it.
+    fn from_cache(_id: &str) -> Option<Self>
     where
-        Self: Sized + for<'de> Deserialize<'de>,
+        Self: Sized,
     {
-        Json(self.load_into(id, internal).await)
+        None
+    }
+    /// Read before the entry is loaded, see `EntityCache::generation`.
+    fn cache_generation(_id: &str) -> u64 {
+        0
     }
+    fn to_cache(&self, _id: &str, _generation: u64) {}
 
-    async fn load_into(&self, id: &str, _internal: bool) -> Self {
-        match crate::db::sqlx_sqlite::select(
-            &Self::get_table(false),
-            &Self::get_db_columns(),
-            &Self::get_identity_column_name(),
-            id,
-        )
-        .await
-        {
-            Ok(k) => {
-                let mut r = String::new();
-                k.iter().for_each(|e| r.push_str(e));
-                match Self::from_str(&r) {
-                    Ok(ok) => ok,
-                    Err(_er) => Self::empty(),
-                }
-            }
+    async fn load_into(&self, id: &str, _internal: bool) -> Self
+    where
+        Self: Sized + for<'de> Deserialize<'de>,
+    {
+        match self.try_load(id).await {
+            Ok(json) => json,
             Err(e) => {
                 error!("{:?}", e);
                 Self::empty()
@@ -77,55 +62,58 @@ pub trait FromStorage:
         }
     }
 }
-#[cfg(feature = "file")]
+
 #[rocket::async_trait]
-pub trait ToStorage: ToPersistence + Serialize + IdGenerator + ToFile {
-    async fn save(&self) -> Result<String, VoteErrorKind> {
-        match rocket::serde::json::to_string(&self) {
-            Ok(stringified) => {
-                let path = Path::get_full_path(self, true) + "/" + &self.get_id() + ".json";
-                self.save_to_file(path, stringified).await
-            }
-            Err(e) => {
-                error!("{:?}", e);
-                Err(VoteErrorKind::Serialize(e))
-            }
-        }
+pub trait ToStorage: ToPersistence + Serialize + IdGenerator + Selfaware {
+    async fn save(&self) -> Result<String, VoteErrorKind<'static>> {
+        self.put(PutMode::Create).await
     }
-    async fn update(&self) -> Result<String, VoteErrorKind> {
-        match rocket::serde::json::to_string(&self) {
-            Ok(stringified) => {
-                let path = Path::get_full_path(self, true) + "/" + &self.get_id() + ".json";
-                self.create(path, stringified).await
-            }
+    async fn update(&self) -> Result<String, VoteErrorKind<'static>> {
+        self.put(PutMode::Replace).await
+    }
+    async fn put(&self, mode: PutMode) -> Result<String, VoteErrorKind<'static>> {
+        let stringified = match rocket::serde::json::to_string(&self) {
+            Ok(stringified) => stringified,
             Err(e) => {
                 error!("{:?}", e);
-                Err(VoteErrorKind::Serialize(e))
+                return Err(VoteErrorKind::Serialize(e));
             }
-        }
-    }
-}
-#[cfg(feature = "db")]
-#[rocket::async_trait]
-pub trait ToStorage: ToPersistence + Serialize {
-    async fn save(&self) -> Result<String, VoteErrorKind> {
-        <Self as Query>::save(&self).await
-    }
-    async fn update(&self, new_pairs: BTreeMap<&str, &String>) -> Result<String, VoteErrorKind> {
-        info!("Update with these values: {:?}", new_pairs);
-        if new_pairs.len() == 0 {
-            self.create().await
-        } else {
-            <Self as Query>::update(&self, new_pairs).await
+        };
+        match storage()?
+            .put(Self::KIND, &self.get_id(), stringified, mode)
+            .await
+        {
+            Err(VoteErrorKind::Conflict(_)) if mode == PutMode::Create => {
+                Err(VoteErrorKind::Conflict(self.get_type() + " already exist."))
+            }
+            stored => stored,
         }
     }
 }
 
 impl ToPersistence for Votings {}
-impl FromPersistence for Votings {}
 impl FromStorage for Votings {}
 
-impl FromStorage for Voting {}
+/// Votings, candidates and criteria are loaded several times per request and
+/// kept in the `EntityCache`.
+macro_rules! cached_from_storage {
+    ($($entity:ty),*) => {
+        $(
+            impl FromStorage for $entity {
+                fn from_cache(id: &str) -> Option<Self> {
+                    cache().get(Self::KIND, id)
+                }
+                fn cache_generation(id: &str) -> u64 {
+                    cache().generation(Self::KIND, id)
+                }
+                fn to_cache(&self, id: &str, generation: u64) {
+                    cache().insert(Self::KIND, id, generation, self.clone())
+                }
+            }
+        )*
+    };
+}
+cached_from_storage!(Voting, Candidate, Criterion);
 impl ToStorage for Voting {}
 
 impl FromStorage for CastBallots {}
@@ -133,34 +121,46 @@ impl ToStorage for CastBallots {}
 impl ToPersistence for CastBallots {}
 
 impl ToStorage for Candidate {}
-impl FromStorage for Candidate {}
+
+impl ToStorage for Operator {}
+impl FromStorage for Operator {}
+impl ToPersistence for Operator {}
 
 impl FromStorage for Criteria {}
-impl FromPersistence for Criteria {}
 impl ToPersistence for Criteria {}
 
 impl ToStorage for EmojiCategories {}
 impl FromStorage for EmojiCategories {}
 impl ToPersistence for EmojiCategories {}
-impl FromPersistence for EmojiCategories {}
 
 impl ToPersistence for Criterion {}
-impl FromPersistence for Criterion {}
 impl ToStorage for Criterion {}
-impl FromStorage for Criterion {}
 
 impl ToPersistence for Candidate {}
-impl FromPersistence for Candidate {}
-
-impl FromPersistence for CastBallots {}
 
 impl ToPersistence for Voting {}
-impl FromPersistence for Voting {}
 
-impl Path for Votings {}
-impl Path for Voting {}
-impl Path for CastBallots {}
-impl Path for Candidate {}
-impl Path for Criteria {}
-impl Path for Criterion {}
-impl Path for EmojiCategories {}
+impl Path for Votings {
+    const KIND: Kind = Kind::Voting;
+}
+impl Path for Voting {
+    const KIND: Kind = Kind::Voting;
+}
+impl Path for CastBallots {
+    const KIND: Kind = Kind::Ballot;
+}
+impl Path for Candidate {
+    const KIND: Kind = Kind::Candidate;
+}
+impl Path for Operator {
+    const KIND: Kind = Kind::Operator;
+}
+impl Path for Criteria {
+    const KIND: Kind = Kind::Criterion;
+}
+impl Path for Criterion {
+    const KIND: Kind = Kind::Criterion;
+}
+impl Path for EmojiCategories {
+    const KIND: Kind = Kind::Emojis;
+}
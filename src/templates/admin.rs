@@ -1,9 +1,11 @@
 use crate::{
-    authentication::{ElevatedUser, UserRole},
+    authentication::{can, Authorized},
     common::{Empty, Fill, Voting, VotingStyles, Votings},
     criteria::get_criterias,
+    csrf::CsrfToken,
+    operators::list_operators,
     persistence::ToPersistence,
-    routes::{API_CRITERIA, API_USERS, API_VOTINGS},
+    routes::{API_CRITERIA, API_OPERATORS, API_SESSION, API_USERS, API_VOTINGS},
     templates::common::render_template,
     users::{get_users_by_type, User},
 };
@@ -12,55 +14,50 @@ use rocket::get;
 
 #[get("/")]
 pub async fn render_admin_panel<'r>(
-    _elevated_user: ElevatedUser,
+    csrf: CsrfToken,
+    _authorized: Authorized<can::ManageCriteria>,
 ) -> rocket_dyn_templates::Template {
     let html_file = "admin";
     match get_criterias().await {
-        Ok(c) => build_admin_panel(c.into_inner(), html_file).await,
-        Err(_) => build_admin_panel(vec![], html_file).await,
+        Ok(c) => build_admin_panel(c.into_inner(), html_file, csrf).await,
+        Err(_) => build_admin_panel(vec![], html_file, csrf).await,
     }
 }
 #[get("/votings")]
 pub async fn render_votings_admin_panel<'r>(
-    _elevated_user: ElevatedUser,
+    csrf: CsrfToken,
+    _authorized: Authorized<can::CreateVoting>,
 ) -> rocket_dyn_templates::Template {
     let html_file = "admin-voting";
     match get_criterias().await {
-        Ok(c) => build_admin_panel(c.into_inner(), html_file).await,
-        Err(_) => build_admin_panel(vec![], html_file).await,
+        Ok(c) => build_admin_panel(c.into_inner(), html_file, csrf).await,
+        Err(_) => build_admin_panel(vec![], html_file, csrf).await,
     }
 }
 #[get("/")]
-pub async fn render_dev_admin_panel() -> rocket_dyn_templates::Template {
+pub async fn render_dev_admin_panel(csrf: CsrfToken) -> rocket_dyn_templates::Template {
     let html_file = "admin";
     match get_criterias().await {
-        Ok(c) => build_admin_panel(c.into_inner(), html_file).await,
-        Err(_) => build_admin_panel(vec![], html_file).await,
+        Ok(c) => build_admin_panel(c.into_inner(), html_file, csrf).await,
+        Err(_) => build_admin_panel(vec![], html_file, csrf).await,
     }
 }
 #[get("/votings/<voting>")]
 pub async fn render_voting_admin_panel(
-    elevated_user: ElevatedUser,
+    csrf: CsrfToken,
+    _authorized: Authorized<can::EditVoting>,
     voting: &str,
 ) -> rocket_dyn_templates::Template {
-    if elevated_user.role == UserRole::Admin {
-        match get_criterias().await {
-            Ok(c) => build_voting_admin_panel(c.into_inner(), voting).await,
-            Err(_) => build_voting_admin_panel(vec![], voting).await,
-        }
-    } else {
-        render_template(
-            "error",
-            rocket_dyn_templates::context! {
-                reason: String::from("Unauthorized")
-            },
-        )
+    match get_criterias().await {
+        Ok(c) => build_voting_admin_panel(c.into_inner(), voting, csrf).await,
+        Err(_) => build_voting_admin_panel(vec![], voting, csrf).await,
     }
 }
 
 async fn build_voting_admin_panel(
     criterias: Vec<String>,
     voting: &str,
+    csrf: CsrfToken,
 ) -> rocket_dyn_templates::Template {
     let voting = Voting::fill(voting, false, "voting").await;
     let candidates = get_users_by_type(User::Candidate).await.into_inner();
@@ -71,27 +68,32 @@ async fn build_voting_admin_panel(
             criterias,
             voting,
             votings_route: API_VOTINGS,
+            csrf_token: csrf.0,
         },
     )
 }
 #[get("/votings")]
-pub async fn render_votings_dev_admin_panel() -> rocket_dyn_templates::Template {
+pub async fn render_votings_dev_admin_panel(csrf: CsrfToken) -> rocket_dyn_templates::Template {
     let html_file = "admin-voting";
     match get_criterias().await {
-        Ok(c) => build_admin_panel(c.into_inner(), html_file).await,
-        Err(_) => build_admin_panel(vec![], html_file).await,
+        Ok(c) => build_admin_panel(c.into_inner(), html_file, csrf).await,
+        Err(_) => build_admin_panel(vec![], html_file, csrf).await,
     }
 }
 #[get("/votings/<voting>")]
-pub async fn render_voting_dev_admin_panel(voting: &str) -> rocket_dyn_templates::Template {
+pub async fn render_voting_dev_admin_panel(
+    csrf: CsrfToken,
+    voting: &str,
+) -> rocket_dyn_templates::Template {
     match get_criterias().await {
-        Ok(c) => build_voting_admin_panel(c.into_inner(), voting).await,
-        Err(_) => build_voting_admin_panel(vec![], voting).await,
+        Ok(c) => build_voting_admin_panel(c.into_inner(), voting, csrf).await,
+        Err(_) => build_voting_admin_panel(vec![], voting, csrf).await,
     }
 }
 async fn build_admin_panel(
     criterias: Vec<String>,
     html_file: &'static str,
+    csrf: CsrfToken,
 ) -> rocket_dyn_templates::Template {
     let candidates = get_users_by_type(User::Candidate).await.into_inner();
     render_template(
@@ -102,34 +104,59 @@ async fn build_admin_panel(
             users_route: API_USERS,
             votings_route: API_VOTINGS,
             criteria_route: API_CRITERIA,
-            default_styles: VotingStyles::default()
+            default_styles: VotingStyles::default(),
+            csrf_token: csrf.0,
         },
     )
 }
 
 #[get("/manage")]
 pub async fn render_admin_manage_panel<'r>(
-    elevated_user: ElevatedUser,
+    csrf: CsrfToken,
+    authorized: Authorized<can::ViewInviteCodes>,
 ) -> rocket_dyn_templates::Template {
-    if elevated_user.role == UserRole::Admin {
-        let voting_index = Votings::empty().index().await.unwrap();
-        let votings: Vec<_> = voting_index
-            .iter()
-            .map(|b| Voting::fill(b, true, "voting"))
-            .collect();
-        let collected_votings: Vec<Voting> = futures::future::join_all(votings).await;
-        render_template(
-            "manage-votings",
-            rocket_dyn_templates::context! {
-                votings: collected_votings
-            },
-        )
-    } else {
-        render_template(
-            "error",
-            rocket_dyn_templates::context! {
-                reason: String::from("Unauthorized")
-            },
-        )
-    }
+    let voting_index = Votings::empty().index().await.unwrap();
+    let votings: Vec<_> = voting_index
+        .iter()
+        .map(|b| Voting::fill(b, true, "voting"))
+        .collect();
+    let collected_votings: Vec<Voting> = futures::future::join_all(votings)
+        .await
+        .into_iter()
+        .filter(|v| authorized.user.owns(v))
+        .collect();
+    render_template(
+        "manage-votings",
+        rocket_dyn_templates::context! {
+            votings: collected_votings,
+            csrf_token: csrf.0,
+        },
+    )
+}
+
+#[get("/operators")]
+pub async fn render_operators_admin_panel(
+    csrf: CsrfToken,
+    authorized: Authorized<can::ManageOperators>,
+) -> rocket_dyn_templates::Template {
+    render_template(
+        "admin-operators",
+        rocket_dyn_templates::context! {
+            operators: list_operators().await,
+            operators_route: API_OPERATORS,
+            current_operator: authorized.user.name,
+            csrf_token: csrf.0,
+        },
+    )
+}
+
+#[get("/login")]
+pub async fn render_login_admin_panel(csrf: CsrfToken) -> rocket_dyn_templates::Template {
+    render_template(
+        "admin-login",
+        rocket_dyn_templates::context! {
+            session_route: API_SESSION,
+            csrf_token: csrf.0,
+        },
+    )
 }
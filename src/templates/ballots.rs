@@ -1,10 +1,11 @@
 use crate::{
     ballots::{ballots_by_candidate, ballots_by_voted_on, ballots_by_voter, ballots_sorted},
     common::{Fill, Voting},
+    persistence::Storage,
     templates::common::render_template,
 };
 
-use rocket::get;
+use rocket::{get, State};
 
 /////////////////////////////////////////////
 //                                         //
@@ -13,9 +14,12 @@ use rocket::get;
 ////////////////////////////////////////////
 
 #[get("/<voting_id>")]
-pub async fn render_ballots_by_voted_on(voting_id: &str) -> rocket_dyn_templates::Template {
+pub async fn render_ballots_by_voted_on(
+    storage: &State<Storage>,
+    voting_id: &str,
+) -> rocket_dyn_templates::Template {
     let voting: Voting = Voting::fill(voting_id, false, "voting").await;
-    let table = ballots_by_voted_on(voting_id).await;
+    let table = ballots_by_voted_on(storage, voting_id).await;
     render_template(
         "cast-ballots",
         rocket_dyn_templates::context! {
@@ -29,20 +33,21 @@ pub async fn render_ballots_sorted(voting_id: &str, sort: &str) -> rocket_dyn_te
     let voting: Voting = Voting::fill(voting_id, false, "voting").await;
     let table = ballots_sorted(voting_id, sort).await;
     render_template(
-        "cast-ballots",
+        "results",
         rocket_dyn_templates::context! {
             voting,
-            ballots: table.rows
+            candidates: table.rows
         },
     )
 }
 #[get("/<voting_id>/voters/<voter>")]
 pub async fn render_ballots_by_voter(
+    storage: &State<Storage>,
     voting_id: &str,
     voter: &str,
 ) -> rocket_dyn_templates::Template {
     let voting: Voting = Voting::fill(voting_id, false, "voting").await;
-    let table = ballots_by_voter(voting_id, voter).await;
+    let table = ballots_by_voter(storage, voting_id, voter).await;
     if table.rows.len() != 0 {
         render_template(
             "cast-user-ballots",
@@ -59,11 +64,12 @@ pub async fn render_ballots_by_voter(
 }
 #[get("/<voting_id>/candidates/<candidate>")]
 pub async fn render_ballots_by_candidate(
+    storage: &State<Storage>,
     voting_id: &str,
     candidate: &str,
 ) -> rocket_dyn_templates::Template {
     let voting: Voting = Voting::fill(voting_id, false, "voting").await;
-    let table = ballots_by_candidate(voting_id, candidate).await;
+    let table = ballots_by_candidate(storage, voting_id, candidate).await;
     if table.rows.len() != 0 {
         render_template(
             "cast-user-ballots",
@@ -1,7 +1,9 @@
 use crate::{
     common::{get_users_internal, Empty, Fill, Voting, Votings},
+    csrf::{is_same_token, CsrfToken},
     error::VoteErrorKind,
     persistence::ToPersistence,
+    rate_limit::{is_throttled, record_failure, record_success, Scope},
     templates::common::render_template,
 };
 use chrono::Utc;
@@ -46,13 +48,20 @@ impl<'r> FromRequest<'r> for VotingGuard {
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let default_voting = Voting::empty();
+        let voting_id = req.uri().path().segments().get(1).unwrap_or_default();
+        if is_throttled(req, Scope::Voting(voting_id)) {
+            return Outcome::Error((
+                Status::TooManyRequests,
+                VoteErrorKind::Unauthorized(Unauthorized(String::from("Try again later."))),
+            ));
+        }
         let voting: Voting = match req.uri().path().segments().get(1) {
             Some(v) => Voting::fill(v, false, "voting").await,
             None => default_voting,
         };
         debug!("FromReuest VotingGuard: {:?}", voting);
         fn is_correct_invite_code(key: &str, voting: &Voting) -> bool {
-            key == voting.invite_code
+            !voting.invite_code.is_empty() && is_same_token(key, &voting.invite_code)
         }
         let unautorized_error =
             VoteErrorKind::Unauthorized(Unauthorized(String::from("Supply an invite code.")));
@@ -60,8 +69,10 @@ impl<'r> FromRequest<'r> for VotingGuard {
             Some(key) => match key {
                 Ok(k) => {
                     if is_correct_invite_code(k, &voting) {
+                        record_success(req, Scope::Voting(voting_id));
                         Outcome::Success(VotingGuard { voting: voting })
                     } else {
+                        record_failure(req, Scope::Voting(voting_id));
                         Outcome::Error((Status::BadRequest, unautorized_error))
                     }
                 }
@@ -74,6 +85,7 @@ impl<'r> FromRequest<'r> for VotingGuard {
 #[get("/<voting_id>")]
 pub async fn render_voting<'r>(
     guard: VotingGuard,
+    csrf: CsrfToken,
     voting_id: &str,
 ) -> rocket_dyn_templates::Template {
     let voting = guard.voting;
@@ -88,7 +100,8 @@ pub async fn render_voting<'r>(
                     voting,
                     expires_at,
                     candidates,
-                    voters: users.voters
+                    voters: users.voters,
+                    csrf_token: csrf.0,
                 },
             )
         } else {
@@ -1,31 +1,60 @@
 use rocket::{
-    debug, get,
+    debug, error, get,
     http::Status,
     info, post, put,
-    response::status::{Conflict, Created},
+    response::status::Created,
     serde::{json::Json, Deserialize, Serialize},
 };
-use std::collections::BTreeMap;
 
 use crate::{
-    authentication::ElevatedUser,
-    common::{Candidate, CreateVoting, Criterion, Fill, Voting, VotingStyles},
+    authentication::{can, Authorized},
+    common::{
+        generate_invite_code, is_valid_invite_code, Candidate, CreateVoting, Criterion, Empty,
+        Fill, Voting, VotingStyles,
+    },
+    csrf::CsrfVerified,
     routes::API_VOTINGS,
-    serialize::ToStorage,
+    serialize::{FromStorage, ToStorage},
 };
 
-use regex::Regex;
+/// The stored voting, the storage error is passed on instead of an empty one.
+async fn try_voting(voting: &str) -> Result<Voting, Status> {
+    Voting::empty()
+        .try_load(&voting.to_lowercase())
+        .await
+        .map_err(|e| {
+            error!("{:?}", e);
+            Status::from(&e)
+        })
+}
+/// The stored voting with its invite code, for operators who own it.
+async fn owned_voting(
+    authorized: &Authorized<can::ViewInviteCodes>,
+    voting: &str,
+) -> Result<Voting, Status> {
+    let voting = try_voting(voting).await?;
+    match authorized.user.owns(&voting) {
+        true => Ok(voting),
+        false => Err(Status::Forbidden),
+    }
+}
 
 #[get("/raw/<voting>")]
-pub async fn get_raw_vote(voting: &str) -> Result<Json<Voting>, Conflict<String>> {
-    Ok(Voting::fill_json(voting, false, "voting").await)
+pub async fn get_raw_vote(
+    authorized: Authorized<can::ViewInviteCodes>,
+    voting: &str,
+) -> Result<Json<Voting>, Status> {
+    Ok(Json(owned_voting(&authorized, voting).await?))
 }
 #[get("/raw/<voting>?full")]
-pub async fn get_full_vote(voting: &str) -> Result<Json<Voting>, Conflict<String>> {
-    let voting = Voting::fill(voting, false, "voting").await;
+pub async fn get_full_vote(
+    authorized: Authorized<can::ViewInviteCodes>,
+    voting: &str,
+) -> Result<Json<Voting>, Status> {
+    let voting = owned_voting(&authorized, voting).await?;
     let requested_voting = CreateVoting {
         name: voting.name,
-        expires_at: voting.expires_at.unwrap(),
+        expires_at: voting.expires_at.ok_or(Status::InternalServerError)?,
         candidates: vec![],
         criterias: vec![],
         styles: None,
@@ -53,6 +82,8 @@ pub async fn query_full_voting(voting: CreateVoting) -> Voting {
         categories: vec![],
         styles,
         invite_code: voting.invite_code,
+        owner: None,
+        invite_code_rotations: vec![],
     };
     let requested_criterias: Vec<_> = voting
         .criterias
@@ -78,15 +109,27 @@ pub async fn query_full_voting(voting: CreateVoting) -> Voting {
 }
 
 #[post("/", format = "application/json", data = "<voting>")]
-pub async fn post_vote(voting: Json<CreateVoting>) -> Result<Created<&'static str>, Status> {
-    let re = Regex::new(r"[a-zA-Z]{1}[a-zA-Z0-9]{4}").unwrap();
-    let extracted_voting = voting.into_inner();
-    if re.is_match(&extracted_voting.invite_code) {
-        let vote_to_be_created = query_full_voting(extracted_voting).await;
+pub async fn post_vote(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::CreateVoting>,
+    voting: Json<CreateVoting>,
+) -> Result<Created<&'static str>, Status> {
+    let mut extracted_voting = voting.into_inner();
+    if extracted_voting.invite_code.is_empty() {
+        extracted_voting.invite_code = generate_invite_code();
+    }
+    if is_valid_invite_code(&extracted_voting.invite_code) {
+        let mut vote_to_be_created = query_full_voting(extracted_voting).await;
+        vote_to_be_created.owner = Some(authorized.user.name.clone());
         match vote_to_be_created.save().await {
-            Ok(_done) => Ok(Created::new(
-                API_VOTINGS.to_owned() + "/" + &vote_to_be_created.name.clone(),
-            )),
+            Ok(_done) => {
+                authorized
+                    .user
+                    .audit("created voting", &vote_to_be_created.name);
+                Ok(Created::new(
+                    API_VOTINGS.to_owned() + "/" + &vote_to_be_created.name.clone(),
+                ))
+            }
             Err(e) => {
                 info!("{:?}", e);
                 Err(Status::Conflict)
@@ -98,8 +141,12 @@ pub async fn post_vote(voting: Json<CreateVoting>) -> Result<Created<&'static st
 }
 
 #[put("/<voting>/close")]
-#[cfg(not(feature = "sqlx_sqlite"))]
-pub async fn close_vote<'r>(_elevated_user: ElevatedUser, voting: &str) -> Result<String, Status> {
+pub async fn close_vote(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::CloseVoting>,
+    voting: &str,
+) -> Result<String, Status> {
+    authorized.user.audit("closed voting", voting);
     let mut voting = Voting::fill(voting, true, "voting").await;
     voting.expires_at = Some(chrono::Utc::now());
     match voting.update().await {
@@ -108,15 +155,19 @@ pub async fn close_vote<'r>(_elevated_user: ElevatedUser, voting: &str) -> Resul
     }
 }
 
-#[put("/<voting>/close")]
-#[cfg(feature = "sqlx_sqlite")]
-pub async fn close_vote<'r>(_elevated_user: ElevatedUser, voting: &str) -> Result<String, Status> {
-    let voting = Voting::fill(voting, true, "voting").await;
-    let my_new_closing_vote = chrono::Utc::now().to_string();
-    let mut tree = BTreeMap::new();
-    tree.insert("expires_at", &my_new_closing_vote);
-    match voting.update(tree).await {
-        Ok(done) => Ok(done),
+#[put("/<voting>/invite_code")]
+pub async fn rotate_invite_code(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::EditVoting>,
+    voting: &str,
+) -> Result<String, Status> {
+    let mut voting = Voting::fill(&voting.to_lowercase(), true, "voting").await;
+    let invite_code = voting.rotate_invite_code(&authorized.user.name);
+    authorized
+        .user
+        .audit("rotated invite code of", &voting.name);
+    match voting.update().await {
+        Ok(_done) => Ok(invite_code),
         Err(_e) => Err(Status::Conflict),
     }
 }
@@ -128,11 +179,13 @@ pub struct PossibleVotingModification {
     categories: Option<Vec<String>>,
 }
 #[put("/<voting>/add", format = "application/json", data = "<modifications>")]
-pub async fn modify_voting<'r>(
-    _elevated_user: ElevatedUser,
+pub async fn modify_voting(
+    _csrf: CsrfVerified,
+    authorized: Authorized<can::EditVoting>,
     voting: &str,
     modifications: Json<PossibleVotingModification>,
 ) -> Result<String, Status> {
+    authorized.user.audit("modified voting", voting);
     let unpacked_payload = modifications.into_inner();
     let mut voting = Voting::fill(&voting.to_lowercase(), true, "voting").await;
     let a = match unpacked_payload.candidates {
@@ -195,47 +248,19 @@ pub async fn modify_voting<'r>(
     };
     if a.is_ok() && b.is_ok() {
         info!("{:?}", voting);
-        #[cfg(not(feature = "sqlx_sqlite"))]
         match voting.update().await {
             Ok(done) => Ok(done),
             Err(_e) => Err(Status::Conflict),
         }
-        #[cfg(feature = "sqlx_sqlite")]
-        update_sqlite(voting).await
     } else {
         Err(Status::Conflict)
     }
 }
 
-#[cfg(feature = "sqlx_sqlite")]
-async fn update_sqlite(voting: Voting) -> Result<String, Status> {
-    let mut tree = BTreeMap::new();
-    let serialize_candidates = match rocket::serde::json::to_string(&voting.candidates) {
-        Ok(s) => Ok(s),
-        Err(_) => Err(Status::NotFound),
-    };
-
-    let serialize_categories = match rocket::serde::json::to_string(&voting.categories) {
-        Ok(s) => Ok(s),
-        Err(_) => Err(Status::NotFound),
-    };
-    if serialize_categories.is_ok() && serialize_candidates.is_ok() {
-        let unwrapped_candidate = serialize_candidates.unwrap();
-        let unwrapped_categories = serialize_categories.unwrap();
-        tree.insert("candidates", &unwrapped_candidate);
-        tree.insert("categories", &unwrapped_categories);
-        match voting.update(tree).await {
-            Ok(done) => Ok(done),
-            Err(_e) => Err(Status::Conflict),
-        }
-    } else {
-        Err(Status::NotFound)
-    }
-}
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::config::ADMIN_TOKEN;
+    use crate::{authentication::ElevatedUser, persistence::skips_without_storage};
     #[rocket::async_test]
     async fn query_full_voting() {
         let create_voting = CreateVoting {
@@ -269,29 +294,137 @@ mod test {
         assert_eq!(response.styles.background, "1");
     }
 
+    #[rocket::async_test]
+    async fn post_and_close_vote() {
+        if skips_without_storage() {
+            return;
+        }
+        let create_voting = CreateVoting {
+            name: "Closing".to_string(),
+            expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+            candidates: vec![],
+            criterias: vec![],
+            styles: None,
+            invite_code: String::new(),
+        };
+        let maintainer = Authorized::new(ElevatedUser::new_maintainer());
+        let response = super::post_vote(CsrfVerified::new(), maintainer, Json(create_voting)).await;
+        assert!(response.is_ok());
+        let created = Voting::fill("closing", true, "voting").await;
+        assert_eq!(created.owner, Some(String::from("maintainer")));
+        assert_eq!(created.invite_code.len(), 16);
+
+        let admin = Authorized::new(ElevatedUser::new_admin());
+        let response = super::close_vote(CsrfVerified::new(), admin, "closing").await;
+        assert!(response.is_ok());
+        let closed = Voting::fill("closing", true, "voting").await;
+        assert!(closed.expires_at.unwrap() <= chrono::Utc::now());
+        assert_eq!(closed.invite_code, created.invite_code);
+    }
+
     #[rocket::async_test]
     async fn close_vote() {
-        let elevated_user = ElevatedUser::new_maintainer();
-        let response = super::close_vote(elevated_user, "voting").await;
-        assert_eq!(response, Err(Status { code: 401 }));
+        if skips_without_storage() {
+            return;
+        }
+        let elevated_user = Authorized::new(ElevatedUser::new_maintainer());
+        let response = super::close_vote(CsrfVerified::new(), elevated_user, "voting").await;
+        assert!(response.is_ok());
     }
 
+    #[cfg(not(feature = "db"))]
     #[rocket::async_test]
     async fn close_vote_conflict() {
-        let elevated_user = ElevatedUser::new_maintainer();
-        let response = super::close_vote(elevated_user, "votings").await;
+        let elevated_user = Authorized::new(ElevatedUser::new_maintainer());
+        let response = super::close_vote(CsrfVerified::new(), elevated_user, "votings").await;
         assert_eq!(response, Ok(String::from("Done")));
     }
+    // The database has no row to update, unlike a file, which is written.
+    #[cfg(feature = "db")]
+    #[rocket::async_test]
+    async fn close_vote_conflict() {
+        let elevated_user = Authorized::new(ElevatedUser::new_maintainer());
+        let response = super::close_vote(CsrfVerified::new(), elevated_user, "votings").await;
+        assert_eq!(response, Err(Status::Conflict));
+    }
+    /// Closes the voting through the guards, with the `Authorization` header.
+    async fn close_vote_with(authorization: Option<&str>) -> Status {
+        use crate::csrf::{CSRF_COOKIE, CSRF_HEADER};
+        use rocket::{
+            http::{Cookie, Header},
+            local::asynchronous::Client,
+        };
+        let rocket = rocket::build().mount("/", rocket::routes![super::close_vote]);
+        let client = Client::untracked(rocket).await.unwrap();
+        let mut request = client
+            .put("/voting/close")
+            .header(Header::new(CSRF_HEADER, "same"))
+            .cookie(Cookie::new(CSRF_COOKIE, "same"));
+        if let Some(authorization) = authorization {
+            request = request.header(Header::new("Authorization", authorization.to_string()));
+        }
+        let status = request.dispatch().await.status();
+        status
+    }
     #[rocket::async_test]
     async fn close_vote_unauthores_no_token() {
-        let elevated_user = ElevatedUser::new_maintainer();
-        let response = super::close_vote(elevated_user, "voting").await;
-        assert_eq!(response, Err(Status::Unauthorized));
+        assert_eq!(close_vote_with(None).await, Status::Unauthorized);
     }
     #[rocket::async_test]
     async fn close_vote_unauthores_wrong_token() {
-        let elevated_user = ElevatedUser::new_maintainer();
-        let response = super::close_vote(elevated_user, "voting").await;
-        assert_eq!(response, Err(Status::Unauthorized));
+        assert_eq!(
+            close_vote_with(Some("Bearer wrong")).await,
+            Status::Unauthorized
+        );
+    }
+
+    #[test]
+    fn maintainer_owns_only_own_votings() {
+        let mut voting = crate::common::Voting::from("voting");
+        let maintainer = ElevatedUser::new("alice", crate::authentication::UserRole::Maintainer);
+        assert!(!maintainer.owns(&voting));
+        voting.owner = Some(String::from("alice"));
+        assert!(maintainer.owns(&voting));
+        voting.owner = Some(String::from("bob"));
+        assert!(!maintainer.owns(&voting));
+        assert!(ElevatedUser::new_admin().owns(&voting));
+    }
+
+    #[rocket::async_test]
+    async fn post_vote_refuses_weak_invite_codes() {
+        for invite_code in ["T1234", "xT1234567890123456!", "1T234567890123456"] {
+            let create_voting = CreateVoting {
+                name: "Weak".to_string(),
+                expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+                candidates: vec![],
+                criterias: vec![],
+                styles: None,
+                invite_code: invite_code.to_string(),
+            };
+            let admin = Authorized::new(ElevatedUser::new_admin());
+            let response = super::post_vote(CsrfVerified::new(), admin, Json(create_voting)).await;
+            assert_eq!(response.err(), Some(Status::UnprocessableEntity));
+        }
+    }
+
+    #[test]
+    fn generated_invite_codes_are_strong() {
+        let code = generate_invite_code();
+        assert_eq!(code.len(), 16);
+        assert!(code.chars().next().unwrap().is_ascii_alphabetic());
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert!(is_valid_invite_code(&code));
+        assert_ne!(code, generate_invite_code());
+    }
+
+    #[test]
+    fn rotating_invalidates_old_invite_code() {
+        let mut voting = crate::common::Voting::from("voting");
+        let old_code = voting.invite_code.clone();
+        let new_code = voting.rotate_invite_code("alice");
+        assert_ne!(old_code, new_code);
+        assert_eq!(voting.invite_code, new_code);
+        assert_eq!(voting.invite_code_rotations.len(), 1);
+        assert_eq!(voting.invite_code_rotations[0].rotated_by, "alice");
     }
 }
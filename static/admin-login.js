@@ -0,0 +1,29 @@
+function authorization() {
+  const operator = document.getElementById("login-operator").value.trim();
+  const password = document.getElementById("login-password").value;
+  if (!operator) {
+    return "Bearer " + password;
+  }
+  const encoded = new TextEncoder().encode(operator + ":" + password);
+  return "Basic " + btoa(String.fromCharCode(...encoded));
+}
+window.addEventListener("load", () => {
+  const form = document.getElementById("login-form");
+  form.addEventListener("submit", (event) => {
+    event.preventDefault();
+    const meta = document.querySelector('meta[name="csrf-token"]');
+    fetch(form.getAttribute("data-route") + "/", {
+      method: "POST",
+      headers: {
+        Authorization: authorization(),
+        "X-CSRF-Token": meta ? meta.content : "",
+      },
+    }).then((response) => {
+      if (response.ok) {
+        window.location.assign(window.location.origin + "/admin");
+      } else {
+        window.alert("Login failed: " + response.status);
+      }
+    });
+  });
+});
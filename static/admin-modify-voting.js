@@ -11,6 +11,7 @@ function put(formId, route) {
     method: "PUT",
     body,
     headers: {
+      ...this.votersVerdict.csrfHeaders(),
       "Content-Type": "application/json",
       "X-Concafectl": "admin",
       "x-user": "admin",
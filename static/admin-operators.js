@@ -0,0 +1,50 @@
+function post(route) {
+  const body = {
+    name: this.votersVerdict.getValueByElementId("operator-name"),
+    password: document.getElementById("operator-password").value,
+    role: this.votersVerdict.getValueByElementId("operator-role"),
+  };
+  return this.votersVerdict.ajax(
+    this.rxjs.ajax.ajax({
+      url: route + window.location.search,
+      method: "POST",
+      body,
+      headers: {
+        ...this.votersVerdict.csrfHeaders(),
+        "Content-Type": "application/json",
+      },
+    }),
+    () => window.location.reload(),
+  );
+}
+function put(name, route) {
+  const password = document.getElementById(name + "-password").value;
+  const body = {
+    role: this.votersVerdict.getValueByElementId(name + "-role"),
+    password: password ? password : null,
+  };
+  return this.votersVerdict.ajax(
+    this.rxjs.ajax.ajax({
+      url: route + window.location.search,
+      method: "PUT",
+      body,
+      headers: {
+        ...this.votersVerdict.csrfHeaders(),
+        "Content-Type": "application/json",
+      },
+    }),
+    () => window.alert("Operator " + name + " saved."),
+  );
+}
+window.addEventListener("load", () => {
+  const form = document.getElementById("operator-form");
+  this.votersVerdict.fromEvent(form, "submit", () =>
+    post(form.getAttribute("data-route")),
+  );
+  const buttons = Array.from(document.querySelectorAll(".operator-modify"));
+  for (const button of buttons) {
+    this.votersVerdict.fromEvent(button, "click", () =>
+      put(button.id, button.getAttribute("data-route")),
+    );
+  }
+});
@@ -35,6 +35,13 @@ function post(formId, route) {
     },
   };
   actOnSuccess = () => {
+    if (!body.invite_code) {
+      // The generated invite code is only shown on the manage page.
+      window.location.assign(
+        window.location.origin + "/admin/manage" + window.location.search,
+      );
+      return;
+    }
     window.location.assign(
       window.location.origin +
         "/votings/" +
@@ -45,9 +52,10 @@ function post(formId, route) {
   };
   const ajax = this.rxjs.ajax.ajax({
     method: "POST",
-    url: route,
+    url: route + window.location.search,
     body,
     headers: {
+      ...this.votersVerdict.csrfHeaders(),
       "Content-Type": "application/json",
       "X-Concafectl": "admin",
       "x-user": "admin",
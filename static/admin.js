@@ -31,10 +31,11 @@ function post(formId, route) {
   }
   return this.votersVerdict.ajax(
     this.rxjs.ajax.ajax({
-      url: route,
+      url: route + window.location.search,
       method: "POST",
       body,
       headers: {
+        ...this.votersVerdict.csrfHeaders(),
         "Content-Type": "application/json",
         "X-Concafectl": "admin",
         "x-user": "admin",
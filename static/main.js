@@ -39,6 +39,7 @@ function postVote() {
       method: "POST",
       body,
       headers: {
+        ...this.votersVerdict.csrfHeaders(),
         "Content-Type": "application/json",
         "X-concafe": document.querySelector("h1").innerText,
         "x-concafe-user": user,
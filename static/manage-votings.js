@@ -1,25 +1,34 @@
+function votingRequest(voting, action) {
+  return this.rxjs.ajax.ajax({
+    url: ["/api", "v1", "votings", voting, action, window.location.search].join(
+      "/",
+    ),
+    method: "PUT",
+    headers: this.votersVerdict.csrfHeaders(),
+  });
+}
 function get_votings() {
-  const buttons = Array.from(document.querySelectorAll("button"));
+  const buttons = Array.from(document.querySelectorAll("button.close"));
   for (const button of buttons) {
-    const form = document.getElementById(button.id);
-    const ajaxRequest = this.rxjs.ajax.ajax({
-      url: [
-        "/api",
-        "v1",
-        "votings",
-        button.id,
-        "close",
-        window.location.search,
-      ].join("/"),
-      method: "PUT",
-    });
+    const ajaxRequest = votingRequest(button.dataset.voting, "close");
     const handleClick = () =>
       this.votersVerdict.ajax(ajaxRequest, () =>
         window.alert("Voting is now closed."),
       );
-    this.votersVerdict.fromEvent(form, "click", handleClick);
+    this.votersVerdict.fromEvent(button, "click", handleClick);
+  }
+}
+function rotate_invite_codes() {
+  const buttons = Array.from(document.querySelectorAll("button.rotate"));
+  for (const button of buttons) {
+    const voting = button.dataset.voting;
+    const ajaxRequest = votingRequest(voting, "invite_code");
+    const handleClick = () =>
+      this.votersVerdict.ajax(ajaxRequest, () => window.location.reload());
+    this.votersVerdict.fromEvent(button, "click", handleClick);
   }
 }
 window.addEventListener("load", () => {
   get_votings();
+  rotate_invite_codes();
 });
@@ -27,6 +27,10 @@
     }
     return checkedValues;
   }
+  function csrfHeaders() {
+    const meta = document.querySelector('meta[name="csrf-token"]');
+    return { "X-CSRF-Token": meta ? meta.content : "" };
+  }
   function ajax(ajaxRequest, mapFn) {
     return ajaxRequest.pipe(
       globalThis.rxjs.tap(() => tap(mapFn)),
@@ -73,6 +77,7 @@
   }
   return {
     ajax,
+    csrfHeaders,
     fromEvent: fromEventWrapper,
     fromEventIntoTap,
     getValuesByFieldSetId,
@@ -0,0 +1,40 @@
+-- The fixtures of test-data/ for the database backends, on top of the latest migration.
+INSERT INTO votings ( name, expires_at, created_at, candidates, categories, styles, invite_code, owner, invite_code_rotations ) VALUES (
+  'Voting',
+  '2023-12-22T16:31:19.745Z',
+  '2023-11-24T16:31:19.751587849Z',
+  '[{"voter":false,"id":"candidate_doe","label":"Doe"},{"voter":false,"id":"candidate_joe","label":"Joe"}]',
+  '[{"name":"Style","min":0,"max":10,"weight":15.0},{"name":"Weather","min":0,"max":15,"weight":15.0}]',
+  '{"background":"#33d17a","font":"#613583","selection":"#deddda","fields":"#77767b"}',
+  'W1234',
+  '',
+  '[]'
+);
+INSERT INTO voting_candidates ( voting, candidate, position ) VALUES
+  ('Voting', 'candidate_doe', 0),
+  ('Voting', 'candidate_joe', 1);
+INSERT INTO voting_criteria ( voting, criterion, weight, position ) VALUES
+  ('Voting', 'Style', 15.0, 0),
+  ('Voting', 'Weather', 15.0, 1);
+
+INSERT INTO candidates ( voter, id, label ) VALUES
+  (FALSE, 'candidate_joe', 'Joe'),
+  (FALSE, 'candidate_doe', 'Doe'),
+  (TRUE, 'voter_obama', 'Obama'),
+  (TRUE, 'voter_michelle', 'Michelle');
+
+INSERT INTO criteria ( name, min, max, weight ) VALUES
+  ('Style', 0, 10, 15.0),
+  ('Weather', 0, 15, 15.0);
+
+INSERT INTO ballots ( human_identifier, voter, candidate, sum, weighted, mean, notes, votes, voted_on, voting ) VALUES
+  ('voting-obama-doe', 'Obama', 'Doe', 15, 22.5, 7.5, 'Nope', '[{"name":"Style","point":5},{"name":"Weather","point":10}]', '2023-11-24T16:31:28.352746225Z', 'voting'),
+  ('voting-obama-joe', 'Obama', 'Joe', 12, 18.0, 6.0, 'Jippy', '[{"name":"Style","point":2},{"name":"Weather","point":10}]', '2023-11-24T16:31:38.115288845Z', 'voting'),
+  ('voting-michelle-joe', 'Michelle', 'Joe', 14, 21.0, 7.0, 'Super style', '[{"name":"Style","point":7},{"name":"Weather","point":7}]', '2023-11-24T16:31:52.200140786Z', 'voting');
+INSERT INTO votes ( ballot, criterion, point ) VALUES
+  ('voting-obama-doe', 'Style', 5),
+  ('voting-obama-doe', 'Weather', 10),
+  ('voting-obama-joe', 'Style', 2),
+  ('voting-obama-joe', 'Weather', 10),
+  ('voting-michelle-joe', 'Style', 7),
+  ('voting-michelle-joe', 'Weather', 7);